@@ -0,0 +1,217 @@
+#![feature(test)]
+
+extern crate test;
+
+use ipiis_api::{client::IpiisClient, common::Ipiis};
+use ipis::{
+    async_trait::async_trait,
+    core::{anyhow::Result, signed::IsSigned},
+    env::Infer,
+    rkyv::AlignedVec,
+    tokio,
+};
+use ipwis_api::resource::DummyResourceManager;
+use ipwis_common::kernel::{
+    batch::SyscallBatch,
+    interrupt::{
+        DynInterruptHandler, InterruptHandler, InterruptId, InterruptModule, InterruptTask,
+    },
+    memory::Memory,
+    task::{TaskCtx, TaskPoll},
+};
+use ipwis_kernel::{
+    config::KernelConfig, interrupt::InterruptManager, kernel::Kernel, memory::IpwisMemoryFamily,
+};
+use ipwis_testkit::guest::{build_guest_with_heap, escape};
+use test::Bencher;
+
+const NUM_CALLS: usize = 1_000;
+
+const ECHO: InterruptId = InterruptId("ipwis_bench_echo");
+const PAYLOAD: &[u8] = b"0123456789abcdef";
+
+#[bench]
+fn bench_syscall_individual(b: &mut Bencher) {
+    bench(b, Mode::Individual)
+}
+
+#[bench]
+fn bench_syscall_batch(b: &mut Bencher) {
+    bench(b, Mode::Batch)
+}
+
+// note: both modes pay the same cost to spawn a task, so the difference is the saving
+fn bench(b: &mut Bencher, mode: Mode) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let (client, kernel) = runtime.block_on(async {
+        // create an IPIIS account
+        let client = IpiisClient::infer().await;
+
+        // boot a kernel with an echo module
+        let mut interrupt_manager = InterruptManager::default();
+        interrupt_manager.insert(EchoModule).unwrap();
+        let kernel = Kernel::<DummyResourceManager>::boot_with_interrupts(
+            KernelConfig::default(),
+            interrupt_manager,
+        )
+        .await
+        .unwrap();
+
+        (client, kernel)
+    });
+
+    // prepare a program
+    let program = mode.build_program();
+
+    b.iter(|| {
+        runtime.block_on(async {
+            // create a task and sign
+            let ctx = TaskCtx::new_sandbox();
+            let ctx = client.sign(client.account_me().account_ref(), ctx).unwrap();
+            let ctx = client.sign_as_guarantor(ctx).unwrap();
+
+            let id = kernel.spawn(ctx, &program).await.unwrap();
+            let record = kernel.wait(id).await.unwrap();
+            assert!(matches!(&record.poll, TaskPoll::Ready(_)));
+
+            kernel.acknowledge(id).await.unwrap();
+        })
+    });
+}
+
+struct EchoModule;
+
+#[async_trait]
+impl InterruptModule<IpwisMemoryFamily> for EchoModule {
+    fn id(&self) -> InterruptId {
+        ECHO
+    }
+
+    async fn spawn_handler(
+        &self,
+        _task: &InterruptTask,
+    ) -> Result<Box<dyn DynInterruptHandler<IpwisMemoryFamily>>> {
+        Ok(Box::new(EchoHandler))
+    }
+}
+
+struct EchoHandler;
+
+#[async_trait]
+impl<M> InterruptHandler<M> for EchoHandler
+where
+    M: Memory,
+{
+    async unsafe fn handle_raw(&mut self, _memory: &mut M, inputs: &[u8]) -> Result<AlignedVec> {
+        let mut outputs = AlignedVec::with_capacity(inputs.len());
+        outputs.extend_from_slice(inputs);
+        Ok(outputs)
+    }
+
+    async fn release(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Mode {
+    Individual,
+    Batch,
+}
+
+impl Mode {
+    // the guest memory layout
+    const PTR_HANDLER: u32 = 256;
+    const PTR_INPUTS: u32 = 264;
+    const PTR_OUTPUTS: u32 = 272;
+    const PTR_ERRORS: u32 = 280;
+    const PTR_BATCH: u32 = 288;
+    const PTR_HANDLER_DATA: u32 = 1_024;
+    const PTR_INPUTS_DATA: u32 = 2_048;
+    const PTR_BATCH_DATA: u32 = 8_192;
+    const PTR_HEAP: u32 = 1_048_576;
+
+    fn build_program(self) -> Vec<u8> {
+        let batch = {
+            let mut batch = SyscallBatch::default();
+            for _ in 0..NUM_CALLS {
+                batch.push_raw(ECHO, PAYLOAD.to_vec());
+            }
+            batch.to_bytes().unwrap()
+        };
+
+        let descriptors: Vec<u8> = [
+            (Self::PTR_HANDLER_DATA, ECHO.0.len()),
+            (Self::PTR_INPUTS_DATA, PAYLOAD.len()),
+            (0, 0), // outputs
+            (0, 0), // errors
+            (Self::PTR_BATCH_DATA, batch.len()),
+        ]
+        .into_iter()
+        .flat_map(|(ptr, len)| {
+            // note: wasm uses little-endian
+            let mut buf = ptr.to_le_bytes().to_vec();
+            buf.extend((len as u32).to_le_bytes());
+            buf
+        })
+        .collect();
+
+        let body = match self {
+            Self::Individual => format!(
+                r#"
+                (loop $loop
+                    (drop (call $syscall
+                        (i32.const {handler}) (i32.const {inputs})
+                        (i32.const {outputs}) (i32.const {errors})))
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br_if $loop (i32.lt_u (local.get $i) (i32.const {NUM_CALLS}))))
+                "#,
+                handler = Self::PTR_HANDLER,
+                inputs = Self::PTR_INPUTS,
+                outputs = Self::PTR_OUTPUTS,
+                errors = Self::PTR_ERRORS,
+            ),
+            Self::Batch => format!(
+                r#"
+                (drop (call $syscall_batch
+                    (i32.const {batch}) (i32.const {outputs}) (i32.const {errors})))
+                "#,
+                batch = Self::PTR_BATCH,
+                outputs = Self::PTR_OUTPUTS,
+                errors = Self::PTR_ERRORS,
+            ),
+        };
+
+        let imports = r#"
+            (import "__ipwis_kernel" "__ipwis_syscall"
+                (func $syscall (param i32 i32 i32 i32) (result i32)))
+            (import "__ipwis_kernel" "__ipwis_syscall_batch"
+                (func $syscall_batch (param i32 i32 i32) (result i32)))
+        "#;
+
+        let body = format!(
+            r#"
+            (data (i32.const {ptr_descriptors}) "{descriptors}")
+            (data (i32.const {ptr_handler}) "{handler}")
+            (data (i32.const {ptr_inputs}) "{inputs}")
+            (data (i32.const {ptr_batch}) "{batch}")
+
+            (func (export "__ipwis_syscall") (param i32 i32 i32 i32) (result i32)
+                (local $i i32)
+                {body}
+                (i32.const 0))
+            "#,
+            ptr_descriptors = Self::PTR_HANDLER,
+            descriptors = escape(&descriptors),
+            ptr_handler = Self::PTR_HANDLER_DATA,
+            handler = escape(ECHO.0.as_bytes()),
+            ptr_inputs = Self::PTR_INPUTS_DATA,
+            inputs = escape(PAYLOAD),
+            ptr_batch = Self::PTR_BATCH_DATA,
+            batch = escape(&batch),
+        );
+
+        build_guest_with_heap(imports, &body, Self::PTR_HEAP).unwrap()
+    }
+}
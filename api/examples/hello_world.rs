@@ -20,7 +20,7 @@ async fn main() -> Result<()> {
     let ctx = client.sign(client.account_me().account_ref(), ctx)?;
     let ctx = client.sign_as_guarantor(ctx)?;
 
-    let id = kernel.spawn(ctx, my_program).await?.unwrap();
+    let id = kernel.spawn(ctx, my_program).await?;
     let ctx = kernel.wait(id).await?;
 
     Ok(())
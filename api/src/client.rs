@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use ipiis_api::common::Ipiis;
 use ipis::{
     async_trait::async_trait,
@@ -12,15 +14,22 @@ use ipis::{
 use ipsis_common::Ipsis;
 use ipwis_common::Ipwis;
 use ipwis_kernel::{
-    common::task::{TaskCtx, TaskId, TaskPoll},
+    common::{
+        task::{TaskCtx, TaskId, TaskPoll, TaskReport},
+        usage::UsageSummary,
+    },
     kernel::Kernel,
 };
+use ipwis_modules_ipiis_common::IpwisSyscallReceiver;
+
+use crate::{quota::QuotaResourceManager, resource::SystemResourceManager};
 
 pub type IpwisClient = IpwisClientInner<::ipiis_api::client::IpiisClient>;
 
 pub struct IpwisClientInner<IpiisClient> {
     pub ipiis: IpiisClient,
-    kernel: Kernel<super::resource::DummyResourceManager>,
+    kernel: Kernel<QuotaResourceManager<SystemResourceManager>>,
+    syscall_receiver: Option<Arc<dyn IpwisSyscallReceiver + Send + Sync>>,
 }
 
 impl<IpiisClient> AsRef<::ipiis_api::client::IpiisClient> for IpwisClientInner<IpiisClient>
@@ -70,9 +79,23 @@ impl<IpiisClient> IpwisClientInner<IpiisClient> {
     pub async fn with_ipiis_client(ipiis: IpiisClient) -> Result<Self> {
         Ok(Self {
             ipiis,
-            kernel: Kernel::boot().await?,
+            kernel: Kernel::boot_with_config(crate::config::infer()?).await?,
+            syscall_receiver: None,
         })
     }
+
+    /// Sets the receiver of the syscalls, which are forwarded from the remote kernels.
+    pub fn with_syscall_receiver(
+        mut self,
+        receiver: Arc<dyn IpwisSyscallReceiver + Send + Sync>,
+    ) -> Self {
+        self.syscall_receiver = Some(receiver);
+        self
+    }
+
+    pub fn syscall_receiver(&self) -> Option<&(dyn IpwisSyscallReceiver + Send + Sync)> {
+        self.syscall_receiver.as_deref()
+    }
 }
 
 #[async_trait]
@@ -90,10 +113,8 @@ where
         match &ctx.program {
             Some(program) => {
                 let program: Vec<u8> = self.ipiis.get(program).await?;
-                match self.kernel.spawn(ctx, &program).await? {
-                    Some(id) => self.ipiis.sign(guarantee, id).map(Some),
-                    None => Ok(None),
-                }
+                let id = self.kernel.spawn(ctx, &program).await?;
+                self.ipiis.sign(guarantee, id).map(Some)
             }
             None => bail!("Empty program"),
         }
@@ -110,13 +131,41 @@ where
         self.task_spawn(ctx).await
     }
 
-    async fn task_poll(&self, id: GuarantorSigned<TaskId>) -> Result<GuaranteeSigned<TaskPoll>> {
-        let poll = match self.kernel.poll(id.data.data.data).await {
-            Ok(Some(ctx)) => TaskPoll::Ready(todo!()),
-            Ok(None) => TaskPoll::Pending,
-            Err(err) => TaskPoll::Trap(Text::with_en_us(err.to_string())),
+    async fn task_poll(&self, id: GuarantorSigned<TaskId>) -> Result<GuaranteeSigned<TaskReport>> {
+        let task_id = id.data.data.data;
+        let report = match self.kernel.poll(task_id).await {
+            Ok(Some(record)) => TaskReport {
+                poll: record.poll.clone(),
+                leaked_bytes: Some(record.leaked_bytes),
+            },
+            Ok(None) => TaskReport {
+                poll: match self.kernel.queue_state(task_id) {
+                    Some(state) => TaskPoll::Queued(state),
+                    None => TaskPoll::Pending,
+                },
+                leaked_bytes: None,
+            },
+            Err(err) => TaskReport {
+                poll: TaskPoll::Trap(Text::with_en_us(err.to_string())),
+                leaked_bytes: None,
+            },
         };
 
-        self.ipiis.sign(id.guarantor.account, poll)
+        self.ipiis.sign(id.guarantor.account, report)
+    }
+
+    async fn task_acknowledge(
+        &self,
+        id: GuarantorSigned<TaskId>,
+    ) -> Result<GuaranteeSigned<TaskId>> {
+        let task_id = id.data.data.data;
+        self.kernel.acknowledge(task_id).await?;
+
+        self.ipiis.sign(id.guarantor.account, task_id)
+    }
+
+    async fn usage_summary(&self, account: AccountRef) -> Result<GuaranteeSigned<UsageSummary>> {
+        let summary = self.kernel.usage_summary(&account);
+        self.ipiis.sign(account, summary)
     }
 }
@@ -0,0 +1,82 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use ipis::core::anyhow::{bail, Result};
+use ipwis_kernel::config::{FairShareConfig, KernelConfig};
+use serde::Deserialize;
+
+/// The environment variable, which has the path of the runtime config file.
+pub const ENV_PATH: &str = "IPWIS_RUNTIME_CONFIG";
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct RuntimeConfigFile {
+    fair_share: FairShareConfigFile,
+    ledger_path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct FairShareConfigFile {
+    slots: usize,
+    default_weight: u32,
+    weights: HashMap<String, u32>,
+}
+
+impl Default for FairShareConfigFile {
+    fn default() -> Self {
+        let config = FairShareConfig::default();
+        Self {
+            slots: config.slots,
+            default_weight: config.default_weight,
+            weights: Default::default(),
+        }
+    }
+}
+
+/// Loads the kernel config from the runtime config file, e.g.
+///
+/// ```toml
+/// ledger_path = "/var/lib/ipwis/usage.ledger"
+///
+/// [fair_share]
+/// slots = 64
+///
+/// [fair_share.weights]
+/// "<account>" = 4
+/// ```
+pub fn load(path: impl AsRef<Path>) -> Result<KernelConfig> {
+    from_toml(&::std::fs::read_to_string(path)?)
+}
+
+pub fn from_toml(s: &str) -> Result<KernelConfig> {
+    let file: RuntimeConfigFile = ::toml::from_str(s)?;
+    if file.fair_share.slots == 0 {
+        bail!("the number of the slots should be positive");
+    }
+
+    Ok(KernelConfig {
+        fair_share: FairShareConfig {
+            slots: file.fair_share.slots,
+            default_weight: file.fair_share.default_weight,
+            weights: file
+                .fair_share
+                .weights
+                .into_iter()
+                .map(|(account, weight)| Ok((account.parse()?, weight)))
+                .collect::<Result<_>>()?,
+        },
+        ledger_path: file.ledger_path,
+        ..Default::default()
+    })
+}
+
+/// Loads the kernel config from the file in `IPWIS_RUNTIME_CONFIG`, or the default one.
+pub fn infer() -> Result<KernelConfig> {
+    match ::std::env::var(ENV_PATH) {
+        Ok(path) => load(path),
+        Err(_) => Ok(Default::default()),
+    }
+}
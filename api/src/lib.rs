@@ -1,5 +1,7 @@
 pub extern crate ipwis_common as common;
 
 pub mod client;
+pub mod config;
+pub mod quota;
 pub mod resource;
 pub mod server;
@@ -0,0 +1,492 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use ipis::{
+    async_trait::async_trait,
+    core::{account::AccountRef, anyhow::Result},
+    env::Infer,
+};
+use ipwis_common::kernel::{
+    error::ExternError,
+    resource::{ResourceAlloc, ResourceId, ResourceManager},
+    task::TaskConstraints,
+};
+use serde::Deserialize;
+
+/// The limits of an account, which are checked when its tasks are spawned.
+///
+/// `None` means the resource is not limited.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct QuotaLimits {
+    pub max_concurrent_tasks: Option<u32>,
+    pub max_spawns_per_minute: Option<u32>,
+    /// The maximum sum of the fuel of the running tasks.
+    pub max_fuel: Option<u64>,
+    /// The maximum sum of the memory of the running tasks, in bytes.
+    pub max_memory_bytes: Option<u64>,
+}
+
+impl QuotaLimits {
+    /// Returns the limits, which are overridden by the given ones if specified.
+    pub fn merge(&self, overrides: &Self) -> Self {
+        Self {
+            max_concurrent_tasks: overrides.max_concurrent_tasks.or(self.max_concurrent_tasks),
+            max_spawns_per_minute: overrides
+                .max_spawns_per_minute
+                .or(self.max_spawns_per_minute),
+            max_fuel: overrides.max_fuel.or(self.max_fuel),
+            max_memory_bytes: overrides.max_memory_bytes.or(self.max_memory_bytes),
+        }
+    }
+}
+
+/// The quotas of the accounts, e.g.
+///
+/// ```toml
+/// [default]
+/// max_concurrent_tasks = 4
+/// max_spawns_per_minute = 60
+///
+/// [accounts."<account>"]
+/// max_concurrent_tasks = 64
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QuotaConfig {
+    pub default: QuotaLimits,
+    pub accounts: HashMap<AccountRef, QuotaLimits>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct QuotaConfigFile {
+    default: QuotaLimits,
+    accounts: HashMap<String, QuotaLimits>,
+}
+
+impl QuotaConfig {
+    /// The environment variable, which has the path of the config file.
+    pub const ENV_PATH: &'static str = "IPWIS_QUOTA_CONFIG";
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_toml(&::std::fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self> {
+        let file: QuotaConfigFile = ::toml::from_str(s)?;
+        Ok(Self {
+            default: file.default,
+            accounts: file
+                .accounts
+                .into_iter()
+                .map(|(account, limits)| Ok((account.parse()?, limits)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Returns the limits of the account, overridden by its own ones.
+    pub fn limits(&self, account: &AccountRef) -> QuotaLimits {
+        match self.accounts.get(account) {
+            Some(overrides) => self.default.merge(overrides),
+            None => self.default,
+        }
+    }
+}
+
+#[derive(Default)]
+struct QuotaState {
+    accounts: HashMap<AccountRef, AccountUsage>,
+    tasks: HashMap<ResourceId, (AccountRef, Usage)>,
+}
+
+#[derive(Default)]
+struct AccountUsage {
+    running: Usage,
+    spawns: VecDeque<Instant>,
+}
+
+#[derive(Copy, Clone, Default)]
+struct Usage {
+    tasks: u32,
+    fuel: u64,
+    memory_bytes: u64,
+    // note: the spawn is counted out by its own date, as the others may be counted in meanwhile
+    spawned_date: Option<Instant>,
+}
+
+/// Enforces the quotas of the guarantee accounts, before the inner manager reserves the resources.
+pub struct QuotaResourceManager<R> {
+    inner: R,
+    config: QuotaConfig,
+    state: Mutex<QuotaState>,
+}
+
+#[async_trait]
+impl<'a, R> Infer<'a> for QuotaResourceManager<R>
+where
+    R: Infer<'a, GenesisResult = R> + Send,
+    <R as Infer<'a>>::GenesisArgs: Sized,
+{
+    type GenesisArgs = <R as Infer<'a>>::GenesisArgs;
+    type GenesisResult = Self;
+
+    async fn try_infer() -> Result<Self> {
+        Ok(Self::new(R::try_infer().await?, infer_config()?))
+    }
+
+    async fn genesis(
+        args: <Self as Infer<'a>>::GenesisArgs,
+    ) -> Result<<Self as Infer<'a>>::GenesisResult> {
+        Ok(Self::new(R::genesis(args).await?, infer_config()?))
+    }
+}
+
+fn infer_config() -> Result<QuotaConfig> {
+    match ::std::env::var(QuotaConfig::ENV_PATH) {
+        Ok(path) => QuotaConfig::load(path),
+        Err(_) => Ok(Default::default()),
+    }
+}
+
+impl<R> QuotaResourceManager<R> {
+    pub fn new(inner: R, config: QuotaConfig) -> Self {
+        Self {
+            inner,
+            config,
+            state: Default::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn config(&self) -> &QuotaConfig {
+        &self.config
+    }
+
+    /// Returns the number of the running tasks of the account.
+    pub fn running_tasks(&self, account: &AccountRef) -> u32 {
+        self.state
+            .lock()
+            .unwrap()
+            .accounts
+            .get(account)
+            .map(|usage| usage.running.tasks)
+            .unwrap_or_default()
+    }
+
+    /// Checks the quotas of the account, and then counts the task in.
+    fn reserve(&self, account: &AccountRef, constraints: &TaskConstraints) -> Result<Usage> {
+        let limits = self.config.limits(account);
+        let resources = &constraints.resources;
+
+        let mut state = self.state.lock().unwrap();
+        let account_usage = state.accounts.entry(*account).or_default();
+
+        // note: the spawns are counted on a sliding window of a minute
+        let now = Instant::now();
+        while let Some(&date) = account_usage.spawns.front() {
+            if now.duration_since(date) < Duration::from_secs(60) {
+                break;
+            }
+            account_usage.spawns.pop_front();
+        }
+
+        let running = account_usage.running;
+        check(
+            account,
+            "concurrent tasks",
+            limits.max_concurrent_tasks.map(Into::into),
+            Some(running.tasks as u64 + 1),
+        )?;
+        check(
+            account,
+            "spawns per minute",
+            limits.max_spawns_per_minute.map(Into::into),
+            Some(account_usage.spawns.len() as u64 + 1),
+        )?;
+        check(
+            account,
+            "fuel",
+            limits.max_fuel,
+            resources.fuel.map(|fuel| fuel.saturating_add(running.fuel)),
+        )?;
+        check(
+            account,
+            "memory",
+            limits.max_memory_bytes,
+            resources
+                .memory_bytes
+                .map(|memory_bytes| memory_bytes.saturating_add(running.memory_bytes)),
+        )?;
+
+        let usage = Usage {
+            tasks: 1,
+            fuel: resources.fuel.unwrap_or_default(),
+            memory_bytes: resources.memory_bytes.unwrap_or_default(),
+            spawned_date: Some(now),
+        };
+        account_usage.spawns.push_back(now);
+        account_usage.running = running.add(usage);
+        Ok(usage)
+    }
+
+    fn unreserve(&self, account: &AccountRef, usage: Usage, is_spawned: bool) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(account_usage) = state.accounts.get_mut(account) {
+            account_usage.running = account_usage.running.sub(usage);
+            if let (false, Some(date)) = (is_spawned, usage.spawned_date) {
+                if let Some(index) = account_usage.spawns.iter().rposition(|&d| d == date) {
+                    account_usage.spawns.remove(index);
+                }
+            }
+        }
+    }
+}
+
+/// Fails if the requested amount is beyond the limit; `None` means the amount is unbounded.
+fn check(
+    account: &AccountRef,
+    name: &str,
+    limit: Option<u64>,
+    requested: Option<u64>,
+) -> Result<()> {
+    match (limit, requested) {
+        (Some(limit), Some(requested)) if requested > limit => {
+            Err(ExternError::limit_exceeded(format!(
+                "the account {account} has exceeded the limit of {name}: {requested} > {limit}"
+            ))
+            .into())
+        }
+        (Some(limit), None) => Err(ExternError::limit_exceeded(format!(
+            "the account {account} should declare the {name} of the task: unbounded > {limit}"
+        ))
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+#[async_trait]
+impl<R> ResourceManager for QuotaResourceManager<R>
+where
+    R: ResourceManager + Send + Sync,
+{
+    async fn alloc(
+        &self,
+        guarantee: &AccountRef,
+        constraints: &TaskConstraints,
+    ) -> Result<ResourceAlloc> {
+        // note: the task is counted in before the inner manager, so that the quotas are not raced
+        let usage = self.reserve(guarantee, constraints)?;
+
+        match self.inner.alloc(guarantee, constraints).await {
+            Ok(ResourceAlloc::Ready(id)) => {
+                let mut state = self.state.lock().unwrap();
+                state.tasks.insert(id, (*guarantee, usage));
+                Ok(ResourceAlloc::Ready(id))
+            }
+            result => {
+                self.unreserve(guarantee, usage, false);
+                result
+            }
+        }
+    }
+
+    fn release(&self, id: ResourceId) {
+        self.inner.release(id);
+
+        let task = self.state.lock().unwrap().tasks.remove(&id);
+        if let Some((account, usage)) = task {
+            self.unreserve(&account, usage, true);
+        }
+    }
+}
+
+impl Usage {
+    fn add(self, other: Self) -> Self {
+        Self {
+            tasks: self.tasks.saturating_add(other.tasks),
+            fuel: self.fuel.saturating_add(other.fuel),
+            memory_bytes: self.memory_bytes.saturating_add(other.memory_bytes),
+            spawned_date: self.spawned_date,
+        }
+    }
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            tasks: self.tasks.saturating_sub(other.tasks),
+            fuel: self.fuel.saturating_sub(other.fuel),
+            memory_bytes: self.memory_bytes.saturating_sub(other.memory_bytes),
+            spawned_date: self.spawned_date,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ipiis_api::{client::IpiisClient, common::Ipiis};
+    use ipis::{core::account::AccountRef, env::Infer, tokio};
+    use ipwis_common::kernel::{
+        error::{ErrorCode, ExternError},
+        resource::{ResourceAlloc, ResourceManager},
+        task::{TaskConstraints, TaskCtx},
+    };
+
+    use super::{QuotaConfig, QuotaResourceManager};
+    use crate::resource::DummyResourceManager;
+
+    fn constraints(fuel: Option<u64>, memory_bytes: Option<u64>) -> TaskConstraints {
+        let mut ctx = TaskCtx::new_sandbox();
+        ctx.constraints.resources.fuel = fuel;
+        ctx.constraints.resources.memory_bytes = memory_bytes;
+        ctx.constraints
+    }
+
+    async fn reject(
+        manager: &QuotaResourceManager<DummyResourceManager>,
+        guarantee: &AccountRef,
+        constraints: TaskConstraints,
+    ) -> ExternError {
+        let error = manager
+            .alloc(guarantee, &constraints)
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::LimitExceeded);
+        error
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_tasks() {
+        let client = IpiisClient::infer().await;
+        let guarantee = client.account_me().account_ref();
+
+        let config = QuotaConfig::from_toml(&format!(
+            r#"
+            [default]
+            max_concurrent_tasks = 1
+            max_fuel = 100
+
+            [accounts."{guarantee}"]
+            max_concurrent_tasks = 2
+            "#
+        ))
+        .unwrap();
+        let manager = QuotaResourceManager::new(DummyResourceManager::default(), config);
+
+        let mut ctx = TaskCtx::new_sandbox();
+        ctx.constraints.resources.fuel = Some(10);
+        let constraints = ctx.constraints;
+
+        // test the per-account overrides are applied
+        let mut ids = vec![];
+        for _ in 0..2 {
+            match manager.alloc(&guarantee, &constraints).await.unwrap() {
+                ResourceAlloc::Ready(id) => ids.push(id),
+                ResourceAlloc::Rejected(reason) => panic!("{reason}"),
+            }
+        }
+
+        // test the rejection reports which limit is hit
+        let error = manager
+            .alloc(&guarantee, &constraints)
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::LimitExceeded);
+        assert!(error.message.contains("concurrent tasks"));
+
+        // test the finished tasks are counted out
+        manager.release(ids[0]);
+        assert_eq!(manager.running_tasks(&guarantee), 1);
+        assert!(manager.alloc(&guarantee, &constraints).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_spawns_per_minute() {
+        let client = IpiisClient::infer().await;
+        let guarantee = client.account_me().account_ref();
+
+        let config = QuotaConfig::from_toml(
+            r#"
+            [default]
+            max_spawns_per_minute = 2
+            "#,
+        )
+        .unwrap();
+        let manager = QuotaResourceManager::new(DummyResourceManager::default(), config);
+        let constraints = TaskCtx::new_sandbox().constraints;
+
+        // test the finished tasks are still counted in the window
+        for _ in 0..2 {
+            match manager.alloc(&guarantee, &constraints).await.unwrap() {
+                ResourceAlloc::Ready(id) => manager.release(id),
+                ResourceAlloc::Rejected(reason) => panic!("{reason}"),
+            }
+        }
+        let error = reject(&manager, &guarantee, constraints.clone()).await;
+        assert!(error.message.contains("spawns per minute"));
+
+        // test the failed spawn counts out its own date, rather than the latest one
+        let manager =
+            QuotaResourceManager::new(DummyResourceManager::default(), Default::default());
+        let first = manager.reserve(&guarantee, &constraints).unwrap();
+        let second = manager.reserve(&guarantee, &constraints).unwrap();
+        manager.unreserve(&guarantee, first, false);
+
+        let state = manager.state.lock().unwrap();
+        assert_eq!(
+            state.accounts[&guarantee].spawns,
+            [second.spawned_date.unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fuel_and_memory() {
+        let client = IpiisClient::infer().await;
+        let guarantee = client.account_me().account_ref();
+
+        let config = QuotaConfig::from_toml(
+            r#"
+            [default]
+            max_fuel = 100
+            max_memory_bytes = 1000
+            "#,
+        )
+        .unwrap();
+        let manager = QuotaResourceManager::new(DummyResourceManager::default(), config);
+        // test the limited resources should be declared
+        let error = reject(&manager, &guarantee, constraints(None, Some(100))).await;
+        assert!(error.message.contains("should declare the fuel"));
+        let error = reject(&manager, &guarantee, constraints(Some(10), None)).await;
+        assert!(error.message.contains("should declare the memory"));
+
+        // test the resources of the running tasks are summed up
+        let id = match manager
+            .alloc(&guarantee, &constraints(Some(60), Some(600)))
+            .await
+            .unwrap()
+        {
+            ResourceAlloc::Ready(id) => id,
+            ResourceAlloc::Rejected(reason) => panic!("{reason}"),
+        };
+        let error = reject(&manager, &guarantee, constraints(Some(60), Some(100))).await;
+        assert!(error.message.contains("fuel"));
+        let error = reject(&manager, &guarantee, constraints(Some(10), Some(600))).await;
+        assert!(error.message.contains("memory"));
+
+        // test the finished tasks are counted out
+        manager.release(id);
+        assert!(manager
+            .alloc(&guarantee, &constraints(Some(60), Some(600)))
+            .await
+            .is_ok());
+    }
+}
@@ -1,6 +1,13 @@
-use ipis::{async_trait::async_trait, core::anyhow::Result, env::Infer, tokio::sync::Mutex};
+use std::{fs, sync::Mutex as SyncMutex};
+
+use ipis::{
+    async_trait::async_trait,
+    core::{account::AccountRef, anyhow::Result},
+    env::Infer,
+    tokio::sync::Mutex,
+};
 use ipwis_common::kernel::{
-    resource::{ResourceId, ResourceManager, ResourceStore},
+    resource::{ResourceAlloc, ResourceId, ResourceManager, ResourceStore},
     task::TaskConstraints,
 };
 
@@ -30,7 +37,296 @@ impl<'a> Infer<'a> for DummyResourceManager {
 
 #[async_trait]
 impl ResourceManager for DummyResourceManager {
-    async fn alloc(&self, _constraints: &TaskConstraints) -> Result<Option<ResourceId>> {
-        self.store.lock().await.insert(|_| Ok(())).map(Some)
+    async fn alloc(
+        &self,
+        _guarantee: &AccountRef,
+        _constraints: &TaskConstraints,
+    ) -> Result<ResourceAlloc> {
+        let mut store = self.store.lock().await;
+        let id = store.insert(|_| Ok(()))?;
+
+        // note: the allocations are not tracked, so the slot is reused by the next generation
+        store.remove(&id)?;
+        Ok(ResourceAlloc::Ready(id))
+    }
+}
+
+/// The capacity of the host, which is shared by the tasks.
+///
+/// `None` means the resource is not limited.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SystemCapacity {
+    pub cpu_millicores: Option<u64>,
+    pub memory_bytes: Option<u64>,
+    pub storage_bytes: Option<u64>,
+}
+
+impl SystemCapacity {
+    /// Reads the capacity of the host from `/proc` and the cgroups.
+    ///
+    /// The storage is not limited, as its capacity is not exposed by them.
+    pub fn detect() -> Self {
+        let cpu_millicores = read("/sys/fs/cgroup/cpu.max")
+            .and_then(|max| parse_cgroup_v2_cpu(&max))
+            .or_else(|| {
+                let quota = read("/sys/fs/cgroup/cpu/cpu.cfs_quota_us")?;
+                let period = read("/sys/fs/cgroup/cpu/cpu.cfs_period_us")?;
+                parse_cgroup_v1_cpu(&quota, &period)
+            })
+            .or_else(|| read("/proc/cpuinfo").and_then(|cpuinfo| parse_cpuinfo(&cpuinfo)));
+
+        let memory_total = read("/proc/meminfo").and_then(|meminfo| parse_meminfo(&meminfo));
+        let memory_limit = read("/sys/fs/cgroup/memory.max")
+            .or_else(|| read("/sys/fs/cgroup/memory/memory.limit_in_bytes"))
+            .and_then(|max| max.trim().parse().ok());
+        let memory_bytes = match (memory_total, memory_limit) {
+            (Some(total), Some(limit)) => Some(total.min(limit)),
+            (total, limit) => total.or(limit),
+        };
+
+        Self {
+            cpu_millicores,
+            memory_bytes,
+            storage_bytes: None,
+        }
+    }
+}
+
+fn read(path: &str) -> Option<String> {
+    fs::read_to_string(path).ok()
+}
+
+fn parse_cgroup_v2_cpu(max: &str) -> Option<u64> {
+    // e.g. "200000 100000", or "max 100000" if unlimited
+    let mut fields = max.split_whitespace();
+    let quota: u64 = fields.next()?.parse().ok()?;
+    let period: u64 = fields.next()?.parse().ok()?;
+    Some(quota.checked_mul(1000)? / period.max(1))
+}
+
+fn parse_cgroup_v1_cpu(quota: &str, period: &str) -> Option<u64> {
+    // note: the quota is -1 if unlimited
+    let quota: u64 = quota.trim().parse().ok()?;
+    let period: u64 = period.trim().parse().ok()?;
+    Some(quota.checked_mul(1000)? / period.max(1))
+}
+
+fn parse_cpuinfo(cpuinfo: &str) -> Option<u64> {
+    match cpuinfo
+        .lines()
+        .filter(|line| line.starts_with("processor"))
+        .count()
+    {
+        0 => None,
+        count => Some(count as u64 * 1000),
+    }
+}
+
+fn parse_meminfo(meminfo: &str) -> Option<u64> {
+    // e.g. "MemTotal:       16315032 kB"
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// The resources, which are reserved for the tasks.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Reservation {
+    pub cpu_millicores: u64,
+    pub memory_bytes: u64,
+    pub storage_bytes: u64,
+}
+
+impl Reservation {
+    fn with_constraints(constraints: &TaskConstraints) -> Self {
+        let resources = &constraints.resources;
+        Self {
+            cpu_millicores: resources.cpu_millicores.unwrap_or_default(),
+            memory_bytes: resources.memory_bytes.unwrap_or_default(),
+            storage_bytes: resources.storage_bytes.unwrap_or_default(),
+        }
+    }
+}
+
+/// Admits the tasks only if their required resources fit in the host's capacity,
+/// together with the reservations of the running tasks.
+pub struct SystemResourceManager {
+    capacity: SystemCapacity,
+    reservations: SyncMutex<ResourceStore<Reservation>>,
+}
+
+#[async_trait]
+impl<'a> Infer<'a> for SystemResourceManager {
+    type GenesisArgs = ();
+    type GenesisResult = Self;
+
+    async fn try_infer() -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self::with_capacity(SystemCapacity::detect()))
+    }
+
+    async fn genesis(
+        (): <Self as Infer<'a>>::GenesisArgs,
+    ) -> Result<<Self as Infer<'a>>::GenesisResult> {
+        <Self as Infer<'a>>::try_infer().await
+    }
+}
+
+impl SystemResourceManager {
+    pub fn with_capacity(capacity: SystemCapacity) -> Self {
+        Self {
+            capacity,
+            reservations: SyncMutex::new(ResourceStore::new("reservations")),
+        }
+    }
+
+    pub fn capacity(&self) -> &SystemCapacity {
+        &self.capacity
+    }
+
+    /// Returns the sum of the reservations of the running tasks.
+    pub fn reserved(&self) -> Reservation {
+        sum(&self.reservations.lock().unwrap())
+    }
+}
+
+fn sum(reservations: &ResourceStore<Reservation>) -> Reservation {
+    reservations
+        .values()
+        .fold(Reservation::default(), |sum, reservation| Reservation {
+            cpu_millicores: sum
+                .cpu_millicores
+                .saturating_add(reservation.cpu_millicores),
+            memory_bytes: sum.memory_bytes.saturating_add(reservation.memory_bytes),
+            storage_bytes: sum.storage_bytes.saturating_add(reservation.storage_bytes),
+        })
+}
+
+#[async_trait]
+impl ResourceManager for SystemResourceManager {
+    async fn alloc(
+        &self,
+        _guarantee: &AccountRef,
+        constraints: &TaskConstraints,
+    ) -> Result<ResourceAlloc> {
+        let requested = Reservation::with_constraints(constraints);
+
+        let mut reservations = self.reservations.lock().unwrap();
+        let reserved = sum(&reservations);
+
+        for (name, capacity, reserved, requested) in [
+            (
+                "CPU",
+                self.capacity.cpu_millicores,
+                reserved.cpu_millicores,
+                requested.cpu_millicores,
+            ),
+            (
+                "memory",
+                self.capacity.memory_bytes,
+                reserved.memory_bytes,
+                requested.memory_bytes,
+            ),
+            (
+                "storage",
+                self.capacity.storage_bytes,
+                reserved.storage_bytes,
+                requested.storage_bytes,
+            ),
+        ] {
+            let capacity = match capacity {
+                Some(capacity) => capacity,
+                None => continue,
+            };
+
+            // note: the task never fits if it requires more than the whole capacity
+            if requested > capacity {
+                return Ok(ResourceAlloc::Rejected(format!(
+                    "the task requires more {name} than the capacity: {requested} > {capacity}"
+                )));
+            }
+            let available = capacity.saturating_sub(reserved);
+            if requested > available {
+                return Ok(ResourceAlloc::Rejected(format!(
+                    "not enough {name} for now: {requested} > {available}"
+                )));
+            }
+        }
+
+        reservations
+            .insert(|_| Ok(requested))
+            .map(ResourceAlloc::Ready)
+    }
+
+    fn release(&self, id: ResourceId) {
+        // note: the reservation may be already released
+        let _ = self.reservations.lock().unwrap().remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ipiis_api::{client::IpiisClient, common::Ipiis};
+    use ipis::{env::Infer, tokio};
+    use ipwis_common::kernel::{
+        resource::{ResourceAlloc, ResourceManager},
+        task::TaskCtx,
+    };
+
+    use super::{parse_cgroup_v1_cpu, parse_cgroup_v2_cpu, SystemCapacity, SystemResourceManager};
+
+    #[tokio::test]
+    async fn test_reservation() {
+        let client = IpiisClient::infer().await;
+        let guarantee = client.account_me().account_ref();
+
+        let manager = SystemResourceManager::with_capacity(SystemCapacity {
+            cpu_millicores: Some(1000),
+            memory_bytes: Some(1024),
+            storage_bytes: None,
+        });
+
+        let constraints = |memory_bytes| {
+            let mut ctx = TaskCtx::new_sandbox();
+            ctx.constraints.resources.cpu_millicores = Some(500);
+            ctx.constraints.resources.memory_bytes = Some(memory_bytes);
+            ctx.constraints
+        };
+
+        let id = match manager.alloc(&guarantee, &constraints(768)).await.unwrap() {
+            ResourceAlloc::Ready(id) => id,
+            ResourceAlloc::Rejected(reason) => panic!("{reason}"),
+        };
+
+        // test the reservations of the running tasks are taken into account
+        let alloc = manager.alloc(&guarantee, &constraints(512)).await.unwrap();
+        assert!(matches!(alloc, ResourceAlloc::Rejected(reason) if reason.contains("memory")));
+
+        // test the task never fits beyond the capacity
+        let alloc = manager.alloc(&guarantee, &constraints(2048)).await.unwrap();
+        assert!(matches!(alloc, ResourceAlloc::Rejected(reason) if reason.contains("capacity")));
+
+        // test the reservation is released when the task is finished
+        manager.release(id);
+        assert_eq!(manager.reserved(), Default::default());
+        assert!(manager
+            .alloc(&guarantee, &constraints(512))
+            .await
+            .unwrap()
+            .ok()
+            .is_some());
+    }
+
+    #[test]
+    fn test_parse_cgroup_cpu() {
+        assert_eq!(parse_cgroup_v2_cpu("200000 100000"), Some(2000));
+        assert_eq!(parse_cgroup_v2_cpu("max 100000"), None);
+        assert_eq!(parse_cgroup_v1_cpu("50000\n", "100000\n"), Some(500));
+        assert_eq!(parse_cgroup_v1_cpu("-1", "100000"), None);
+
+        // test the overflowing quota is ignored rather than panicking
+        assert_eq!(parse_cgroup_v2_cpu(&format!("{} 100000", u64::MAX)), None);
     }
 }
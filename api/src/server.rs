@@ -5,8 +5,16 @@ use ipiis_api::{
     common::{handle_external_call, Ipiis, ServerResult},
     server::IpiisServer,
 };
-use ipis::{async_trait::async_trait, core::anyhow::Result, env::Infer};
-use ipwis_common::Ipwis;
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::AccountRef,
+        anyhow::{bail, Result},
+    },
+    env::Infer,
+};
+use ipwis_common::{kernel::error::ExternError, Ipwis};
+use ipwis_modules_ipiis_common::IpwisSyscallReceiver;
 
 use crate::client::IpwisClientInner;
 
@@ -14,6 +22,14 @@ pub struct IpwisServer {
     client: Arc<IpwisClientInner<IpiisServer>>,
 }
 
+impl From<IpwisClientInner<IpiisServer>> for IpwisServer {
+    fn from(client: IpwisClientInner<IpiisServer>) -> Self {
+        Self {
+            client: client.into(),
+        }
+    }
+}
+
 impl ::core::ops::Deref for IpwisServer {
     type Target = IpwisClientInner<IpiisServer>;
 
@@ -48,6 +64,10 @@ handle_external_call!(
     request: ::ipwis_common::io => {
         Spawn => handle_spawn,
         Poll => handle_poll,
+        Acknowledge => handle_acknowledge,
+        Usage => handle_usage,
+        // note: the syscalls are forwarded by the IPIIS modules of the remote kernels
+        Syscall => handle_syscall,
     },
 );
 
@@ -101,4 +121,237 @@ impl IpwisServer {
             poll: ::ipis::stream::DynStream::Owned(poll),
         })
     }
+
+    async fn handle_acknowledge(
+        client: &IpwisClientInner<IpiisServer>,
+        req: ::ipwis_common::io::request::Acknowledge<'static>,
+    ) -> Result<::ipwis_common::io::response::Acknowledge<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let id = req.id.into_owned().await?;
+
+        // handle data
+        let receipt = client.task_acknowledge(id).await?;
+
+        // sign data
+        let server: &IpiisServer = client.as_ref();
+        let sign = server.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipwis_common::io::response::Acknowledge {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+            receipt: ::ipis::stream::DynStream::Owned(receipt),
+        })
+    }
+
+    async fn handle_usage(
+        client: &IpwisClientInner<IpiisServer>,
+        req: ::ipwis_common::io::request::Usage<'static>,
+    ) -> Result<::ipwis_common::io::response::Usage<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let account = req.account.into_owned().await?;
+
+        let server: &IpiisServer = client.as_ref();
+        check_usage(
+            &server.account_me().account_ref(),
+            &sign_as_guarantee.guarantee.account,
+            &account,
+        )?;
+
+        // handle data
+        let summary = client.usage_summary(account).await?;
+
+        // sign data
+        let sign = server.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipwis_common::io::response::Usage {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+            summary: ::ipis::stream::DynStream::Owned(summary),
+        })
+    }
+
+    async fn handle_syscall(
+        client: &IpwisClientInner<IpiisServer>,
+        req: ::ipwis_common::io::request::Syscall<'static>,
+    ) -> Result<::ipwis_common::io::response::Syscall<'static>> {
+        let server: &IpiisServer = client.as_ref();
+        receive_syscall(server, client.syscall_receiver(), req).await
+    }
+}
+
+async fn receive_syscall<S>(
+    server: &S,
+    receiver: Option<&(dyn IpwisSyscallReceiver + Send + Sync)>,
+    req: ::ipwis_common::io::request::Syscall<'static>,
+) -> Result<::ipwis_common::io::response::Syscall<'static>>
+where
+    S: Ipiis,
+{
+    // unpack sign
+    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+    // note: the syscall should be addressed to this kernel
+    let target = sign_as_guarantee.guarantor;
+    if target != server.account_me().account_ref() {
+        bail!(ExternError::permission_denied(format!(
+            "the syscall is addressed to the other account: {target}"
+        )));
+    }
+
+    // unpack data
+    let guarantee = sign_as_guarantee.guarantee.account;
+    let request = sign_as_guarantee.data.data.clone();
+
+    // handle data
+    let outputs = match receiver {
+        Some(receiver) => receiver.receive(&guarantee, request).await?,
+        None => bail!(ExternError::unsupported(format!(
+            "the forwarded syscalls are not supported: {}",
+            request.id,
+        ))),
+    };
+
+    // sign data
+    let sign = server.sign_as_guarantor(sign_as_guarantee)?;
+
+    // pack data
+    Ok(::ipwis_common::io::response::Syscall {
+        __lifetime: Default::default(),
+        __sign: ::ipis::stream::DynStream::Owned(sign),
+        outputs: ::ipis::stream::DynStream::Owned(outputs),
+    })
+}
+
+/// Fails unless the requester queries its own usage, or is the admin, i.e. the server itself.
+fn check_usage(server: &AccountRef, requester: &AccountRef, account: &AccountRef) -> Result<()> {
+    if requester != account && requester != server {
+        bail!(ExternError::permission_denied(format!(
+            "the account {requester} is not allowed to query the usage of {account}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ipiis_api::{client::IpiisClient, common::Ipiis};
+    use ipis::{
+        async_trait::async_trait,
+        core::{account::AccountRef, anyhow::Result},
+        env::Infer,
+        stream::DynStream,
+        tokio,
+    };
+    use ipwis_common::{
+        io,
+        kernel::error::{ErrorCode, ExternError},
+        SyscallRequest,
+    };
+    use ipwis_modules_ipiis_common::IpwisSyscallReceiver;
+
+    use super::{check_usage, receive_syscall};
+
+    /// Reverses the inputs.
+    struct ReverseReceiver;
+
+    #[async_trait]
+    impl IpwisSyscallReceiver for ReverseReceiver {
+        async fn receive(
+            &self,
+            _guarantee: &AccountRef,
+            request: SyscallRequest,
+        ) -> Result<Vec<u8>> {
+            Ok(request.inputs.into_iter().rev().collect())
+        }
+    }
+
+    fn request(
+        client: &IpiisClient,
+        target: AccountRef,
+        request: SyscallRequest,
+    ) -> io::request::Syscall<'static> {
+        io::request::Syscall {
+            __lifetime: Default::default(),
+            __sign: DynStream::Owned(client.sign(target, request).unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_receive_syscall() {
+        // the server stand-in, and the remote kernel which forwards the syscalls
+        let server = IpiisClient::infer().await;
+        let client = IpiisClient::genesis(None).await.unwrap();
+        let target = server.account_me().account_ref();
+
+        let syscall = SyscallRequest {
+            id: "foo".to_string(),
+            inputs: b"bar".to_vec(),
+        };
+
+        // test the syscall is handled and signed by the server
+        let res = receive_syscall(
+            &server,
+            Some(&ReverseReceiver),
+            request(&client, target, syscall.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.outputs.into_owned().await.unwrap(), b"rab");
+
+        let sign = res.__sign.into_owned().await.unwrap();
+        assert_eq!(sign.guarantor.account, target);
+        assert_eq!(sign.data.data.data, syscall);
+
+        // test the syscalls to the other accounts are rejected
+        let other = client.account_me().account_ref();
+        let error = receive_syscall(
+            &server,
+            Some(&ReverseReceiver),
+            request(&client, other, syscall.clone()),
+        )
+        .await
+        .unwrap_err()
+        .downcast::<ExternError>()
+        .unwrap();
+        assert_eq!(error.code, ErrorCode::PermissionDenied);
+
+        // test the syscalls are rejected without a receiver
+        let error = receive_syscall(&server, None, request(&client, target, syscall))
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::Unsupported);
+    }
+
+    #[tokio::test]
+    async fn test_check_usage() {
+        let server = IpiisClient::infer().await.account_me().account_ref();
+        let client = IpiisClient::genesis(None).await.unwrap();
+        let account = client.account_me().account_ref();
+        let other = IpiisClient::genesis(None)
+            .await
+            .unwrap()
+            .account_me()
+            .account_ref();
+
+        // test the accounts may query their own usage, and the admin may query any
+        assert!(check_usage(&server, &account, &account).is_ok());
+        assert!(check_usage(&server, &server, &account).is_ok());
+
+        // test the usage of the other accounts is not open to the others
+        let error = check_usage(&server, &other, &account)
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::PermissionDenied);
+    }
 }
@@ -1,14 +1,20 @@
 pub extern crate ipwis_kernel_common as kernel;
 
+use bytecheck::CheckBytes;
 use ipiis_common::{define_io, external_call, Ipiis, ServerResult};
 use ipis::{
     async_trait::async_trait,
     core::{
         account::{AccountRef, GuaranteeSigned, GuarantorSigned},
         anyhow::Result,
+        signed::IsSigned,
     },
 };
-use ipwis_kernel_common::task::{TaskCtx, TaskId, TaskPoll};
+use ipwis_kernel_common::{
+    task::{TaskCtx, TaskId, TaskReport},
+    usage::UsageSummary,
+};
+use rkyv::{Archive, Deserialize, Serialize};
 
 #[async_trait]
 pub trait Ipwis {
@@ -23,7 +29,18 @@ pub trait Ipwis {
         ctx: TaskCtx,
     ) -> Result<Option<GuaranteeSigned<TaskId>>>;
 
-    async fn task_poll(&self, id: GuarantorSigned<TaskId>) -> Result<GuaranteeSigned<TaskPoll>>;
+    async fn task_poll(&self, id: GuarantorSigned<TaskId>) -> Result<GuaranteeSigned<TaskReport>>;
+
+    /// Drops the result of the finished task, so that it is not retained anymore.
+    async fn task_acknowledge(
+        &self,
+        id: GuarantorSigned<TaskId>,
+    ) -> Result<GuaranteeSigned<TaskId>>;
+
+    /// Returns the usage of the account's finished tasks.
+    ///
+    /// The accounts may query their own usage, while the kernel's own account may query any.
+    async fn usage_summary(&self, account: AccountRef) -> Result<GuaranteeSigned<UsageSummary>>;
 }
 
 #[async_trait]
@@ -67,7 +84,7 @@ where
         self.task_spawn(ctx).await
     }
 
-    async fn task_poll(&self, id: GuarantorSigned<TaskId>) -> Result<GuaranteeSigned<TaskPoll>> {
+    async fn task_poll(&self, id: GuarantorSigned<TaskId>) -> Result<GuaranteeSigned<TaskReport>> {
         // next target
         let target = self.get_account_primary(KIND.as_ref()).await?;
 
@@ -86,8 +103,61 @@ where
         // unpack response
         Ok(poll)
     }
+
+    async fn task_acknowledge(
+        &self,
+        id: GuarantorSigned<TaskId>,
+    ) -> Result<GuaranteeSigned<TaskId>> {
+        // next target
+        let target = self.get_account_primary(KIND.as_ref()).await?;
+
+        // external call
+        let (receipt,) = external_call!(
+            client: self,
+            target: KIND.as_ref() => &target,
+            request: crate::io => Acknowledge,
+            sign: self.sign(target, ())?,
+            inputs: {
+                id: id,
+            },
+            outputs: { receipt, },
+        );
+
+        // unpack response
+        Ok(receipt)
+    }
+
+    async fn usage_summary(&self, account: AccountRef) -> Result<GuaranteeSigned<UsageSummary>> {
+        // next target
+        let target = self.get_account_primary(KIND.as_ref()).await?;
+
+        // external call
+        let (summary,) = external_call!(
+            client: self,
+            target: KIND.as_ref() => &target,
+            request: crate::io => Usage,
+            sign: self.sign(target, ())?,
+            inputs: {
+                account: account,
+            },
+            outputs: { summary, },
+        );
+
+        // unpack response
+        Ok(summary)
+    }
 }
 
+/// A syscall, which is forwarded from the remote kernel.
+#[derive(Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+pub struct SyscallRequest {
+    pub id: String,
+    pub inputs: Vec<u8>,
+}
+
+impl IsSigned for SyscallRequest {}
+
 define_io! {
     Spawn {
         inputs: { },
@@ -104,11 +174,42 @@ define_io! {
         },
         input_sign: GuaranteeSigned<()>,
         outputs: {
-            poll: GuaranteeSigned<TaskPoll>,
+            poll: GuaranteeSigned<TaskReport>,
         },
         output_sign: GuarantorSigned<()>,
         generics: { },
     },
+    Acknowledge {
+        inputs: {
+            id: GuarantorSigned<TaskId>,
+        },
+        input_sign: GuaranteeSigned<()>,
+        outputs: {
+            receipt: GuaranteeSigned<TaskId>,
+        },
+        output_sign: GuarantorSigned<()>,
+        generics: { },
+    },
+    Usage {
+        inputs: {
+            account: AccountRef,
+        },
+        input_sign: GuaranteeSigned<()>,
+        outputs: {
+            summary: GuaranteeSigned<UsageSummary>,
+        },
+        output_sign: GuarantorSigned<()>,
+        generics: { },
+    },
+    Syscall {
+        inputs: { },
+        input_sign: GuaranteeSigned<SyscallRequest>,
+        outputs: {
+            outputs: Vec<u8>,
+        },
+        output_sign: GuarantorSigned<SyscallRequest>,
+        generics: { },
+    },
 }
 
 ::ipis::lazy_static::lazy_static! {
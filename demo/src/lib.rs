@@ -53,9 +53,8 @@ pub unsafe extern "C" fn __ipwis_syscall(
         println!("{:?}", inputs);
     }
 
-    let outputs_buf = Box::leak(inputs.to_bytes().unwrap().into_boxed_slice());
-    (*outputs).ptr = outputs_buf.as_ptr() as ExternDataRef;
-    (*outputs).len = outputs_buf.len() as ExternDataRef;
+    // note: the host takes the ownership of the outputs
+    *outputs = ExternData::from_slice_owned(&inputs.to_bytes().unwrap());
 
     0
 }
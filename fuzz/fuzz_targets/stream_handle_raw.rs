@@ -0,0 +1,125 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use ipis::{core::signed::IsSigned, rkyv::AlignedVec, tokio};
+use ipwis_kernel_api::{
+    memory::IpwisMemoryInner,
+    wasmtime::{Config, Engine, Instance, Module, Store},
+};
+use ipwis_kernel_common::{data::ExternData, interrupt::InterruptHandler, resource::ResourceId};
+use ipwis_modules_stream_api::{common::io, StreamHandler};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    stream: Vec<u8>,
+    ops: Vec<Op>,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Raw(Vec<u8>),
+    ReaderNext { id: u32, ptr: u32, len: u32 },
+    WriterNext { id: u32, ptr: u32, len: u32 },
+    WriterFlush { id: u32 },
+    WriterShutdown { id: u32 },
+}
+
+impl Op {
+    fn to_bytes(&self) -> AlignedVec {
+        let opcode = match *self {
+            Self::Raw(ref bytes) => {
+                let mut buf = AlignedVec::with_capacity(bytes.len());
+                buf.extend_from_slice(bytes);
+                return buf;
+            }
+            Self::ReaderNext { id, ptr, len } => io::OpCode::ReaderNext(io::request::ReaderNext {
+                id: ResourceId(id),
+                buf: ExternData { ptr, len },
+            }),
+            Self::WriterNext { id, ptr, len } => io::OpCode::WriterNext(io::request::WriterNext {
+                id: ResourceId(id),
+                buf: ExternData { ptr, len },
+            }),
+            Self::WriterFlush { id } => {
+                io::OpCode::WriterFlush(io::request::WriterFlush { id: ResourceId(id) })
+            }
+            Self::WriterShutdown { id } => {
+                io::OpCode::WriterShutdown(io::request::WriterShutdown { id: ResourceId(id) })
+            }
+        };
+        opcode.to_bytes().unwrap()
+    }
+
+    // note: only `ReaderNext` is allowed to write to the guest memory
+    fn writable(&self) -> Option<(usize, usize)> {
+        match *self {
+            Self::ReaderNext { ptr, len, .. } => Some((ptr as usize, ptr as usize + len as usize)),
+            _ => None,
+        }
+    }
+}
+
+thread_local! {
+    static GUEST: (Engine, Module) = {
+        let engine = Engine::new(Config::new().async_support(true)).unwrap();
+        let binary = ::ipwis_testkit::guest::build_guest("", "").unwrap();
+        let module = Module::from_binary(&engine, &binary).unwrap();
+        (engine, module)
+    };
+}
+
+fuzz_target!(|input: Input| {
+    let (engine, module) = GUEST.with(Clone::clone);
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async move {
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new_async(&mut store, &module, &[]).await.unwrap();
+        let guest_memory = instance.get_memory(&mut store, "memory").unwrap();
+
+        let mut memory = IpwisMemoryInner::with_instance(&instance, &mut store).unwrap();
+
+        // register some streams, which the opcodes may refer to
+        let mut handler = StreamHandler::default();
+        handler
+            .handle_reader_new(
+                ::std::io::Cursor::new(input.stream.clone()),
+                input.stream.len(),
+            )
+            .unwrap();
+        handler.handle_writer_new(tokio::io::sink()).unwrap();
+
+        for op in &input.ops {
+            let snapshot = guest_memory.data(&*memory.store).to_vec();
+
+            // note: the errors are expected, but the panics are not
+            let _ = unsafe {
+                InterruptHandler::<IpwisMemoryInner<_>>::handle_raw(
+                    &mut handler,
+                    &mut memory,
+                    &op.to_bytes(),
+                )
+                .await
+            };
+
+            // test the host never touches the memory out of the given buffer
+            let data = guest_memory.data(&*memory.store);
+            assert_eq!(data.len(), snapshot.len());
+            let (start, end) = op.writable().unwrap_or_default();
+            for (offset, (new, old)) in data.iter().zip(&snapshot).enumerate() {
+                if offset < start || offset >= end {
+                    assert_eq!(new, old, "out-of-bounds write at {offset:#x}");
+                }
+            }
+        }
+
+        InterruptHandler::<IpwisMemoryInner<&mut Store<()>>>::release(&mut handler)
+            .await
+            .unwrap();
+    })
+});
@@ -0,0 +1,218 @@
+#![no_main]
+
+use std::sync::{Arc, Mutex};
+
+use ipiis_api::{client::IpiisClient, common::Ipiis};
+use ipis::{
+    async_trait::async_trait, core::anyhow::Result, env::Infer, rkyv::AlignedVec,
+    tokio::runtime::Runtime,
+};
+use ipwis_api::resource::DummyResourceManager;
+use ipwis_common::kernel::{
+    interrupt::{
+        DynInterruptHandler, InterruptHandler, InterruptId, InterruptModule, InterruptTask,
+    },
+    memory::Memory,
+    task::{TaskCtx, TaskPoll},
+};
+use ipwis_kernel::{
+    config::KernelConfig, interrupt::InterruptManager, kernel::Kernel, memory::IpwisMemoryFamily,
+};
+use libfuzzer_sys::fuzz_target;
+
+const FUZZ_INPUT: InterruptId = InterruptId("ipwis_fuzz_input");
+
+// the guest memory layout
+const PTR_HANDLER: u32 = 256;
+const PTR_INPUTS: u32 = 264;
+const PTR_OUTPUTS: u32 = 272;
+const PTR_ERRORS: u32 = 280;
+const PTR_FUZZ_DESCRIPTORS: u32 = 512;
+const PTR_HANDLER_DATA: u32 = 1_024;
+const PTR_INPUTS_DATA: u32 = 2_048;
+
+// the size of the fuzz-controlled `{ptr,len}` descriptors of the syscall
+const FUZZ_DESCRIPTORS_LEN: usize = 32;
+
+struct Harness {
+    runtime: Runtime,
+    client: IpiisClient,
+    kernel: Kernel<DummyResourceManager>,
+    input: Arc<Mutex<Vec<u8>>>,
+    program: Vec<u8>,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let runtime = Runtime::new().unwrap();
+        let input = Arc::<Mutex<Vec<u8>>>::default();
+
+        let (client, kernel) = runtime.block_on(async {
+            // create an IPIIS account
+            let client = IpiisClient::infer().await;
+
+            // boot a kernel with the fuzz input module
+            let mut interrupt_manager = InterruptManager::default();
+            interrupt_manager
+                .insert(FuzzInputModule {
+                    input: input.clone(),
+                })
+                .unwrap();
+            let kernel = Kernel::<DummyResourceManager>::boot_with_interrupts(
+                KernelConfig::default(),
+                interrupt_manager,
+            )
+            .await
+            .unwrap();
+
+            (client, kernel)
+        });
+
+        Self {
+            runtime,
+            client,
+            kernel,
+            input,
+            program: build_program(),
+        }
+    }
+
+    fn run(&self, data: &[u8]) {
+        *self.input.lock().unwrap() = data.to_vec();
+
+        self.runtime.block_on(async {
+            // create a task and sign
+            let ctx = TaskCtx::new_sandbox();
+            let ctx = self
+                .client
+                .sign(self.client.account_me().account_ref(), ctx)
+                .unwrap();
+            let ctx = self.client.sign_as_guarantor(ctx).unwrap();
+
+            let id = self.kernel.spawn(ctx, &self.program).await.unwrap();
+
+            // note: the task may trap, but the host should survive
+            let record = self.kernel.wait(id).await.unwrap();
+            assert!(matches!(
+                &record.poll,
+                TaskPoll::Ready(_) | TaskPoll::Trap(_)
+            ));
+
+            self.kernel.acknowledge(id).await.unwrap();
+        })
+    }
+}
+
+thread_local! {
+    static HARNESS: Harness = Harness::new();
+}
+
+fuzz_target!(|data: &[u8]| {
+    HARNESS.with(|harness| harness.run(data));
+});
+
+/// Fetches the fuzz input, and then writes its first 4 `{ptr,len}` pairs into the guest memory
+/// as the descriptors of a syscall, so that the host checks the fuzz-controlled data.
+fn build_program() -> Vec<u8> {
+    let descriptors: Vec<u8> = [
+        (PTR_HANDLER_DATA, FUZZ_INPUT.0.len()),
+        (PTR_INPUTS_DATA, 1),
+        (0, 0), // outputs
+        (0, 0), // errors
+    ]
+    .into_iter()
+    .flat_map(|(ptr, len)| {
+        // note: wasm uses little-endian
+        let mut buf = ptr.to_le_bytes().to_vec();
+        buf.extend((len as u32).to_le_bytes());
+        buf
+    })
+    .collect();
+
+    let imports = r#"
+        (import "__ipwis_kernel" "__ipwis_syscall"
+            (func $syscall (param i32 i32 i32 i32) (result i32)))
+    "#;
+
+    let body = format!(
+        r#"
+        (data (i32.const {PTR_HANDLER}) "{descriptors}")
+        (data (i32.const {PTR_HANDLER_DATA}) "{handler}")
+
+        (func (export "__ipwis_syscall") (param i32 i32 i32 i32) (result i32)
+            (local $data i32)
+
+            ;; fetch the fuzz input
+            (if (i32.ne
+                    (call $syscall
+                        (i32.const {PTR_HANDLER}) (i32.const {PTR_INPUTS})
+                        (i32.const {PTR_OUTPUTS}) (i32.const {PTR_ERRORS}))
+                    (i32.const 0))
+                (then unreachable))
+            (local.set $data (i32.load (i32.const {PTR_OUTPUTS})))
+
+            ;; write the arbitrary descriptors, and then feed them
+            (memory.copy
+                (i32.const {PTR_FUZZ_DESCRIPTORS})
+                (local.get $data)
+                (i32.const {FUZZ_DESCRIPTORS_LEN}))
+            (drop (call $syscall
+                (i32.const {PTR_FUZZ_DESCRIPTORS})
+                (i32.const {ptr_fuzz_inputs})
+                (i32.const {ptr_fuzz_outputs})
+                (i32.const {ptr_fuzz_errors})))
+            (i32.const 0))
+        "#,
+        descriptors = ::ipwis_testkit::guest::escape(&descriptors),
+        handler = ::ipwis_testkit::guest::escape(FUZZ_INPUT.0.as_bytes()),
+        ptr_fuzz_inputs = PTR_FUZZ_DESCRIPTORS + 8,
+        ptr_fuzz_outputs = PTR_FUZZ_DESCRIPTORS + 16,
+        ptr_fuzz_errors = PTR_FUZZ_DESCRIPTORS + 24,
+    );
+
+    ::ipwis_testkit::guest::build_guest(imports, &body).unwrap()
+}
+
+struct FuzzInputModule {
+    input: Arc<Mutex<Vec<u8>>>,
+}
+
+#[async_trait]
+impl InterruptModule<IpwisMemoryFamily> for FuzzInputModule {
+    fn id(&self) -> InterruptId {
+        FUZZ_INPUT
+    }
+
+    async fn spawn_handler(
+        &self,
+        _task: &InterruptTask,
+    ) -> Result<Box<dyn DynInterruptHandler<IpwisMemoryFamily>>> {
+        Ok(Box::new(FuzzInputHandler {
+            input: self.input.clone(),
+        }))
+    }
+}
+
+struct FuzzInputHandler {
+    input: Arc<Mutex<Vec<u8>>>,
+}
+
+#[async_trait]
+impl<M> InterruptHandler<M> for FuzzInputHandler
+where
+    M: Memory,
+{
+    async unsafe fn handle_raw(&mut self, _memory: &mut M, _inputs: &[u8]) -> Result<AlignedVec> {
+        let input = self.input.lock().unwrap();
+
+        // note: the guest always reads the descriptors
+        let mut outputs = AlignedVec::with_capacity(input.len().max(FUZZ_DESCRIPTORS_LEN));
+        outputs.extend_from_slice(&input);
+        outputs.resize(outputs.len().max(FUZZ_DESCRIPTORS_LEN), 0);
+        Ok(outputs)
+    }
+
+    async fn release(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -1,12 +1,15 @@
+use core::marker::PhantomData;
+
 use ipis::{
     async_trait::async_trait,
     core::anyhow::{bail, Result},
 };
 use ipwis_kernel_common::{
     data::{ExternData, ExternDataRef},
-    memory::Memory,
+    error::ExternError,
+    memory::{BorrowMemory, Memory},
 };
-use wasmtime::{AsContext, AsContextMut, Caller, Instance, Trap};
+use wasmtime::{AsContext, AsContextMut, Caller, Instance, StoreContextMut, Trap};
 
 use crate::intrinsics::memory::{self, IpwisAlloc, IpwisAllocZeroed, IpwisDealloc, IpwisRealloc};
 
@@ -33,6 +36,20 @@ impl<'a, 'c, T> IpwisMemoryInner<&'c mut Caller<'a, T>> {
     }
 }
 
+impl<'c, T> IpwisMemoryInner<StoreContextMut<'c, T>> {
+    /// Borrows the caller's memory, so that the caller can be used again once it is dropped.
+    pub fn borrow_caller(caller: &'c mut Caller<'_, T>) -> Result<Self> {
+        Ok(Self {
+            memory: memory::caller::__builtin_memory(caller)?,
+            alloc: memory::caller::__alloc(caller)?,
+            alloc_zeroed: memory::caller::__alloc_zeroed(caller)?,
+            dealloc: memory::caller::__dealloc(caller)?,
+            realloc: memory::caller::__realloc(caller)?,
+            store: caller.as_context_mut(),
+        })
+    }
+}
+
 impl<S> IpwisMemoryInner<S>
 where
     S: AsContextMut,
@@ -49,6 +66,16 @@ where
     }
 }
 
+/// The guest memories, which borrow the stores of `T`.
+pub struct StoreMemory<T>(PhantomData<fn() -> T>);
+
+impl<'a, T> BorrowMemory<'a> for StoreMemory<T>
+where
+    T: Send + Sync + 'static,
+{
+    type Memory = IpwisMemoryInner<StoreContextMut<'a, T>>;
+}
+
 #[async_trait]
 impl<S> Memory for IpwisMemoryInner<S>
 where
@@ -57,14 +84,17 @@ where
 {
     fn host_check(&self, data: ExternData) -> Result<()> {
         if data.is_null() {
-            bail!("data is null");
+            bail!(ExternError::invalid_input("data is null"));
         }
 
-        if ((data.ptr + data.len) as usize) >= self.size() {
-            bail!("data overflow");
+        match data.checked_end() {
+            // note: the buffer may end exactly at the end of the memory
+            Some(end) if end as usize <= self.size() => Ok(()),
+            _ => bail!(ExternError::invalid_input(format!(
+                "data overflow: {:#x}+{:#x}",
+                data.ptr, data.len,
+            ))),
         }
-
-        Ok(())
     }
 
     unsafe fn host_ptr_unchecked<T>(&self, ptr: ExternDataRef) -> *const T {
@@ -75,18 +105,48 @@ where
         self.memory.data_ptr(&self.store).add(ptr as usize) as *mut T
     }
 
-    async fn dump(&mut self, data: &[u8]) -> Result<ExternData> {
+    async fn dump_aligned(&mut self, data: &[u8], align: ExternDataRef) -> Result<ExternData> {
         let data_len = data.len();
+        let len = data_len.try_into()?;
 
-        let len = data_len as ExternDataRef;
-        let align = 1; // u8
+        if !align.is_power_of_two() {
+            bail!(ExternError::invalid_input(format!(
+                "invalid alignment: {align}"
+            )));
+        }
+
+        // note: zero-sized allocations are not allowed, so use a dangling pointer instead
+        if len == 0 {
+            return Ok(ExternData { ptr: align, len });
+        }
 
-        // safety: TODO: checking linear memory's limit
+        // safety: the allocated range is checked below
         let ptr = unsafe { self.alloc(len, align) }.await?;
 
+        // note: the guest allocator is not trusted
+        let dst = ExternData { ptr, len };
+        self.host_check(dst)?;
+        if ptr % align != 0 {
+            bail!(ExternError::fatal(format!(
+                "misaligned allocation: {ptr:#x} % {align}"
+            )));
+        }
+
         // safety: the source and destination are already checked
         unsafe { ::core::ptr::copy(data.as_ptr(), self.host_ptr_mut_unchecked(ptr), data_len) };
-        Ok(ExternData { ptr, len })
+        Ok(dst)
+    }
+
+    async unsafe fn free_aligned(&mut self, data: ExternData, align: ExternDataRef) -> Result<()> {
+        // note: zero-sized data is never allocated
+        if data.len == 0 {
+            return Ok(());
+        }
+
+        self.host_check(data)?;
+        self.dealloc(data.ptr, data.len, align)
+            .await
+            .map_err(Into::into)
     }
 }
 
@@ -162,35 +222,37 @@ where
 #[cfg(not(target_os = "wasi"))]
 #[cfg(test)]
 mod tests {
-    use ipis::tokio;
+    use core::future::Future;
+
+    use ipis::{
+        bytecheck::CheckBytes,
+        core::signed::{IsSigned, Serializer},
+        object::IntoObjectData,
+        rkyv::{
+            de::deserializers::SharedDeserializeMap, validation::validators::DefaultValidator,
+            Archive, Deserialize, Serialize,
+        },
+        tokio,
+    };
     use ipwis_kernel_common::{
-        data::{ExternData, ExternDataRef},
+        data::{ExternData, ExternDataRef, EXTERN_DATA_ALIGN},
         extrinsics::{InterruptArgs, SYSCALL_OK},
         memory::Memory,
         modules::{FUNC_NAME_SYSCALL, MODULE_NAME_API, MODULE_NAME_COMMON},
+        resource::ResourceId,
+        task::TaskCtx,
     };
+    use ipwis_modules_stream_common::io;
     use wasmtime::{Caller, Config, Engine, Linker, Store, TypedFunc};
     use wasmtime_wasi::{WasiCtx, WasiCtxBuilder};
 
     use super::IpwisMemoryInner;
 
+    type TestProgram =
+        for<'a> fn(Caller<'a, WasiCtx>) -> Box<dyn Future<Output = ExternDataRef> + Send + 'a>;
+
     #[tokio::test]
     async fn test_memory_alloc_dealloc() {
-        // define the WASI functions globally on the `Config`.
-        let engine = Engine::new(Config::new().async_support(true)).unwrap();
-        let mut linker = Linker::<WasiCtx>::new(&engine);
-        ::wasmtime_wasi::add_to_linker(&mut linker, |s| s).unwrap();
-
-        // Create a WASI context and put it in a Store; all instances in the store
-        // share this context. `WasiCtxBuilder` provides a number of ways to
-        // configure what the target program will have access to.
-        let wasi = WasiCtxBuilder::new()
-            .inherit_stdio()
-            .inherit_args()
-            .unwrap()
-            .build();
-        let mut store = Store::new(&engine, wasi);
-
         // Define our test program
         async fn test(mut caller: Caller<'_, WasiCtx>) -> ExternDataRef {
             // instantiate the memory module
@@ -208,7 +270,7 @@ mod tests {
                 unsafe {
                     // create a zeroed array
                     let ptr = memory.alloc_zeroed(size, align).await.unwrap();
-                    let slice = memory.load_raw(ExternData { ptr, len: size }).unwrap();
+                    let slice = memory.read_raw(ExternData { ptr, len: size }).unwrap();
 
                     // test the array is zeroed
                     assert!(slice.iter().all(|&item| item == 0));
@@ -227,16 +289,92 @@ mod tests {
             SYSCALL_OK
         }
 
+        run_test(|caller| Box::new(test(caller))).await
+    }
+
+    #[tokio::test]
+    async fn test_memory_object_roundtrip() {
+        // Define our test program
+        async fn test(mut caller: Caller<'_, WasiCtx>) -> ExternDataRef {
+            // instantiate the memory module
+            let mut memory = IpwisMemoryInner::with_caller(&mut caller).unwrap();
+
+            roundtrip(&mut memory, &TaskCtx::new_sandbox()).await;
+            roundtrip(&mut memory, &"hello world".__into_object_data()).await;
+            roundtrip(
+                &mut memory,
+                &io::OpCode::ReaderNext(io::request::ReaderNext {
+                    id: ResourceId(42),
+                    buf: ExternData { ptr: 1, len: 2 },
+                }),
+            )
+            .await;
+            roundtrip(
+                &mut memory,
+                &io::OpCode::WriterShutdown(io::request::WriterShutdown { id: ResourceId(42) }),
+            )
+            .await;
+
+            SYSCALL_OK
+        }
+
+        async fn roundtrip<M, T>(memory: &mut M, value: &T)
+        where
+            M: Memory,
+            T: Archive + Serialize<Serializer> + IsSigned + Clone + Send + Sync,
+            <T as Archive>::Archived:
+                for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<T, SharedDeserializeMap>,
+        {
+            let data = memory.dump_object(value).await.unwrap();
+
+            // test the archive is aligned in the guest memory
+            assert_eq!(data.ptr % EXTERN_DATA_ALIGN, 0);
+
+            // test the archive is restored as it is
+            let restored: T = memory.load_object_raw(data).unwrap();
+            assert_eq!(
+                restored.to_bytes().unwrap().as_slice(),
+                value.to_bytes().unwrap().as_slice(),
+            );
+
+            // test the descriptor is restored too
+            let doubled = memory.dump_doubled_object(value).await.unwrap();
+            let restored: T = memory.load_object(doubled.ptr).unwrap();
+            assert_eq!(
+                restored.to_bytes().unwrap().as_slice(),
+                value.to_bytes().unwrap().as_slice(),
+            );
+        }
+
+        run_test(|caller| Box::new(test(caller))).await
+    }
+
+    async fn run_test(test: TestProgram) {
+        // define the WASI functions globally on the `Config`.
+        let engine = Engine::new(Config::new().async_support(true)).unwrap();
+        let mut linker = Linker::<WasiCtx>::new(&engine);
+        ::wasmtime_wasi::add_to_linker(&mut linker, |s| s).unwrap();
+
+        // Create a WASI context and put it in a Store; all instances in the store
+        // share this context. `WasiCtxBuilder` provides a number of ways to
+        // configure what the target program will have access to.
+        let wasi = WasiCtxBuilder::new()
+            .inherit_stdio()
+            .inherit_args()
+            .unwrap()
+            .build();
+        let mut store = Store::new(&engine, wasi);
+
         // Register our test program
         linker
             .func_wrap4_async(
                 MODULE_NAME_COMMON,
                 FUNC_NAME_SYSCALL,
-                |caller,
-                 _handler: ExternDataRef,
-                 _inputs: ExternDataRef,
-                 _outputs: ExternDataRef,
-                 _errors: ExternDataRef| Box::new(test(caller)),
+                move |caller,
+                      _handler: ExternDataRef,
+                      _inputs: ExternDataRef,
+                      _outputs: ExternDataRef,
+                      _errors: ExternDataRef| test(caller),
             )
             .unwrap();
 
@@ -0,0 +1,124 @@
+use core::task::Poll;
+
+use bytecheck::CheckBytes;
+use ipis::{
+    core::{
+        anyhow::Result,
+        signed::{IsSigned, Serializer},
+    },
+    pin::PinnedInner,
+};
+use rkyv::{
+    de::deserializers::SharedDeserializeMap, validation::validators::DefaultValidator,
+    AlignedVec, Archive, Deserialize, Serialize,
+};
+
+use crate::{
+    data::ExternData,
+    error::ExternError,
+    interrupt::{parse_status, InterruptId},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive(compare(PartialEq))]
+#[archive_attr(derive(CheckBytes, Debug, PartialEq))]
+pub struct SyscallBatch {
+    pub calls: Vec<SyscallBatchCall>,
+}
+
+impl IsSigned for SyscallBatch {}
+
+impl SyscallBatch {
+    pub fn push<I>(&mut self, id: InterruptId, inputs: &mut I) -> Result<()>
+    where
+        I: Serialize<Serializer> + IsSigned + Send + Sync,
+    {
+        self.push_raw(id, inputs.to_bytes()?.to_vec());
+        Ok(())
+    }
+
+    pub fn push_raw(&mut self, id: InterruptId, inputs: Vec<u8>) {
+        self.calls.push(SyscallBatchCall {
+            handler: id.0.to_string(),
+            inputs,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    pub unsafe fn syscall(&mut self) -> Result<Vec<SyscallBatchOutput>> {
+        let inputs = self.to_bytes()?;
+
+        // initiate I/O placeholders
+        let inputs = ExternData::from_slice(&inputs);
+        let mut outputs = ExternData::default();
+        let mut errors = ExternData::default();
+
+        // execute syscall
+        let status = crate::extrinsics::__ipwis_syscall_batch(
+            inputs.as_ptr(),
+            outputs.as_mut_ptr(),
+            errors.as_mut_ptr(),
+        );
+
+        match parse_status("SyscallBatch", status, outputs, errors)? {
+            Poll::Ready(outputs) => {
+                let outputs: SyscallBatchOutputs = PinnedInner::deserialize_owned(outputs)?;
+                Ok(outputs.results)
+            }
+            Poll::Pending => {
+                Err(ExternError::fatal("SyscallBatch: unexpected pending syscall").into())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive(compare(PartialEq))]
+#[archive_attr(derive(CheckBytes, Debug, PartialEq))]
+pub struct SyscallBatchCall {
+    pub handler: String,
+    pub inputs: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive(compare(PartialEq))]
+#[archive_attr(derive(CheckBytes, Debug, PartialEq))]
+pub struct SyscallBatchOutputs {
+    pub results: Vec<SyscallBatchOutput>,
+}
+
+impl IsSigned for SyscallBatchOutputs {}
+
+#[derive(Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive(compare(PartialEq))]
+#[archive_attr(derive(CheckBytes, Debug, PartialEq))]
+pub enum SyscallBatchOutput {
+    Ok(Vec<u8>),
+    Err(ExternError),
+}
+
+impl SyscallBatchOutput {
+    pub fn into_result<O>(self) -> Result<O>
+    where
+        O: Archive,
+        <O as Archive>::Archived:
+            for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+    {
+        match self {
+            Self::Ok(outputs) => {
+                // note: the outputs may be misaligned for the archives
+                let mut buf = AlignedVec::with_capacity(outputs.len());
+                buf.extend_from_slice(&outputs);
+                PinnedInner::deserialize_owned(buf)
+            }
+            Self::Err(error) => Err(error.into()),
+        }
+    }
+}
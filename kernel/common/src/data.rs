@@ -1,8 +1,10 @@
 use core::mem::MaybeUninit;
 
 use bytecheck::CheckBytes;
-use ipis::core::anyhow::{bail, Result};
-use rkyv::{Archive, Deserialize, Serialize};
+use ipis::{core::anyhow::Result, pin::PinnedInner};
+use rkyv::{AlignedVec, Archive, Deserialize, Serialize};
+
+use crate::error::ExternError;
 
 #[derive(Copy, Clone, Debug, Default, Archive, Serialize, Deserialize)]
 #[archive_attr(derive(CheckBytes, Copy, Clone, Debug))]
@@ -33,6 +35,22 @@ impl ExternData {
         Self::with_raw_parts_mut(ptr as *mut u8, len)
     }
 
+    // note: the ownership is transferred to the host, which releases it with `EXTERN_DATA_ALIGN`
+    pub unsafe fn from_slice_owned(slice: &[u8]) -> Self {
+        if slice.is_empty() {
+            return Self::with_raw_parts(EXTERN_DATA_ALIGN as usize as *const u8, 0);
+        }
+
+        let layout = ::std::alloc::Layout::from_size_align_unchecked(
+            slice.len(),
+            EXTERN_DATA_ALIGN as usize,
+        );
+        let ptr = ::std::alloc::alloc(layout);
+        ::core::ptr::copy_nonoverlapping(slice.as_ptr(), ptr, slice.len());
+
+        Self::with_raw_parts(ptr, slice.len())
+    }
+
     pub fn with_raw_parts(ptr: *const u8, len: usize) -> Self {
         Self {
             ptr: ptr as ExternDataRef,
@@ -51,6 +69,10 @@ impl ExternData {
         self.ptr == 0
     }
 
+    pub fn checked_end(&self) -> Option<ExternDataRef> {
+        self.ptr.checked_add(self.len)
+    }
+
     pub fn as_ptr(&self) -> ExternDataRef {
         self as *const Self as ExternDataRef
     }
@@ -79,28 +101,43 @@ impl ExternData {
         }
     }
 
-    pub unsafe fn into_vec<T>(self) -> Vec<T> {
-        self.try_into_vec().unwrap_or_default()
+    pub unsafe fn into_aligned_vec(self) -> AlignedVec {
+        self.try_into_aligned_vec().unwrap_or_default()
     }
 
-    pub unsafe fn try_into_vec<T>(self) -> Option<Vec<T>> {
-        let ptr = self.ptr as usize as *mut T;
-        let len = self.len as usize;
+    // note: only for the data allocated by the host
+    pub unsafe fn try_into_aligned_vec(self) -> Option<AlignedVec> {
+        let slice = self.try_into_slice::<u8>()?;
 
-        if ptr.is_null() {
-            None
-        } else {
-            Some(Vec::from_raw_parts(ptr, len, len))
+        let mut vec = AlignedVec::with_capacity(slice.len());
+        vec.extend_from_slice(slice);
+
+        // consume owner
+        self.release();
+        Some(vec)
+    }
+
+    // note: the host deallocates the data, so that it can track the live ones
+    pub unsafe fn release(self) {
+        if self.len > 0 {
+            // note: the failures are reported by the host itself
+            crate::extrinsics::__ipwis_syscall_release(self.ptr, self.len);
         }
     }
 
     pub unsafe fn assume_error(self) -> Result<()> {
         // consume owner
-        match self.try_into_vec() {
-            Some(vec) => bail!(String::from_utf8_unchecked(vec)),
+        match self.try_into_aligned_vec() {
+            Some(vec) => {
+                let error: ExternError = PinnedInner::deserialize_owned(&vec)?;
+                Err(error.into())
+            }
             None => Ok(()),
         }
     }
 }
 
 pub type ExternDataRef = u32;
+
+/// The alignment of the data allocated by the host, which fits the archives.
+pub const EXTERN_DATA_ALIGN: ExternDataRef = AlignedVec::ALIGNMENT as ExternDataRef;
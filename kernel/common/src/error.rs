@@ -0,0 +1,226 @@
+use bytecheck::CheckBytes;
+use ipis::core::{anyhow, signed::IsSigned};
+use rkyv::{Archive, Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive(compare(PartialEq))]
+#[archive_attr(derive(CheckBytes, Debug, PartialEq))]
+pub struct ExternError {
+    pub code: ErrorCode,
+    pub module: Option<String>,
+    pub message: String,
+}
+
+impl IsSigned for ExternError {}
+
+impl ExternError {
+    pub fn new(code: ErrorCode, message: impl ToString) -> Self {
+        Self {
+            code,
+            module: None,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn not_found(message: impl ToString) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn permission_denied(message: impl ToString) -> Self {
+        Self::new(ErrorCode::PermissionDenied, message)
+    }
+
+    pub fn would_block(message: impl ToString) -> Self {
+        Self::new(ErrorCode::WouldBlock, message)
+    }
+
+    pub fn limit_exceeded(message: impl ToString) -> Self {
+        Self::new(ErrorCode::LimitExceeded, message)
+    }
+
+    pub fn invalid_input(message: impl ToString) -> Self {
+        Self::new(ErrorCode::InvalidInput, message)
+    }
+
+    pub fn unsupported(message: impl ToString) -> Self {
+        Self::new(ErrorCode::Unsupported, message)
+    }
+
+    pub fn fatal(message: impl ToString) -> Self {
+        Self::new(ErrorCode::Fatal, message)
+    }
+
+    pub fn broken_pipe(message: impl ToString) -> Self {
+        Self::new(ErrorCode::BrokenPipe, message)
+    }
+
+    // note: the innermost module is kept
+    pub fn with_module(mut self, module: &str) -> Self {
+        if self.module.is_none() {
+            self.module = Some(module.to_string());
+        }
+        self
+    }
+}
+
+impl From<anyhow::Error> for ExternError {
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<Self>() {
+            Ok(error) => error,
+            Err(error) => match error.downcast_ref::<::std::io::Error>() {
+                Some(io_error) => Self::new(io_error.kind().into(), error),
+                None => Self::new(ErrorCode::Other, error),
+            },
+        }
+    }
+}
+
+impl ::core::fmt::Display for ExternError {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        match &self.module {
+            Some(module) => write!(f, "[{module}] {:?}: {}", self.code, &self.message),
+            None => write!(f, "{:?}: {}", self.code, &self.message),
+        }
+    }
+}
+
+impl ::std::error::Error for ExternError {}
+
+impl From<ExternError> for ::std::io::Error {
+    fn from(error: ExternError) -> Self {
+        Self::new(error.code.into(), error)
+    }
+}
+
+// note: new codes should be appended at the end to keep the ABI stable
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Archive, Serialize, Deserialize,
+)]
+#[archive(compare(PartialEq, PartialOrd))]
+#[archive_attr(derive(CheckBytes, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash))]
+#[repr(C)]
+pub enum ErrorCode {
+    Other,
+    NotFound,
+    PermissionDenied,
+    WouldBlock,
+    LimitExceeded,
+    InvalidInput,
+    Unsupported,
+    Fatal,
+    BrokenPipe,
+}
+
+impl IsSigned for ErrorCode {}
+
+impl ErrorCode {
+    pub const fn code(&self) -> u32 {
+        match self {
+            Self::Other => 0,
+            Self::NotFound => 1,
+            Self::PermissionDenied => 2,
+            Self::WouldBlock => 3,
+            Self::LimitExceeded => 4,
+            Self::InvalidInput => 5,
+            Self::Unsupported => 6,
+            Self::Fatal => 7,
+            Self::BrokenPipe => 8,
+        }
+    }
+
+    pub const fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 => Some(Self::Other),
+            1 => Some(Self::NotFound),
+            2 => Some(Self::PermissionDenied),
+            3 => Some(Self::WouldBlock),
+            4 => Some(Self::LimitExceeded),
+            5 => Some(Self::InvalidInput),
+            6 => Some(Self::Unsupported),
+            7 => Some(Self::Fatal),
+            8 => Some(Self::BrokenPipe),
+            _ => None,
+        }
+    }
+}
+
+impl From<::std::io::ErrorKind> for ErrorCode {
+    fn from(kind: ::std::io::ErrorKind) -> Self {
+        match kind {
+            ::std::io::ErrorKind::NotFound => Self::NotFound,
+            ::std::io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            ::std::io::ErrorKind::WouldBlock => Self::WouldBlock,
+            ::std::io::ErrorKind::InvalidInput => Self::InvalidInput,
+            ::std::io::ErrorKind::Unsupported => Self::Unsupported,
+            ::std::io::ErrorKind::BrokenPipe => Self::BrokenPipe,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl From<ErrorCode> for ::std::io::ErrorKind {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::Other => Self::Other,
+            ErrorCode::NotFound => Self::NotFound,
+            ErrorCode::PermissionDenied => Self::PermissionDenied,
+            ErrorCode::WouldBlock => Self::WouldBlock,
+            ErrorCode::LimitExceeded => Self::Other,
+            ErrorCode::InvalidInput => Self::InvalidInput,
+            ErrorCode::Unsupported => Self::Unsupported,
+            ErrorCode::Fatal => Self::Other,
+            ErrorCode::BrokenPipe => Self::BrokenPipe,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::ErrorCode;
+
+    #[test]
+    fn test_code() {
+        // note: the codes are a part of the ABI, so they should never be changed
+        let codes = [
+            (ErrorCode::Other, 0),
+            (ErrorCode::NotFound, 1),
+            (ErrorCode::PermissionDenied, 2),
+            (ErrorCode::WouldBlock, 3),
+            (ErrorCode::LimitExceeded, 4),
+            (ErrorCode::InvalidInput, 5),
+            (ErrorCode::Unsupported, 6),
+            (ErrorCode::Fatal, 7),
+            (ErrorCode::BrokenPipe, 8),
+        ];
+        for (error, code) in codes {
+            assert_eq!(error.code(), code);
+            assert_eq!(ErrorCode::from_code(code), Some(error));
+        }
+        assert_eq!(ErrorCode::from_code(codes.len() as u32), None);
+    }
+
+    #[test]
+    fn test_io_error_kind() {
+        // test the codes with their own kinds are round-tripped
+        let kinds = [
+            (ErrorCode::NotFound, ErrorKind::NotFound),
+            (ErrorCode::PermissionDenied, ErrorKind::PermissionDenied),
+            (ErrorCode::WouldBlock, ErrorKind::WouldBlock),
+            (ErrorCode::InvalidInput, ErrorKind::InvalidInput),
+            (ErrorCode::Unsupported, ErrorKind::Unsupported),
+            (ErrorCode::BrokenPipe, ErrorKind::BrokenPipe),
+        ];
+        for (error, kind) in kinds {
+            assert_eq!(ErrorKind::from(error), kind);
+            assert_eq!(ErrorCode::from(kind), error);
+        }
+
+        // test the others fall back to the generic ones
+        for error in [ErrorCode::Other, ErrorCode::LimitExceeded, ErrorCode::Fatal] {
+            assert_eq!(ErrorKind::from(error), ErrorKind::Other);
+        }
+        assert_eq!(ErrorCode::from(ErrorKind::TimedOut), ErrorCode::Other);
+    }
+}
@@ -0,0 +1,29 @@
+use core::{
+    future::Future,
+    task::{Context, Poll},
+};
+
+use ipis::{core::anyhow::Result, futures::task::noop_waker_ref};
+
+use crate::interrupt::InterruptTicket;
+
+/// Runs the future to completion on the guest.
+///
+/// The guest has no reactor, so it parks in the kernel until any of the
+/// submitted interrupts is completed, and then polls the future again.
+pub unsafe fn block_on<F>(future: F) -> Result<F::Output>
+where
+    F: Future,
+{
+    let mut future = Box::pin(future);
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            break Ok(output);
+        }
+
+        // note: the completed ticket is kept until the future polls it
+        InterruptTicket::wait_any()?;
+    }
+}
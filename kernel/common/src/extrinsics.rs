@@ -8,6 +8,35 @@ extern "C" {
         outputs: ExternDataRef,
         errors: ExternDataRef,
     ) -> ExternDataRef;
+
+    pub fn __ipwis_syscall_batch(
+        inputs: ExternDataRef,
+        outputs: ExternDataRef,
+        errors: ExternDataRef,
+    ) -> ExternDataRef;
+
+    pub fn __ipwis_syscall_submit(
+        handler: ExternDataRef,
+        inputs: ExternDataRef,
+        ticket: ExternDataRef,
+        errors: ExternDataRef,
+    ) -> ExternDataRef;
+
+    pub fn __ipwis_syscall_poll(
+        ticket: ExternDataRef,
+        outputs: ExternDataRef,
+        errors: ExternDataRef,
+    ) -> ExternDataRef;
+
+    pub fn __ipwis_syscall_wait(
+        ticket: ExternDataRef,
+        outputs: ExternDataRef,
+        errors: ExternDataRef,
+    ) -> ExternDataRef;
+
+    pub fn __ipwis_syscall_wait_any(ticket: ExternDataRef, errors: ExternDataRef) -> ExternDataRef;
+
+    pub fn __ipwis_syscall_release(ptr: ExternDataRef, len: ExternDataRef) -> ExternDataRef;
 }
 
 pub type InterruptFn = unsafe extern "C" fn(
@@ -26,3 +55,4 @@ pub type InterruptArgs = (
 pub const SYSCALL_OK: ExternDataRef = 0;
 pub const SYSCALL_ERR_NORMAL: ExternDataRef = 1;
 pub const SYSCALL_ERR_FATAL: ExternDataRef = 2;
+pub const SYSCALL_PENDING: ExternDataRef = 3;
@@ -1,7 +1,22 @@
+use core::{
+    any::{Any, TypeId},
+    future::Future,
+    pin::Pin,
+    task::Poll,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
 use bytecheck::CheckBytes;
 use ipis::{
     async_trait::async_trait,
     core::{
+        account::{AccountRef, GuarantorSigned},
         anyhow::Result,
         signed::{IsSigned, Serializer},
     },
@@ -12,7 +27,15 @@ use rkyv::{
     Archive, Deserialize, Serialize,
 };
 
-use crate::{data::ExternData, memory::Memory};
+use crate::{
+    data::{ExternData, ExternDataRef},
+    error::ExternError,
+    extrinsics::{SYSCALL_ERR_FATAL, SYSCALL_ERR_NORMAL, SYSCALL_OK, SYSCALL_PENDING},
+    memory::{BorrowedMemory, Memory, MemoryFamily},
+    protection::ProtectionMode,
+    resource::ResourceId,
+    task::{TaskCtx, TaskId},
+};
 
 #[async_trait]
 pub trait InterruptHandler<M>
@@ -22,6 +45,17 @@ where
 {
     async unsafe fn handle_raw(&mut self, memory: &mut M, inputs: &[u8]) -> Result<AlignedVec>;
 
+    // note: the handlers which cannot run apart from the guest are completed immediately
+    async unsafe fn submit_raw(
+        &mut self,
+        memory: &mut M,
+        inputs: &[u8],
+    ) -> Result<InterruptSubmission> {
+        self.handle_raw(memory, inputs)
+            .await
+            .map(InterruptSubmission::Ready)
+    }
+
     async fn release(&mut self) -> Result<()>;
 }
 
@@ -31,37 +65,243 @@ where
     Self: InterruptHandler<M> + Send + Sync,
     M: Memory,
 {
-    async fn handle_fallback(
-        &self,
-        memory: &mut M,
-        id: InterruptId,
-        inputs: &[u8],
-    ) -> Result<AlignedVec>;
+    async fn handle_fallback(&self, memory: &mut M, id: &str, inputs: &[u8]) -> Result<AlignedVec>;
+}
+
+/// An interrupt handler, which accepts the guest memory borrowed only for a syscall.
+pub trait DynInterruptHandler<F>
+where
+    Self: Send + Sync,
+    F: MemoryFamily,
+{
+    unsafe fn handle_raw<'a, 'm>(
+        &'a mut self,
+        memory: &'a mut BorrowedMemory<'m, F>,
+        inputs: &'a [u8],
+    ) -> InterruptHandlerFuture<'a, AlignedVec>
+    where
+        'm: 'a;
+
+    unsafe fn submit_raw<'a, 'm>(
+        &'a mut self,
+        memory: &'a mut BorrowedMemory<'m, F>,
+        inputs: &'a [u8],
+    ) -> InterruptHandlerFuture<'a, InterruptSubmission>
+    where
+        'm: 'a;
+
+    fn release(&mut self) -> InterruptHandlerFuture<'_, ()>;
+}
+
+impl<F, H> DynInterruptHandler<F> for H
+where
+    F: MemoryFamily,
+    H: for<'m> InterruptHandler<BorrowedMemory<'m, F>>,
+{
+    unsafe fn handle_raw<'a, 'm>(
+        &'a mut self,
+        memory: &'a mut BorrowedMemory<'m, F>,
+        inputs: &'a [u8],
+    ) -> InterruptHandlerFuture<'a, AlignedVec>
+    where
+        'm: 'a,
+    {
+        InterruptHandler::<BorrowedMemory<'m, F>>::handle_raw(self, memory, inputs)
+    }
+
+    unsafe fn submit_raw<'a, 'm>(
+        &'a mut self,
+        memory: &'a mut BorrowedMemory<'m, F>,
+        inputs: &'a [u8],
+    ) -> InterruptHandlerFuture<'a, InterruptSubmission>
+    where
+        'm: 'a,
+    {
+        InterruptHandler::<BorrowedMemory<'m, F>>::submit_raw(self, memory, inputs)
+    }
+
+    fn release(&mut self) -> InterruptHandlerFuture<'_, ()> {
+        InterruptHandler::<BorrowedMemory<'static, F>>::release(self)
+    }
+}
+
+/// A fallback handler, which accepts the guest memory borrowed only for a syscall.
+pub trait DynInterruptFallbackHandler<F>
+where
+    Self: DynInterruptHandler<F> + Send + Sync,
+    F: MemoryFamily,
+{
+    fn handle_fallback<'a, 'm>(
+        &'a self,
+        memory: &'a mut BorrowedMemory<'m, F>,
+        id: &'a str,
+        inputs: &'a [u8],
+    ) -> InterruptHandlerFuture<'a, AlignedVec>
+    where
+        'm: 'a;
+}
+
+impl<F, H> DynInterruptFallbackHandler<F> for H
+where
+    F: MemoryFamily,
+    H: for<'m> InterruptFallbackHandler<BorrowedMemory<'m, F>>,
+{
+    fn handle_fallback<'a, 'm>(
+        &'a self,
+        memory: &'a mut BorrowedMemory<'m, F>,
+        id: &'a str,
+        inputs: &'a [u8],
+    ) -> InterruptHandlerFuture<'a, AlignedVec>
+    where
+        'm: 'a,
+    {
+        InterruptFallbackHandler::<BorrowedMemory<'m, F>>::handle_fallback(self, memory, id, inputs)
+    }
 }
 
+pub type InterruptHandlerFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
 #[async_trait]
-pub trait InterruptModule<M>
+pub trait InterruptModule<F>
 where
     Self: Send + Sync,
-    M: Memory,
+    F: MemoryFamily,
 {
     fn id(&self) -> InterruptId;
 
-    async fn spawn_handler(&self) -> Result<Box<dyn InterruptHandler<M>>>;
+    async fn spawn_handler(&self, task: &InterruptTask) -> Result<Box<dyn DynInterruptHandler<F>>>;
 }
 
 #[async_trait]
-pub trait InterruptFallbackModule<M>
+pub trait InterruptFallbackModule<F>
 where
-    Self: InterruptModule<M> + Send + Sync,
-    M: Memory,
+    Self: InterruptModule<F> + Send + Sync,
+    F: MemoryFamily,
 {
-    async fn spawn_fallback(&self) -> Result<Box<dyn InterruptFallbackHandler<M>>>;
+    async fn spawn_fallback(
+        &self,
+        task: &InterruptTask,
+    ) -> Result<Box<dyn DynInterruptFallbackHandler<F>>>;
+}
+
+/// The task, which the interrupt handlers are spawned for.
+#[derive(Clone, Debug)]
+pub struct InterruptTask {
+    pub id: TaskId,
+    pub resource_id: ResourceId,
+    pub ctx: Arc<GuarantorSigned<TaskCtx>>,
+    pub protection_mode: ProtectionMode,
+    pub extensions: InterruptExtensions,
+    /// The task, which has spawned this one on the kernel.
+    pub parent: Option<TaskParent>,
+}
+
+impl InterruptTask {
+    /// Returns the account, which has requested the task.
+    pub fn guarantee(&self) -> AccountRef {
+        self.ctx.guarantee.account
+    }
+}
+
+/// The lineage of a task, which is recorded by the kernel rather than the task itself.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TaskParent {
+    pub id: TaskId,
+    pub guarantee: AccountRef,
+}
+
+/// The per-task states, which are shared between the interrupt handlers of the task.
+#[derive(Clone, Default)]
+pub struct InterruptExtensions(Arc<Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>>);
+
+impl ::core::fmt::Debug for InterruptExtensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InterruptExtensions")
+            .finish_non_exhaustive()
+    }
+}
+
+impl InterruptExtensions {
+    pub fn get_or_default<T>(&self) -> Arc<T>
+    where
+        T: Any + Default + Send + Sync,
+    {
+        self.0
+            .lock()
+            .unwrap()
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(T::default()))
+            .clone()
+            .downcast()
+            // note: the entries are keyed by their own types
+            .unwrap()
+    }
+
+    /// Drops the per-task states, e.g. the streams of the finished task.
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear()
+    }
+}
+
+/// The outcome of the task, which is marked by the kernel before the handlers are released.
+#[derive(Debug, Default)]
+pub struct InterruptTaskOutcome {
+    trapped: AtomicBool,
+}
+
+impl InterruptTaskOutcome {
+    pub fn with_task(task: &InterruptTask) -> Arc<Self> {
+        task.extensions.get_or_default()
+    }
+
+    pub fn set_trapped(&self) {
+        self.trapped.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_trapped(&self) -> bool {
+        self.trapped.load(Ordering::SeqCst)
+    }
+}
+
+/// The usage of the task, which is counted by the handlers and collected by the kernel.
+#[derive(Debug, Default)]
+pub struct InterruptTaskUsage {
+    stream_bytes_read: AtomicU64,
+    stream_bytes_written: AtomicU64,
+}
+
+impl InterruptTaskUsage {
+    pub fn with_task(task: &InterruptTask) -> Arc<Self> {
+        task.extensions.get_or_default()
+    }
+
+    pub fn add_stream_bytes_read(&self, len: u64) {
+        self.stream_bytes_read.fetch_add(len, Ordering::Relaxed);
+    }
+
+    pub fn add_stream_bytes_written(&self, len: u64) {
+        self.stream_bytes_written.fetch_add(len, Ordering::Relaxed);
+    }
+
+    pub fn stream_bytes_read(&self) -> u64 {
+        self.stream_bytes_read.load(Ordering::Relaxed)
+    }
+
+    pub fn stream_bytes_written(&self) -> u64 {
+        self.stream_bytes_written.load(Ordering::Relaxed)
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct InterruptId(pub &'static str);
 
+// note: the guest-given ids can be resolved without leaking them
+impl ::core::borrow::Borrow<str> for InterruptId {
+    fn borrow(&self) -> &str {
+        self.0
+    }
+}
+
 impl ::core::fmt::Display for InterruptId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "InterruptHandler({})", &self.0)
@@ -83,7 +323,7 @@ impl InterruptId {
         PinnedInner::deserialize_owned(outputs)
     }
 
-    pub unsafe fn syscall_raw(&self, inputs: &[u8]) -> Result<Vec<u8>> {
+    pub unsafe fn syscall_raw(&self, inputs: &[u8]) -> Result<AlignedVec> {
         // initiate I/O placeholders
         let handler = ExternData::from_slice(self.0.as_bytes());
         let inputs = ExternData::from_slice(inputs);
@@ -91,19 +331,190 @@ impl InterruptId {
         let mut errors = ExternData::default();
 
         // execute syscall
-        crate::extrinsics::__ipwis_syscall(
+        let status = crate::extrinsics::__ipwis_syscall(
             handler.as_ptr(),
             inputs.as_ptr(),
             outputs.as_mut_ptr(),
             errors.as_mut_ptr(),
         );
 
-        // try parsing error
-        errors.assume_error()?;
+        match parse_status(self, status, outputs, errors)? {
+            Poll::Ready(outputs) => Ok(outputs),
+            Poll::Pending => {
+                Err(ExternError::fatal(format!("{self}: unexpected pending syscall")).into())
+            }
+        }
+    }
+
+    pub unsafe fn submit<I>(&self, inputs: &mut I) -> Result<InterruptTicket>
+    where
+        I: Serialize<Serializer> + IsSigned + Send + Sync,
+    {
+        let inputs = inputs.to_bytes()?;
+
+        self.submit_raw(&inputs)
+    }
+
+    pub unsafe fn submit_raw(&self, inputs: &[u8]) -> Result<InterruptTicket> {
+        // initiate I/O placeholders
+        let handler = ExternData::from_slice(self.0.as_bytes());
+        let inputs = ExternData::from_slice(inputs);
+        let mut ticket: ExternDataRef = 0;
+        let mut errors = ExternData::default();
+
+        // execute syscall
+        let status = crate::extrinsics::__ipwis_syscall_submit(
+            handler.as_ptr(),
+            inputs.as_ptr(),
+            &mut ticket as *mut ExternDataRef as ExternDataRef,
+            errors.as_mut_ptr(),
+        );
+
+        parse_status(self, status, Default::default(), errors)?;
+        Ok(InterruptTicket(ResourceId(ticket)))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InterruptTicket(pub ResourceId);
+
+impl ::core::fmt::Display for InterruptTicket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "InterruptTicket({:x})", &self.0)
+    }
+}
+
+impl InterruptTicket {
+    pub unsafe fn poll<O>(&self) -> Result<Poll<O>>
+    where
+        O: Archive,
+        <O as Archive>::Archived:
+            for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+    {
+        match self.poll_raw()? {
+            Poll::Ready(outputs) => PinnedInner::deserialize_owned(outputs).map(Poll::Ready),
+            Poll::Pending => Ok(Poll::Pending),
+        }
+    }
+
+    pub unsafe fn poll_raw(&self) -> Result<Poll<AlignedVec>> {
+        // initiate I/O placeholders
+        let mut outputs = ExternData::default();
+        let mut errors = ExternData::default();
+
+        // execute syscall
+        let status = crate::extrinsics::__ipwis_syscall_poll(
+            self.0 .0,
+            outputs.as_mut_ptr(),
+            errors.as_mut_ptr(),
+        );
+
+        parse_status(self, status, outputs, errors)
+    }
+
+    pub unsafe fn wait<O>(&self) -> Result<O>
+    where
+        O: Archive,
+        <O as Archive>::Archived:
+            for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+    {
+        let outputs = self.wait_raw()?;
+
+        PinnedInner::deserialize_owned(outputs)
+    }
+
+    pub unsafe fn wait_raw(&self) -> Result<AlignedVec> {
+        // initiate I/O placeholders
+        let mut outputs = ExternData::default();
+        let mut errors = ExternData::default();
+
+        // execute syscall
+        let status = crate::extrinsics::__ipwis_syscall_wait(
+            self.0 .0,
+            outputs.as_mut_ptr(),
+            errors.as_mut_ptr(),
+        );
+
+        match parse_status(self, status, outputs, errors)? {
+            Poll::Ready(outputs) => Ok(outputs),
+            Poll::Pending => {
+                Err(ExternError::fatal(format!("{self}: unexpected pending syscall")).into())
+            }
+        }
+    }
+
+    // note: the completed ticket should be polled to take its result
+    pub unsafe fn wait_any() -> Result<Self> {
+        // initiate I/O placeholders
+        let mut ticket: ExternDataRef = 0;
+        let mut errors = ExternData::default();
+
+        // execute syscall
+        let status = crate::extrinsics::__ipwis_syscall_wait_any(
+            &mut ticket as *mut ExternDataRef as ExternDataRef,
+            errors.as_mut_ptr(),
+        );
+
+        parse_status("InterruptTicket(*)", status, Default::default(), errors)?;
+        Ok(Self(ResourceId(ticket)))
+    }
+}
+
+pub(crate) unsafe fn parse_status(
+    context: impl ::core::fmt::Display,
+    status: ExternDataRef,
+    outputs: ExternData,
+    errors: ExternData,
+) -> Result<Poll<AlignedVec>> {
+    match status {
+        SYSCALL_OK => {
+            // parse result
+            // note: the host may return nothing
+            Ok(Poll::Ready(outputs.into_aligned_vec()))
+        }
+        SYSCALL_ERR_NORMAL => {
+            // try parsing error
+            errors.assume_error()?;
+            Err(ExternError::fatal(format!("{context}: missing syscall error")).into())
+        }
+        SYSCALL_ERR_FATAL => {
+            // note: the outputs and errors are not trustworthy
+            Err(ExternError::fatal(format!("{context}: fatal syscall failure")).into())
+        }
+        SYSCALL_PENDING => Ok(Poll::Pending),
+        status => {
+            Err(ExternError::fatal(format!("{context}: unknown syscall status: {status}")).into())
+        }
+    }
+}
+
+pub enum InterruptSubmission {
+    Ready(AlignedVec),
+    Pending(InterruptFuture),
+}
+
+impl InterruptSubmission {
+    pub fn pending<F>(future: F) -> Self
+    where
+        F: Future<Output = Result<InterruptCompletion>> + Send + 'static,
+    {
+        Self::Pending(Box::pin(future))
+    }
+}
+
+pub type InterruptFuture = Pin<Box<dyn Future<Output = Result<InterruptCompletion>> + Send>>;
+
+pub struct InterruptCompletion {
+    pub outputs: AlignedVec,
+    // the data which should be copied into the guest memory on completion
+    pub copy_to: Option<(ExternData, Vec<u8>)>,
+}
 
-        // parse result
-        let ptr = outputs.ptr as *mut u8;
-        let len = outputs.len as usize;
-        Ok(Vec::from_raw_parts(ptr, len, len))
+impl From<AlignedVec> for InterruptCompletion {
+    fn from(outputs: AlignedVec) -> Self {
+        Self {
+            outputs,
+            copy_to: None,
+        }
     }
 }
@@ -1,16 +1,26 @@
 #![allow(clippy::missing_safety_doc)]
 
+pub mod batch;
 pub mod data;
+pub mod error;
+pub mod executor;
 pub mod extrinsics;
 pub mod interrupt;
 pub mod memory;
 pub mod protection;
 pub mod resource;
 pub mod task;
+pub mod usage;
 
 pub mod modules {
     pub const MODULE_NAME_API: &str = "__ipwis_kernel_api";
     pub const MODULE_NAME_COMMON: &str = "__ipwis_kernel";
 
     pub const FUNC_NAME_SYSCALL: &str = "__ipwis_syscall";
+    pub const FUNC_NAME_SYSCALL_BATCH: &str = "__ipwis_syscall_batch";
+    pub const FUNC_NAME_SYSCALL_SUBMIT: &str = "__ipwis_syscall_submit";
+    pub const FUNC_NAME_SYSCALL_POLL: &str = "__ipwis_syscall_poll";
+    pub const FUNC_NAME_SYSCALL_WAIT: &str = "__ipwis_syscall_wait";
+    pub const FUNC_NAME_SYSCALL_WAIT_ANY: &str = "__ipwis_syscall_wait_any";
+    pub const FUNC_NAME_SYSCALL_RELEASE: &str = "__ipwis_syscall_release";
 }
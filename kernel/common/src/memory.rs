@@ -2,21 +2,29 @@ use ipis::{
     async_trait::async_trait,
     bytecheck::CheckBytes,
     core::{
-        anyhow::Result,
+        anyhow::{bail, Result},
         signed::{IsSigned, Serializer},
     },
+    pin::PinnedInner,
     rkyv::{
-        de::deserializers::SharedDeserializeMap, validation::validators::DefaultValidator, Archive,
-        Deserialize, Serialize,
+        de::deserializers::SharedDeserializeMap, validation::validators::DefaultValidator,
+        AlignedVec, Archive, Deserialize, Serialize,
     },
 };
 
-use crate::data::{ExternData, ExternDataRef};
+use crate::{
+    data::{ExternData, ExternDataRef, EXTERN_DATA_ALIGN},
+    error::ExternError,
+};
 
 #[async_trait]
 pub trait Memory: Send + Sync {
     fn is_null(&self, data: ExternDataRef) -> Result<bool> {
-        Ok((data as *const ExternData).is_null())
+        if data == 0 {
+            return Ok(true);
+        }
+        self.read_value::<ExternData>(data)
+            .map(|data| data.is_null())
     }
 
     fn host_check(&self, data: ExternData) -> Result<()>;
@@ -57,37 +65,148 @@ pub trait Memory: Send + Sync {
             .map(|ptr| unsafe { &mut *ptr })
     }
 
-    fn load(&self, data: ExternDataRef) -> Result<&[u8]> {
-        // safety: checking is already done by `host_ptr`
-        let data = unsafe { *self.host_ptr(data)? };
+    /// # Safety
+    ///
+    /// The view is invalidated when the guest memory grows,
+    /// so it should never be held across `dump` or any guest call.
+    unsafe fn load(&self, data: ExternDataRef) -> Result<&[u8]> {
+        let data = self.read_value(data)?;
         self.load_raw(data)
     }
 
-    fn load_raw(&self, data: ExternData) -> Result<&[u8]> {
+    /// # Safety
+    ///
+    /// See [`Memory::load`].
+    unsafe fn load_raw(&self, data: ExternData) -> Result<&[u8]> {
         self.host_check(data)
             // safety: checking is already done by `host_check`
-            .map(|()| unsafe {
+            .map(|()| {
                 let ptr = self.host_ptr_unchecked(data.ptr);
                 ::core::slice::from_raw_parts(ptr, data.len as usize)
             })
     }
 
-    fn load_mut(&mut self, data: ExternDataRef) -> Result<&mut [u8]> {
-        // safety: checking is already done by `host_ptr_mut`
-        let data = unsafe { *self.host_ptr_mut(data)? };
+    /// # Safety
+    ///
+    /// See [`Memory::load`].
+    unsafe fn load_mut(&mut self, data: ExternDataRef) -> Result<&mut [u8]> {
+        let data = self.read_value(data)?;
         self.load_mut_raw(data)
     }
 
-    fn load_mut_raw(&mut self, data: ExternData) -> Result<&mut [u8]> {
+    /// # Safety
+    ///
+    /// See [`Memory::load`].
+    unsafe fn load_mut_raw(&mut self, data: ExternData) -> Result<&mut [u8]> {
         self.host_check(data)
             // safety: checking is already done by `host_check`
-            .map(|()| unsafe {
+            .map(|()| {
                 let ptr = self.host_ptr_mut_unchecked(data.ptr);
                 ::core::slice::from_raw_parts_mut(ptr, data.len as usize)
             })
     }
 
-    async fn dump(&mut self, data: &[u8]) -> Result<ExternData>;
+    fn read_value<T>(&self, ptr: ExternDataRef) -> Result<T>
+    where
+        T: Copy,
+    {
+        self.host_ptr(ptr)
+            // safety: checking is already done by `host_ptr`
+            .map(|ptr| unsafe { ptr.read_unaligned() })
+    }
+
+    fn read(&self, data: ExternDataRef) -> Result<Vec<u8>> {
+        let data = self.read_value(data)?;
+        self.read_raw(data)
+    }
+
+    fn read_raw(&self, data: ExternData) -> Result<Vec<u8>> {
+        // safety: the view is copied at once
+        unsafe { self.load_raw(data) }.map(<[u8]>::to_vec)
+    }
+
+    // note: the guest data may be misaligned for the archives
+    fn read_aligned(&self, data: ExternDataRef) -> Result<AlignedVec> {
+        let data = self.read_value(data)?;
+        self.read_aligned_raw(data)
+    }
+
+    fn read_aligned_raw(&self, data: ExternData) -> Result<AlignedVec> {
+        // safety: the view is copied at once
+        let data = unsafe { self.load_raw(data)? };
+
+        let mut buf = AlignedVec::with_capacity(data.len());
+        buf.extend_from_slice(data);
+        Ok(buf)
+    }
+
+    fn load_object<T>(&self, data: ExternDataRef) -> Result<T>
+    where
+        T: Archive,
+        <T as Archive>::Archived:
+            for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<T, SharedDeserializeMap>,
+    {
+        let data = self.read_value(data)?;
+        self.load_object_raw(data)
+    }
+
+    fn load_object_raw<T>(&self, data: ExternData) -> Result<T>
+    where
+        T: Archive,
+        <T as Archive>::Archived:
+            for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<T, SharedDeserializeMap>,
+    {
+        PinnedInner::deserialize_owned(self.read_aligned_raw(data)?)
+    }
+
+    fn write_value<T>(&mut self, ptr: ExternDataRef, value: T) -> Result<()>
+    where
+        T: Copy,
+    {
+        self.host_ptr_mut(ptr)
+            // safety: checking is already done by `host_ptr_mut`
+            .map(|ptr| unsafe { ptr.write_unaligned(value) })
+    }
+
+    fn write_raw(&mut self, dst: ExternData, src: &[u8]) -> Result<()> {
+        if src.len() > dst.len as usize {
+            bail!(ExternError::invalid_input(format!(
+                "the data overflows the buffer: {} > {}",
+                src.len(),
+                dst.len,
+            )));
+        }
+
+        let dst = ExternData {
+            ptr: dst.ptr,
+            len: src.len().try_into()?,
+        };
+        // safety: the view is written at once
+        unsafe { self.load_mut_raw(dst)? }.copy_from_slice(src);
+        Ok(())
+    }
+
+    // note: the guest should release the data with the same alignment
+    async fn dump(&mut self, data: &[u8]) -> Result<ExternData> {
+        self.dump_aligned(data, EXTERN_DATA_ALIGN).await
+    }
+
+    async fn dump_aligned(&mut self, data: &[u8], align: ExternDataRef) -> Result<ExternData>;
+
+    async unsafe fn free(&mut self, data: ExternData) -> Result<()> {
+        self.free_aligned(data, EXTERN_DATA_ALIGN).await
+    }
+
+    async unsafe fn free_aligned(&mut self, data: ExternData, align: ExternDataRef) -> Result<()>;
+
+    async fn dump_object<T>(&mut self, data: &T) -> Result<ExternData>
+    where
+        T: Archive + Serialize<Serializer> + IsSigned + Clone + Send + Sync,
+        <T as Archive>::Archived:
+            for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<T, SharedDeserializeMap>,
+    {
+        self.dump(&data.to_bytes()?).await
+    }
 
     async fn dump_doubled(&mut self, data: &[u8]) -> Result<ExternData> {
         let data = self.dump(data).await?.as_bytes();
@@ -105,13 +224,16 @@ pub trait Memory: Send + Sync {
         <T as Archive>::Archived:
             for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<T, SharedDeserializeMap>,
     {
-        self.dump_doubled(&data.to_bytes()?).await
+        let data = self.dump_object(data).await?.as_bytes();
+        self.dump(&data).await
     }
 
-    async fn dump_to(&mut self, src: &[u8], dst: ExternDataRef) -> Result<()> {
-        // safety: mutability makes this block thread-safe
-        let dst = unsafe { ::core::mem::transmute(self.host_ref_mut::<ExternData>(dst)?) };
-        self.dump_to_raw(src, dst).await
+    async fn dump_to(&mut self, src: &[u8], dst: ExternDataRef) -> Result<ExternData> {
+        // note: the destination is checked first, but written only after dumping,
+        //       as allocating may grow the memory and invalidate any view
+        self.read_value::<ExternData>(dst)?;
+        let data = self.dump(src).await?;
+        self.write_value(dst, data).map(|()| data)
     }
 
     async fn dump_to_raw(&mut self, src: &[u8], dst: &mut ExternData) -> Result<()> {
@@ -123,18 +245,33 @@ pub trait Memory: Send + Sync {
         &mut self,
         err: ::ipis::core::anyhow::Error,
         dst: ExternDataRef,
-    ) -> Result<()> {
-        self.dump_to(&err.to_string().into_bytes(), dst).await
+    ) -> Result<ExternData> {
+        let err: ExternError = err.into();
+        self.dump_to(&err.to_bytes()?, dst).await
     }
 
     fn set_len(&mut self, len: ExternDataRef, dst: ExternDataRef) -> Result<()> {
-        // safety: mutability makes this block thread-safe
-        let dst = unsafe { ::core::mem::transmute(self.host_ref_mut::<ExternData>(dst)?) };
-        self.set_len_raw(len, dst);
-        Ok(())
+        let mut data: ExternData = self.read_value(dst)?;
+        self.set_len_raw(len, &mut data);
+        self.write_value(dst, data)
     }
 
     fn set_len_raw(&mut self, len: ExternDataRef, dst: &mut ExternData) {
         dst.len = len
     }
 }
+
+/// Borrows a guest memory for the lifetime `'a`.
+pub trait BorrowMemory<'a> {
+    type Memory: Memory;
+}
+
+/// The memories, which can be borrowed for any lifetime.
+///
+/// The interrupt handlers outlive a syscall,
+/// but the guest memory is borrowed only while the syscall is handled.
+pub trait MemoryFamily: for<'a> BorrowMemory<'a> + 'static {}
+
+impl<F> MemoryFamily for F where F: for<'a> BorrowMemory<'a> + 'static {}
+
+pub type BorrowedMemory<'a, F> = <F as BorrowMemory<'a>>::Memory;
@@ -1,17 +1,44 @@
-use std::{collections::HashMap, sync::atomic::{AtomicU32, Ordering}};
+use std::{collections::HashMap, sync::atomic::{AtomicU32, Ordering}, time::Duration};
 
 use bytecheck::CheckBytes;
 use ipis::{
     async_trait::async_trait,
-    core::{anyhow::Result, signed::IsSigned, value::chrono::DateTime},
+    core::{account::AccountRef, anyhow::Result, signed::IsSigned, value::chrono::DateTime},
 };
 use rkyv::{Archive, Deserialize, Serialize};
 
-use crate::{data::ExternDataRef, task::TaskConstraints};
+use crate::{data::ExternDataRef, error::ExternError, task::TaskConstraints};
 
 #[async_trait]
 pub trait ResourceManager {
-    async fn alloc(&self, constraints: &TaskConstraints) -> Result<Option<ResourceId>>;
+    /// Reserves the resources for the task of the guarantee,
+    /// or returns the reason why they cannot fit.
+    async fn alloc(
+        &self,
+        guarantee: &AccountRef,
+        constraints: &TaskConstraints,
+    ) -> Result<ResourceAlloc>;
+
+    /// Releases the resources, which are reserved for the finished task.
+    fn release(&self, id: ResourceId) {
+        let _ = id;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResourceAlloc {
+    Ready(ResourceId),
+    /// The resources cannot fit for now, with the reason.
+    Rejected(String),
+}
+
+impl ResourceAlloc {
+    pub fn ok(self) -> Option<ResourceId> {
+        match self {
+            Self::Ready(id) => Some(id),
+            Self::Rejected(_) => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
@@ -19,40 +46,230 @@ pub trait ResourceManager {
 #[archive_attr(derive(CheckBytes, Debug, PartialEq))]
 pub struct ResourceConstraints {
     pub due_date: DateTime,
+    /// The maximum size of the task's key-value namespace, in bytes.
+    pub kv_quota: Option<u64>,
+    /// The required CPU time, in millicores.
+    pub cpu_millicores: Option<u64>,
+    /// The required size of the memory, in bytes.
+    pub memory_bytes: Option<u64>,
+    /// The required size of the storage, in bytes.
+    pub storage_bytes: Option<u64>,
+    /// The maximum fuel, which the task can consume.
+    pub fuel: Option<u64>,
+    /// The maximum numbers of the open handles, for each kind of the resources.
+    pub handle_limits: Vec<HandleLimit>,
 }
 
 impl ResourceConstraints {
     pub const UNLIMITED: Self = ResourceConstraints {
         due_date: DateTime::MAX_DATETIME,
+        kv_quota: None,
+        cpu_millicores: None,
+        memory_bytes: None,
+        storage_bytes: None,
+        fuel: None,
+        handle_limits: Vec::new(),
     };
+
+    /// Returns the maximum number of the open handles of the kind, or `None` if unlimited.
+    pub fn handle_limit(&self, kind: &str) -> Option<u32> {
+        self.handle_limits
+            .iter()
+            .find(|limit| limit.kind == kind)
+            .map(|limit| limit.max)
+    }
+
+    /// Returns the remaining time until the due date, or `None` if unlimited.
+    pub fn remaining(&self) -> Option<Duration> {
+        if self.due_date == Self::UNLIMITED.due_date {
+            return None;
+        }
+
+        let millis = self
+            .due_date
+            .timestamp_millis()
+            .saturating_sub(DateTime::now().timestamp_millis());
+        Some(Duration::from_millis(millis.max(0) as u64))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive(compare(PartialEq))]
+#[archive_attr(derive(CheckBytes, Debug, PartialEq))]
+pub struct HandleLimit {
+    /// The kind of the resources, e.g. `"streams"`.
+    pub kind: String,
+    pub max: u32,
 }
 
+impl HandleLimit {
+    pub fn new(kind: impl ToString, max: u32) -> Self {
+        Self {
+            kind: kind.to_string(),
+            max,
+        }
+    }
+}
+
+/// The number of the lower bits of `ResourceId`, which are used for the slot index.
+const RESOURCE_INDEX_BITS: u32 = 20;
+const RESOURCE_INDEX_MASK: ExternDataRef = (1 << RESOURCE_INDEX_BITS) - 1;
+const RESOURCE_GENERATION_MAX: ExternDataRef = ExternDataRef::MAX >> RESOURCE_INDEX_BITS;
 
+/// A store of the resources, which hands out generational handles.
+///
+/// The slot of a removed resource is reused with the next generation,
+/// so that a stale handle never reaches the new resource.
 #[derive(Debug)]
 pub struct ResourceStore<R> {
+    kind: &'static str,
+    limit: Option<u32>,
     seed: ResourceIdSeed,
-    pub map: HashMap<ResourceId, R>,
+    free: Vec<ResourceId>,
+    map: HashMap<ResourceId, R>,
 }
 
 impl<R> ResourceStore<R> {
+    pub fn new(kind: &'static str) -> Self {
+        Self::with_limit(kind, None)
+    }
+
+    pub fn with_limit(kind: &'static str, limit: Option<u32>) -> Self {
+        Self {
+            kind,
+            limit,
+            seed: Default::default(),
+            free: Default::default(),
+            map: Default::default(),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    pub fn limit(&self) -> Option<u32> {
+        self.limit
+    }
+
+    /// Sets the maximum number of the open handles.
+    ///
+    /// The handles, which are already open, are kept even if they are beyond the limit.
+    pub fn set_limit(&mut self, limit: Option<u32>) {
+        self.limit = limit;
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
     pub fn insert<F>(&mut self, f: F) -> Result<ResourceId>
     where
         F: FnOnce(ResourceId) -> Result<R>,
     {
-        let id = self.seed.generate();
+        if let Some(limit) = self.limit {
+            if self.map.len() >= limit as usize {
+                return Err(ExternError::limit_exceeded(format!(
+                    "too many open handles of {}: > {limit}",
+                    self.kind,
+                ))
+                .into());
+            }
+        }
+
+        let id = match self.free.pop() {
+            Some(id) => id,
+            None => self.seed.generate()?,
+        };
+
+        match f(id) {
+            Ok(resource) => {
+                self.map.insert(id, resource);
+                Ok(id)
+            }
+            Err(e) => {
+                // note: the handle has never been exposed, so it can be reused as-is
+                self.free.push(id);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn get(&self, id: &ResourceId) -> Result<&R> {
+        self.map.get(id).ok_or_else(|| self.not_found(id))
+    }
+
+    pub fn get_mut(&mut self, id: &ResourceId) -> Result<&mut R> {
+        match self.map.get_mut(id) {
+            Some(resource) => Ok(resource),
+            None => Err(self.not_found(id)),
+        }
+    }
+
+    /// Removes the resource, and then releases its handle for the next generation.
+    pub fn remove(&mut self, id: &ResourceId) -> Result<R> {
+        let resource = self.map.remove(id).ok_or_else(|| self.not_found(id))?;
+        self.release(id);
+        Ok(resource)
+    }
 
-        self.map.insert(id, f(id)?);
+    /// Removes all of the resources, and then releases their handles.
+    pub fn clear(&mut self) {
+        self.drain();
+    }
+
+    /// Removes all of the resources, and then returns them with their handles released.
+    pub fn drain(&mut self) -> Vec<R> {
+        let resources: Vec<_> = self.map.drain().collect();
+        resources
+            .into_iter()
+            .map(|(id, resource)| {
+                self.release(&id);
+                resource
+            })
+            .collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ResourceId, &R)> {
+        self.map.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&ResourceId, &mut R)> {
+        self.map.iter_mut()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &R> {
+        self.map.values()
+    }
 
-        Ok(id)
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut R> {
+        self.map.values_mut()
+    }
+
+    fn release(&mut self, id: &ResourceId) {
+        // note: the slot is retired when its generations are exhausted
+        if let Some(id) = id.next_generation() {
+            self.free.push(id);
+        }
+    }
+
+    fn not_found(&self, id: &ResourceId) -> ::ipis::core::anyhow::Error {
+        let message = if self.seed.is_issued(id) {
+            format!("stale or closed handle of {}: {id:x}", self.kind)
+        } else {
+            format!("failed to find the handle of {}: {id:x}", self.kind)
+        };
+        ExternError::not_found(message).into()
     }
 }
 
 impl<R> Default for ResourceStore<R> {
     fn default() -> Self {
-        Self {
-            seed: Default::default(),
-            map: Default::default(),
-        }
+        Self::new("resources")
     }
 }
 
@@ -66,8 +283,18 @@ impl Default for ResourceIdSeed {
 }
 
 impl ResourceIdSeed {
-    pub fn generate(&self) -> ResourceId {
-        ResourceId(self.0.fetch_add(1, Ordering::SeqCst))
+    pub fn generate(&self) -> Result<ResourceId> {
+        let index = self.0.fetch_add(1, Ordering::SeqCst);
+        if index > RESOURCE_INDEX_MASK {
+            // note: keep the seed saturated, so that it never wraps around
+            self.0.store(RESOURCE_INDEX_MASK + 1, Ordering::SeqCst);
+            return Err(ExternError::limit_exceeded("the resource handles are exhausted").into());
+        }
+        Ok(ResourceId(index))
+    }
+
+    fn is_issued(&self, id: &ResourceId) -> bool {
+        id.index() != 0 && id.index() < self.0.load(Ordering::SeqCst)
     }
 }
 
@@ -81,8 +308,100 @@ pub struct ResourceId(pub ExternDataRef);
 
 impl IsSigned for ResourceId {}
 
+impl ResourceId {
+    /// Returns the index of the slot, which is shared by the generations.
+    pub const fn index(&self) -> ExternDataRef {
+        self.0 & RESOURCE_INDEX_MASK
+    }
+
+    /// Returns the generation of the slot, which is increased whenever the slot is reused.
+    pub const fn generation(&self) -> ExternDataRef {
+        self.0 >> RESOURCE_INDEX_BITS
+    }
+
+    fn next_generation(&self) -> Option<Self> {
+        match self.generation() {
+            RESOURCE_GENERATION_MAX => None,
+            generation => Some(Self(
+                ((generation + 1) << RESOURCE_INDEX_BITS) | self.index(),
+            )),
+        }
+    }
+}
+
 impl ::core::fmt::LowerHex for ResourceId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         ::core::fmt::LowerHex::fmt(&self.0, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{ErrorCode, ExternError};
+
+    use super::{ResourceStore, RESOURCE_GENERATION_MAX};
+
+    #[test]
+    fn test_stale_id() {
+        let mut store = ResourceStore::new("tests");
+        let stale = store.insert(|_| Ok("hello")).unwrap();
+        assert_eq!(store.remove(&stale).unwrap(), "hello");
+
+        // test the slot is reused with the next generation
+        let id = store.insert(|_| Ok("world")).unwrap();
+        assert_eq!(id.index(), stale.index());
+        assert_eq!(id.generation(), stale.generation() + 1);
+
+        // test the stale id never reaches the new resource
+        let error = store
+            .get(&stale)
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::NotFound);
+        assert!(store.remove(&stale).is_err());
+        assert_eq!(*store.get(&id).unwrap(), "world");
+    }
+
+    #[test]
+    fn test_generation_exhausted() {
+        let mut store = ResourceStore::new("tests");
+        let first = store.insert(|_| Ok(())).unwrap();
+
+        let mut id = first;
+        for generation in 1..=RESOURCE_GENERATION_MAX {
+            store.remove(&id).unwrap();
+            id = store.insert(|_| Ok(())).unwrap();
+            assert_eq!(id.index(), first.index());
+            assert_eq!(id.generation(), generation);
+        }
+
+        // test the exhausted slot is retired
+        store.remove(&id).unwrap();
+        let id = store.insert(|_| Ok(())).unwrap();
+        assert_ne!(id.index(), first.index());
+        assert_eq!(id.generation(), 0);
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut store = ResourceStore::with_limit("tests", Some(1));
+        let id = store.insert(|_| Ok(())).unwrap();
+
+        let error = store
+            .insert(|_| Ok(()))
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::LimitExceeded);
+
+        // test the slot is freed by removing the resource
+        store.remove(&id).unwrap();
+        store.insert(|_| Ok(())).unwrap();
+
+        // test the open handles are kept beyond the lowered limit
+        store.set_limit(Some(0));
+        assert_eq!(store.len(), 1);
+        assert!(store.insert(|_| Ok(())).is_err());
+    }
+}
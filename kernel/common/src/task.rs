@@ -22,6 +22,7 @@ use rkyv::{Archive, Deserialize, Serialize};
 
 use crate::{
     data::{ExternData, ExternDataRef},
+    interrupt::InterruptExtensions,
     protection::ProtectionMode,
     resource::{ResourceConstraints, ResourceId},
 };
@@ -42,6 +43,8 @@ impl<R> Future for Entry<R> {
 pub struct Task<R> {
     pub ctx: Arc<GuarantorSigned<TaskCtx>>,
     pub state: Arc<Mutex<TaskState>>,
+    /// The per-task states of the interrupt handlers, which the host may reach while running.
+    pub extensions: InterruptExtensions,
     pub handler: tokio::task::JoinHandle<R>,
 }
 
@@ -160,10 +163,41 @@ pub enum TaskPoll {
     Pending,
     Ready(Box<ObjectData>),
     Trap(Text),
+    /// The task is waiting for an execution slot.
+    Queued(TaskQueueState),
 }
 
 impl IsSigned for TaskPoll {}
 
+/// The poll of the task, which is reported to the client.
+#[derive(Clone, Debug, PartialEq, Archive, Serialize, Deserialize)]
+#[archive(compare(PartialEq))]
+#[archive_attr(derive(CheckBytes, Debug, PartialEq))]
+pub struct TaskReport {
+    pub poll: TaskPoll,
+    /// The bytes, which the finished task has not released in its memory.
+    pub leaked_bytes: Option<u64>,
+}
+
+impl IsSigned for TaskReport {}
+
+/// The queue state of the task's guarantee account.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive(compare(PartialEq))]
+#[archive_attr(derive(CheckBytes, Debug, PartialEq))]
+pub struct TaskQueueState {
+    /// The number of the account's tasks, which are queued ahead of the task.
+    pub position: u32,
+    /// The number of the account's queued tasks.
+    pub queued: u32,
+    /// The number of the account's running tasks.
+    pub running: u32,
+    /// The weight of the account's share of the execution slots.
+    pub weight: u32,
+}
+
+impl IsSigned for TaskQueueState {}
+
 #[derive(
     Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Archive, Serialize, Deserialize,
 )]
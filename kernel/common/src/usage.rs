@@ -0,0 +1,87 @@
+use bytecheck::CheckBytes;
+use ipis::core::{account::AccountRef, signed::IsSigned, value::chrono::DateTime};
+use rkyv::{Archive, Deserialize, Serialize};
+
+use crate::{resource::ResourceId, task::TaskId};
+
+/// The resources, which a finished task has consumed.
+#[derive(Clone, Debug, PartialEq, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+pub struct UsageRecord {
+    pub task_id: TaskId,
+    pub resource_id: ResourceId,
+    /// The account, which has requested the task and is billed for it.
+    pub guarantee: AccountRef,
+    /// The account, which has signed the task as a guarantor.
+    pub guarantor: AccountRef,
+    pub completed_date: DateTime,
+    pub wall_time_ms: u64,
+    pub fuel: u64,
+    pub peak_memory_bytes: u64,
+    pub syscalls: Vec<SyscallCount>,
+    pub stream_bytes_read: u64,
+    pub stream_bytes_written: u64,
+}
+
+impl IsSigned for UsageRecord {}
+
+/// The number of the syscalls, which are sent to an interrupt handler.
+#[derive(Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes, Debug, PartialEq, Eq))]
+pub struct SyscallCount {
+    pub id: String,
+    pub count: u64,
+}
+
+impl IsSigned for SyscallCount {}
+
+/// The sum of the usage records of an account's tasks.
+#[derive(Clone, Debug, PartialEq, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+pub struct UsageSummary {
+    pub account: AccountRef,
+    pub tasks: u64,
+    pub wall_time_ms: u64,
+    pub fuel: u64,
+    /// The largest peak memory of the tasks, as they are not billed by the sum.
+    pub peak_memory_bytes: u64,
+    pub syscalls: Vec<SyscallCount>,
+    pub stream_bytes_read: u64,
+    pub stream_bytes_written: u64,
+}
+
+impl IsSigned for UsageSummary {}
+
+impl UsageSummary {
+    pub fn new(account: AccountRef) -> Self {
+        Self {
+            account,
+            tasks: 0,
+            wall_time_ms: 0,
+            fuel: 0,
+            peak_memory_bytes: 0,
+            syscalls: Default::default(),
+            stream_bytes_read: 0,
+            stream_bytes_written: 0,
+        }
+    }
+
+    pub fn add(&mut self, record: &UsageRecord) {
+        self.tasks += 1;
+        self.wall_time_ms = self.wall_time_ms.saturating_add(record.wall_time_ms);
+        self.fuel = self.fuel.saturating_add(record.fuel);
+        self.peak_memory_bytes = self.peak_memory_bytes.max(record.peak_memory_bytes);
+        for syscall in &record.syscalls {
+            match self.syscalls.iter_mut().find(|sum| sum.id == syscall.id) {
+                Some(sum) => sum.count = sum.count.saturating_add(syscall.count),
+                None => self.syscalls.push(syscall.clone()),
+            }
+        }
+        self.stream_bytes_read = self
+            .stream_bytes_read
+            .saturating_add(record.stream_bytes_read);
+        self.stream_bytes_written = self
+            .stream_bytes_written
+            .saturating_add(record.stream_bytes_written);
+    }
+}
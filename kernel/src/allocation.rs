@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+use ipis::core::anyhow::{bail, Result};
+use ipwis_kernel_common::{
+    data::{ExternData, ExternDataRef},
+    error::ExternError,
+};
+
+/// The live data, which are allocated by the host in a guest memory.
+#[derive(Debug, Default)]
+pub struct AllocationLedger {
+    map: BTreeMap<ExternDataRef, Allocation>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Allocation {
+    pub len: ExternDataRef,
+    pub owner: AllocationOwner,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AllocationOwner {
+    /// Released by the host when the task is finished.
+    Host,
+    /// Released by the guest via `__ipwis_syscall_release`.
+    Guest,
+}
+
+impl AllocationLedger {
+    pub fn insert(&mut self, data: ExternData, owner: AllocationOwner) {
+        // note: zero-sized data is never allocated
+        if data.len > 0 {
+            self.map.insert(
+                data.ptr,
+                Allocation {
+                    len: data.len,
+                    owner,
+                },
+            );
+        }
+    }
+
+    pub fn release(&mut self, data: ExternData) -> Result<()> {
+        match self.map.get(&data.ptr) {
+            Some(allocation) if allocation.owner != AllocationOwner::Guest => {
+                bail!(ExternError::permission_denied(format!(
+                    "the data is owned by the host: {:#x}",
+                    data.ptr,
+                )))
+            }
+            Some(allocation) if allocation.len != data.len => {
+                bail!(ExternError::invalid_input(format!(
+                    "mismatched data length: {:#x}: {} != {}",
+                    data.ptr, allocation.len, data.len,
+                )))
+            }
+            Some(_) => {
+                self.map.remove(&data.ptr);
+                Ok(())
+            }
+            None => bail!(ExternError::not_found(format!(
+                "failed to find the data: {:#x}",
+                data.ptr,
+            ))),
+        }
+    }
+
+    pub fn drain_host(&mut self) -> Vec<ExternData> {
+        let host: Vec<_> = self
+            .map
+            .iter()
+            .filter(|(_, allocation)| allocation.owner == AllocationOwner::Host)
+            .map(|(&ptr, allocation)| ExternData {
+                ptr,
+                len: allocation.len,
+            })
+            .collect();
+
+        for data in &host {
+            self.map.remove(&data.ptr);
+        }
+        host
+    }
+
+    pub fn live_bytes(&self) -> u64 {
+        self.map
+            .values()
+            .map(|allocation| allocation.len as u64)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ipwis_kernel_common::{
+        data::ExternData,
+        error::{ErrorCode, ExternError},
+    };
+
+    use super::{AllocationLedger, AllocationOwner};
+
+    #[test]
+    fn test_leaked() {
+        let mut ledger = AllocationLedger::default();
+
+        let host = ExternData { ptr: 16, len: 8 };
+        let guest = ExternData { ptr: 32, len: 24 };
+        ledger.insert(host, AllocationOwner::Host);
+        ledger.insert(guest, AllocationOwner::Guest);
+
+        // test the host-owned data cannot be released by the guest
+        let error = ledger
+            .release(host)
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::PermissionDenied);
+
+        // test only the data, which the guest has not released, are leaked
+        let drained = ledger.drain_host();
+        assert_eq!(drained.len(), 1);
+        assert_eq!((drained[0].ptr, drained[0].len), (host.ptr, host.len));
+        assert_eq!(ledger.live_bytes(), 24);
+    }
+
+    #[test]
+    fn test_released() {
+        let mut ledger = AllocationLedger::default();
+
+        let guest = ExternData { ptr: 32, len: 24 };
+        ledger.insert(guest, AllocationOwner::Guest);
+
+        // test the length should match
+        let error = ledger
+            .release(ExternData { ptr: 32, len: 8 })
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidInput);
+
+        ledger.release(guest).unwrap();
+        assert!(ledger.drain_host().is_empty());
+        assert_eq!(ledger.live_bytes(), 0);
+
+        // test the data cannot be released twice
+        let error = ledger
+            .release(guest)
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::NotFound);
+    }
+}
@@ -0,0 +1,139 @@
+use ipis::{
+    core::anyhow::{bail, Result},
+    futures::{future::select_all, FutureExt},
+    tokio::{self, task::JoinHandle},
+};
+use ipwis_kernel_common::{
+    error::ExternError,
+    interrupt::{InterruptCompletion, InterruptSubmission},
+    resource::{ResourceId, ResourceStore},
+};
+
+pub struct CompletionQueue {
+    tickets: ResourceStore<Ticket>,
+}
+
+impl Default for CompletionQueue {
+    fn default() -> Self {
+        Self::with_limit(None)
+    }
+}
+
+enum Ticket {
+    Pending(JoinHandle<Result<InterruptCompletion>>),
+    Ready(Result<InterruptCompletion>),
+}
+
+impl CompletionQueue {
+    pub const KIND: &'static str = "interrupt tickets";
+
+    pub fn with_limit(limit: Option<u32>) -> Self {
+        Self {
+            tickets: ResourceStore::with_limit(Self::KIND, limit),
+        }
+    }
+
+    pub fn submit(&mut self, module: &str, submission: InterruptSubmission) -> Result<ResourceId> {
+        self.tickets.insert(|_| {
+            Ok(match submission {
+                InterruptSubmission::Ready(outputs) => Ticket::Ready(Ok(outputs.into())),
+                // note: the pending operations are driven concurrently, apart from the guest
+                InterruptSubmission::Pending(future) => {
+                    let module = module.to_string();
+                    Ticket::Pending(tokio::spawn(async move {
+                        future
+                            .await
+                            .map_err(|error| ExternError::from(error).with_module(&module).into())
+                    }))
+                }
+            })
+        })
+    }
+
+    pub fn poll(&mut self, id: ResourceId) -> Result<Option<Result<InterruptCompletion>>> {
+        match self.tickets.get_mut(&id)? {
+            Ticket::Ready(_) => self.take(id).map(Some),
+            Ticket::Pending(handler) => match handler.now_or_never() {
+                Some(result) => {
+                    self.tickets.remove(&id)?;
+                    Ok(Some(flatten(result)))
+                }
+                None => Ok(None),
+            },
+        }
+    }
+
+    pub async fn wait(&mut self, id: ResourceId) -> Result<Result<InterruptCompletion>> {
+        match self.tickets.get_mut(&id)? {
+            Ticket::Ready(_) => self.take(id),
+            Ticket::Pending(handler) => {
+                let result = handler.await;
+                self.tickets.remove(&id)?;
+                Ok(flatten(result))
+            }
+        }
+    }
+
+    pub async fn wait_any(&mut self) -> Result<ResourceId> {
+        // skip waiting if something is already completed
+        if let Some(id) = self
+            .tickets
+            .iter()
+            .find(|(_, ticket)| matches!(ticket, Ticket::Ready(_)))
+            .map(|(id, _)| *id)
+        {
+            return Ok(id);
+        }
+
+        let (ids, handlers): (Vec<_>, Vec<_>) = self
+            .tickets
+            .iter_mut()
+            .filter_map(|(id, ticket)| match ticket {
+                Ticket::Pending(handler) => Some((*id, handler)),
+                Ticket::Ready(_) => None,
+            })
+            .unzip();
+        if handlers.is_empty() {
+            bail!(ExternError::would_block("no pending interrupt tickets"));
+        }
+
+        let (result, index, _) = select_all(handlers).await;
+        let id = ids[index];
+
+        // keep the result until the ticket is polled
+        *self.tickets.get_mut(&id)? = Ticket::Ready(flatten(result));
+        Ok(id)
+    }
+
+    /// Drops the ticket, aborting its pending operation.
+    pub fn cancel(&mut self, id: ResourceId) {
+        if let Ok(Ticket::Pending(handler)) = self.tickets.remove(&id) {
+            handler.abort();
+        }
+    }
+
+    pub fn release(&mut self) {
+        for ticket in self.tickets.values() {
+            if let Ticket::Pending(handler) = ticket {
+                handler.abort();
+            }
+        }
+        self.tickets.clear();
+    }
+
+    fn take(&mut self, id: ResourceId) -> Result<Result<InterruptCompletion>> {
+        match self.tickets.get(&id)? {
+            Ticket::Ready(_) => match self.tickets.remove(&id)? {
+                Ticket::Ready(result) => Ok(result),
+                Ticket::Pending(_) => unreachable!(),
+            },
+            Ticket::Pending(_) => bail!("the interrupt ticket is still pending: {id:x}"),
+        }
+    }
+}
+
+fn flatten(
+    result: Result<Result<InterruptCompletion>, tokio::task::JoinError>,
+) -> Result<InterruptCompletion> {
+    result.map_err(Into::into).and_then(|result| result)
+}
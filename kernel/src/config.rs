@@ -0,0 +1,63 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use ipis::core::account::AccountRef;
+
+#[derive(Clone, Debug, Default)]
+pub struct KernelConfig {
+    pub retention: RetentionPolicy,
+    pub fair_share: FairShareConfig,
+    // the append-only file, which the usage records of the finished tasks are written to
+    pub ledger_path: Option<PathBuf>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    // a finished task's result is dropped after this duration unless acknowledged earlier
+    pub ttl: Duration,
+    // how often the reaper collects finished tasks and expired results
+    pub reap_interval: Duration,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(10 * 60),
+            reap_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FairShareConfig {
+    // the maximum number of the tasks, which are running at the same time
+    pub slots: usize,
+    // the weight of the accounts, which are not listed in `weights`
+    pub default_weight: u32,
+    // the share of the slots is proportional to the account's weight
+    pub weights: HashMap<AccountRef, u32>,
+}
+
+impl Default for FairShareConfig {
+    fn default() -> Self {
+        Self {
+            slots: 256,
+            default_weight: 1,
+            weights: Default::default(),
+        }
+    }
+}
+
+impl FairShareConfig {
+    pub fn slots(&self) -> usize {
+        // note: no tasks would be dispatched without a slot
+        self.slots.max(1)
+    }
+
+    pub fn weight(&self, account: &AccountRef) -> u32 {
+        self.weights
+            .get(account)
+            .copied()
+            .unwrap_or(self.default_weight)
+            .max(1)
+    }
+}
@@ -4,10 +4,16 @@ use ipis::core::account::GuarantorSigned;
 use ipis::{core::anyhow::Result, tokio::sync::Mutex};
 use ipwis_kernel_api::wasmtime::{Caller, Engine, Linker, Store};
 use ipwis_kernel_api::wasmtime_wasi::{WasiCtx, WasiCtxBuilder};
-use ipwis_kernel_common::task::{TaskCtx, TaskState};
+use ipwis_kernel_common::{
+    interrupt::{InterruptExtensions, InterruptTask, TaskParent},
+    task::{TaskCtx, TaskState},
+};
 
 use crate::{
+    allocation::AllocationLedger,
+    completion::CompletionQueue,
     interrupt::{InterruptHandlerStore, InterruptManager},
+    memory::PeakMemoryLimiter,
     task::{Task, TaskStore},
 };
 
@@ -21,6 +27,10 @@ pub struct IpwisCtx {
     pub state: Arc<Mutex<TaskState>>,
     pub store: TaskStore<Task>,
     pub interrupt_handlers: InterruptHandlerStore,
+    pub completions: CompletionQueue,
+    pub allocations: AllocationLedger,
+    pub diagnostics: Vec<String>,
+    pub limiter: PeakMemoryLimiter,
 }
 
 impl IpwisCtx {
@@ -29,7 +39,24 @@ impl IpwisCtx {
         ctx: Arc<GuarantorSigned<TaskCtx>>,
         state: TaskState,
         interrupt_manager: Arc<InterruptManager>,
+        extensions: InterruptExtensions,
+        parent: Option<TaskParent>,
     ) -> Result<Self> {
+        let task = InterruptTask {
+            id: state.task_id,
+            resource_id: state.resource_id,
+            ctx: ctx.clone(),
+            protection_mode: state.protection_mode,
+            extensions,
+            parent,
+        };
+
+        let completions = CompletionQueue::with_limit(
+            ctx.constraints
+                .resources
+                .handle_limit(CompletionQueue::KIND),
+        );
+
         Ok(Self {
             // create a WASI context and put it in a Store; all instances in the store
             // share this context. `WasiCtxBuilder` provides a number of ways to
@@ -41,13 +68,18 @@ impl IpwisCtx {
             task: ctx,
             state: Arc::new(Mutex::new(state)),
             store: TaskStore::try_new(engine, interrupt_manager.clone())?,
-            interrupt_handlers: InterruptHandlerStore::with_manager(interrupt_manager),
+            interrupt_handlers: InterruptHandlerStore::with_manager(interrupt_manager, task),
+            completions,
+            allocations: Default::default(),
+            diagnostics: Default::default(),
+            limiter: Default::default(),
         })
     }
 
     pub async fn release(&mut self) -> Result<()> {
-        // order: Task -> Interrupt Store
+        // order: Task -> Completion Queue -> Interrupt Store
         self.store.release().await;
+        self.completions.release();
         self.interrupt_handlers.release().await?;
         Ok(())
     }
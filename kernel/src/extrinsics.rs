@@ -1,13 +1,25 @@
-use ipis::{core::anyhow::Result, log::warn, rkyv::AlignedVec};
+use ipis::{
+    core::{anyhow::Result, signed::IsSigned},
+    log::warn,
+    rkyv::AlignedVec,
+};
 use ipwis_kernel_common::{
-    data::ExternDataRef,
-    extrinsics::{SYSCALL_ERR_FATAL, SYSCALL_ERR_NORMAL, SYSCALL_OK},
-    interrupt::InterruptId,
+    batch::{SyscallBatch, SyscallBatchOutput, SyscallBatchOutputs},
+    data::{ExternData, ExternDataRef},
+    error::ExternError,
+    extrinsics::{SYSCALL_ERR_FATAL, SYSCALL_ERR_NORMAL, SYSCALL_OK, SYSCALL_PENDING},
+    interrupt::{InterruptCompletion, InterruptSubmission},
     memory::Memory,
-    modules::{FUNC_NAME_SYSCALL, MODULE_NAME_COMMON},
+    modules::{
+        FUNC_NAME_SYSCALL, FUNC_NAME_SYSCALL_BATCH, FUNC_NAME_SYSCALL_POLL,
+        FUNC_NAME_SYSCALL_RELEASE, FUNC_NAME_SYSCALL_SUBMIT, FUNC_NAME_SYSCALL_WAIT,
+        FUNC_NAME_SYSCALL_WAIT_ANY, MODULE_NAME_COMMON,
+    },
+    resource::ResourceId,
 };
 
 use crate::{
+    allocation::AllocationOwner,
     ctx::{IpwisCaller, IpwisLinker},
     memory::IpwisMemory,
 };
@@ -20,6 +32,38 @@ pub fn register(linker: &mut IpwisLinker) -> Result<()> {
             Box::new(syscall(caller, handler, inputs, outputs, errors))
         },
     )?;
+    linker.func_wrap3_async(
+        MODULE_NAME_COMMON,
+        FUNC_NAME_SYSCALL_BATCH,
+        |caller, inputs, outputs, errors| Box::new(syscall_batch(caller, inputs, outputs, errors)),
+    )?;
+    linker.func_wrap4_async(
+        MODULE_NAME_COMMON,
+        FUNC_NAME_SYSCALL_SUBMIT,
+        |caller, handler, inputs, ticket, errors| {
+            Box::new(syscall_submit(caller, handler, inputs, ticket, errors))
+        },
+    )?;
+    linker.func_wrap3_async(
+        MODULE_NAME_COMMON,
+        FUNC_NAME_SYSCALL_POLL,
+        |caller, ticket, outputs, errors| Box::new(syscall_poll(caller, ticket, outputs, errors)),
+    )?;
+    linker.func_wrap3_async(
+        MODULE_NAME_COMMON,
+        FUNC_NAME_SYSCALL_WAIT,
+        |caller, ticket, outputs, errors| Box::new(syscall_wait(caller, ticket, outputs, errors)),
+    )?;
+    linker.func_wrap2_async(
+        MODULE_NAME_COMMON,
+        FUNC_NAME_SYSCALL_WAIT_ANY,
+        |caller, ticket, errors| Box::new(syscall_wait_any(caller, ticket, errors)),
+    )?;
+    linker.func_wrap2_async(
+        MODULE_NAME_COMMON,
+        FUNC_NAME_SYSCALL_RELEASE,
+        |caller, ptr, len| Box::new(syscall_release(caller, ptr, len)),
+    )?;
     Ok(())
 }
 
@@ -30,54 +74,287 @@ async fn syscall(
     outputs: ExternDataRef,
     errors: ExternDataRef,
 ) -> ExternDataRef {
-    let mut memory = unsafe {
-        // allow interior mutability
-        match IpwisMemory::with_caller(::core::mem::transmute::<_, &mut IpwisCaller>(&mut caller)) {
-            Ok(memory) => memory,
-            Err(error) => {
-                warn!("{}", error);
-                return SYSCALL_ERR_FATAL;
-            }
-        }
+    let request = match load_memory(&mut caller) {
+        Ok(memory) => load_request(&memory, handler, inputs),
+        Err(error) => return fatal(&mut caller, error),
     };
 
-    async unsafe fn try_handle<'a>(
+    unsafe {
+        let result = match request {
+            Ok((handler, inputs)) => handle_raw(&mut caller, &handler, &inputs)
+                .await
+                .map_err(|error| ExternError::from(error).with_module(&handler).into()),
+            Err(error) => Err(error),
+        };
+        complete(&mut caller, result.map(Into::into), outputs, errors).await
+    }
+}
+
+async fn syscall_batch(
+    mut caller: IpwisCaller<'_>,
+    inputs: ExternDataRef,
+    outputs: ExternDataRef,
+    errors: ExternDataRef,
+) -> ExternDataRef {
+    let batch = match load_memory(&mut caller) {
+        // note: the whole batch is validated only once
+        Ok(memory) => memory.load_object::<SyscallBatch>(inputs),
+        Err(error) => return fatal(&mut caller, error),
+    };
+
+    async unsafe fn try_handle_batch<'a>(
         caller: &mut IpwisCaller<'a>,
-        memory: &mut IpwisMemory<'static>,
-        handler: ExternDataRef,
-        inputs: ExternDataRef,
+        batch: SyscallBatch,
     ) -> Result<AlignedVec> {
-        let handler = {
-            let data = ::core::mem::transmute(memory.load(handler)?); // ignore `memory` lifetime
-            InterruptId(::core::str::from_utf8(data)?)
-        };
-        let inputs: &[u8] = {
-            ::core::mem::transmute(memory.load(inputs)?) // ignore `memory` lifetime
+        let mut results = Vec::with_capacity(batch.calls.len());
+        for call in &batch.calls {
+            // note: the calls are executed in order, regardless of the errors
+            // note: the inputs are copied in, as the archives should be aligned
+            let mut inputs = AlignedVec::with_capacity(call.inputs.len());
+            inputs.extend_from_slice(&call.inputs);
+            let result = handle_raw(caller, &call.handler, &inputs).await;
+
+            results.push(match result {
+                Ok(outputs) => SyscallBatchOutput::Ok(outputs.to_vec()),
+                Err(error) => {
+                    SyscallBatchOutput::Err(ExternError::from(error).with_module(&call.handler))
+                }
+            });
+        }
+
+        SyscallBatchOutputs { results }
+            .to_bytes()
+            .map_err(Into::into)
+    }
+
+    unsafe {
+        let result = match batch {
+            Ok(batch) => try_handle_batch(&mut caller, batch).await,
+            Err(error) => Err(error),
         };
+        complete(&mut caller, result.map(Into::into), outputs, errors).await
+    }
+}
+
+async fn syscall_submit(
+    mut caller: IpwisCaller<'_>,
+    handler: ExternDataRef,
+    inputs: ExternDataRef,
+    ticket: ExternDataRef,
+    errors: ExternDataRef,
+) -> ExternDataRef {
+    let request = match load_memory(&mut caller) {
+        Ok(memory) => load_request(&memory, handler, inputs),
+        Err(error) => return fatal(&mut caller, error),
+    };
+
+    async unsafe fn try_submit<'a>(
+        caller: &mut IpwisCaller<'a>,
+        request: Result<(String, AlignedVec)>,
+        ticket: ExternDataRef,
+    ) -> Result<()> {
+        let (handler, inputs) = request?;
 
-        caller
-            .data_mut()
-            .interrupt_handlers
-            .handle_raw(memory, handler, inputs)
+        let submission = submit_raw(caller, &handler, &inputs)
             .await
+            .map_err(|error| ExternError::from(error).with_module(&handler))?;
+        let id = caller.data_mut().completions.submit(&handler, submission)?;
+
+        let result = load_memory(caller).and_then(|mut memory| memory.write_value(ticket, id.0));
+        if result.is_err() {
+            // note: the ticket is unreachable from the guest, so it is dropped at once
+            caller.data_mut().completions.cancel(id);
+        }
+        result
     }
 
     unsafe {
-        match try_handle(&mut caller, &mut memory, handler, inputs).await {
-            Ok(buf) => match memory.dump_to(&buf, outputs).await {
-                Ok(()) => SYSCALL_OK,
-                Err(error) => {
-                    warn!("{}", error);
-                    SYSCALL_ERR_FATAL
-                }
-            },
-            Err(error) => match memory.dump_error_to(error, errors).await {
-                Ok(()) => SYSCALL_ERR_NORMAL,
-                Err(error) => {
-                    warn!("{}", error);
-                    SYSCALL_ERR_FATAL
-                }
-            },
+        match try_submit(&mut caller, request, ticket).await {
+            Ok(()) => SYSCALL_OK,
+            Err(error) => dump_error(&mut caller, error, errors).await,
+        }
+    }
+}
+
+async fn syscall_poll(
+    mut caller: IpwisCaller<'_>,
+    ticket: ExternDataRef,
+    outputs: ExternDataRef,
+    errors: ExternDataRef,
+) -> ExternDataRef {
+    unsafe {
+        match caller.data_mut().completions.poll(ResourceId(ticket)) {
+            Ok(Some(result)) => complete(&mut caller, result, outputs, errors).await,
+            Ok(None) => SYSCALL_PENDING,
+            Err(error) => dump_error(&mut caller, error, errors).await,
         }
     }
 }
+
+async fn syscall_wait(
+    mut caller: IpwisCaller<'_>,
+    ticket: ExternDataRef,
+    outputs: ExternDataRef,
+    errors: ExternDataRef,
+) -> ExternDataRef {
+    unsafe {
+        match caller.data_mut().completions.wait(ResourceId(ticket)).await {
+            Ok(result) => complete(&mut caller, result, outputs, errors).await,
+            Err(error) => dump_error(&mut caller, error, errors).await,
+        }
+    }
+}
+
+async fn syscall_wait_any(
+    mut caller: IpwisCaller<'_>,
+    ticket: ExternDataRef,
+    errors: ExternDataRef,
+) -> ExternDataRef {
+    async fn try_wait_any<'a>(caller: &mut IpwisCaller<'a>, ticket: ExternDataRef) -> Result<()> {
+        let id = caller.data_mut().completions.wait_any().await?;
+
+        load_memory(caller)?.write_value(ticket, id.0)
+    }
+
+    match try_wait_any(&mut caller, ticket).await {
+        Ok(()) => SYSCALL_OK,
+        Err(error) => unsafe { dump_error(&mut caller, error, errors) }.await,
+    }
+}
+
+async fn syscall_release(
+    mut caller: IpwisCaller<'_>,
+    ptr: ExternDataRef,
+    len: ExternDataRef,
+) -> ExternDataRef {
+    // note: only the data given by the host can be released
+    let data = ExternData { ptr, len };
+    if let Err(error) = caller.data_mut().allocations.release(data) {
+        return fatal(&mut caller, format!("failed to release the data: {error}"));
+    }
+
+    let result = match load_memory(&mut caller) {
+        Ok(mut memory) => unsafe { memory.free(data) }.await,
+        Err(error) => Err(error),
+    };
+    match result {
+        Ok(()) => SYSCALL_OK,
+        Err(error) => fatal(&mut caller, format!("failed to release the data: {error}")),
+    }
+}
+
+// note: the memory borrows the caller, so it should be dropped before the context is used
+fn load_memory<'c>(caller: &'c mut IpwisCaller<'_>) -> Result<IpwisMemory<'c>> {
+    IpwisMemory::borrow_caller(caller)
+}
+
+// note: the request is copied in, as any view is invalidated when the memory grows
+fn load_request(
+    memory: &IpwisMemory<'_>,
+    handler: ExternDataRef,
+    inputs: ExternDataRef,
+) -> Result<(String, AlignedVec)> {
+    let handler = String::from_utf8(memory.read(handler)?)?;
+    let inputs = memory.read_aligned(inputs)?;
+    Ok((handler, inputs))
+}
+
+// note: the handler is taken out of the context, so that the memory can borrow the caller
+async unsafe fn handle_raw(
+    caller: &mut IpwisCaller<'_>,
+    handler: &str,
+    inputs: &[u8],
+) -> Result<AlignedVec> {
+    let mut entry = caller.data_mut().interrupt_handlers.take(handler).await?;
+    let result = match load_memory(caller) {
+        Ok(mut memory) => entry.handle_raw(&mut memory, handler, inputs).await,
+        Err(error) => Err(error),
+    };
+    caller.data_mut().interrupt_handlers.restore(entry);
+    result
+}
+
+async unsafe fn submit_raw(
+    caller: &mut IpwisCaller<'_>,
+    handler: &str,
+    inputs: &[u8],
+) -> Result<InterruptSubmission> {
+    let mut entry = caller.data_mut().interrupt_handlers.take(handler).await?;
+    let result = match load_memory(caller) {
+        Ok(mut memory) => entry.submit_raw(&mut memory, handler, inputs).await,
+        Err(error) => Err(error),
+    };
+    caller.data_mut().interrupt_handlers.restore(entry);
+    result
+}
+
+async unsafe fn complete(
+    caller: &mut IpwisCaller<'_>,
+    result: Result<InterruptCompletion>,
+    outputs: ExternDataRef,
+    errors: ExternDataRef,
+) -> ExternDataRef {
+    let mut memory = match load_memory(caller) {
+        Ok(memory) => memory,
+        Err(error) => return fatal(caller, error),
+    };
+
+    let result = result.and_then(|completion| {
+        if let Some((dst, data)) = completion.copy_to {
+            memory.write_raw(dst, &data)?;
+        }
+        Ok(completion.outputs)
+    });
+
+    match result {
+        Ok(buf) => {
+            let dumped = memory.dump_to(&buf, outputs).await;
+            drop(memory);
+            give(caller, dumped, SYSCALL_OK, "outputs")
+        }
+        Err(error) => {
+            let dumped = memory.dump_error_to(error, errors).await;
+            drop(memory);
+            give(caller, dumped, SYSCALL_ERR_NORMAL, "errors")
+        }
+    }
+}
+
+async unsafe fn dump_error(
+    caller: &mut IpwisCaller<'_>,
+    error: ::ipis::core::anyhow::Error,
+    errors: ExternDataRef,
+) -> ExternDataRef {
+    let dumped = match load_memory(caller) {
+        Ok(mut memory) => memory.dump_error_to(error, errors).await,
+        Err(error) => return fatal(caller, error),
+    };
+    give(caller, dumped, SYSCALL_ERR_NORMAL, "errors")
+}
+
+fn give(
+    caller: &mut IpwisCaller<'_>,
+    dumped: Result<ExternData>,
+    status: ExternDataRef,
+    name: &str,
+) -> ExternDataRef {
+    match dumped {
+        Ok(data) => {
+            // note: the ownership is transferred to the guest
+            caller
+                .data_mut()
+                .allocations
+                .insert(data, AllocationOwner::Guest);
+            status
+        }
+        Err(error) => fatal(caller, format!("failed to dump the {name}: {error}")),
+    }
+}
+
+fn fatal(caller: &mut IpwisCaller<'_>, error: impl ::core::fmt::Display) -> ExternDataRef {
+    warn!("{}", error);
+
+    // keep the context to report it through the task's result
+    caller.data_mut().diagnostics.push(error.to_string());
+    SYSCALL_ERR_FATAL
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, BTreeMap, HashMap},
     sync::Arc,
 };
 
@@ -7,23 +7,26 @@ use ipis::{
     core::anyhow::{bail, Result},
     rkyv::AlignedVec,
 };
-use ipwis_kernel_common::interrupt::{
-    InterruptFallbackHandler, InterruptFallbackModule, InterruptHandler, InterruptId,
-    InterruptModule,
+use ipwis_kernel_common::{
+    error::ExternError,
+    interrupt::{
+        DynInterruptFallbackHandler, DynInterruptHandler, InterruptFallbackModule, InterruptId,
+        InterruptModule, InterruptSubmission, InterruptTask,
+    },
 };
 
-use crate::memory::IpwisMemory;
+use crate::memory::{IpwisMemory, IpwisMemoryFamily};
 
 #[derive(Default)]
 pub struct InterruptManager {
-    map: HashMap<InterruptId, Box<dyn InterruptModule<IpwisMemory<'static>>>>,
-    fallback: Option<Box<dyn InterruptFallbackModule<IpwisMemory<'static>>>>,
+    map: HashMap<InterruptId, Box<dyn InterruptModule<IpwisMemoryFamily>>>,
+    fallback: Option<Box<dyn InterruptFallbackModule<IpwisMemoryFamily>>>,
 }
 
 impl InterruptManager {
     pub fn insert<H>(&mut self, handler: H) -> Result<()>
     where
-        H: InterruptModule<IpwisMemory<'static>> + 'static,
+        H: InterruptModule<IpwisMemoryFamily> + 'static,
     {
         let id = handler.id();
 
@@ -38,87 +41,160 @@ impl InterruptManager {
 
     pub fn set_fallback<H>(&mut self, handler: H) -> Result<()>
     where
-        H: InterruptFallbackModule<IpwisMemory<'static>> + 'static,
+        H: InterruptFallbackModule<IpwisMemoryFamily> + 'static,
     {
         self.fallback = Some(Box::new(handler));
         Ok(())
     }
 
+    // note: the returned id outlives the given one
+    pub fn resolve(&self, id: &str) -> Option<InterruptId> {
+        self.map.get_key_value(id).map(|(id, _)| *id)
+    }
+
     pub async fn spawn_handler(
         &self,
         id: InterruptId,
-    ) -> Result<Option<Box<dyn InterruptHandler<IpwisMemory<'static>>>>> {
+        task: &InterruptTask,
+    ) -> Result<Option<Box<dyn DynInterruptHandler<IpwisMemoryFamily>>>> {
         match self.map.get(&id) {
-            Some(module) => module.spawn_handler().await.map(Some),
+            Some(module) => module.spawn_handler(task).await.map(Some),
             None => Ok(None),
         }
     }
 
     pub async fn spawn_fallback(
         &self,
-    ) -> Result<Option<Box<dyn InterruptFallbackHandler<IpwisMemory<'static>>>>> {
+        task: &InterruptTask,
+    ) -> Result<Option<Box<dyn DynInterruptFallbackHandler<IpwisMemoryFamily>>>> {
         match self.fallback.as_ref() {
-            Some(module) => module.spawn_fallback().await.map(Some),
+            Some(module) => module.spawn_fallback(task).await.map(Some),
             None => Ok(None),
         }
     }
 }
 
+/// The handler, which is taken out of the store while a syscall is handled.
+pub enum InterruptHandlerEntry {
+    Module(InterruptId, Box<dyn DynInterruptHandler<IpwisMemoryFamily>>),
+    Fallback(Box<dyn DynInterruptFallbackHandler<IpwisMemoryFamily>>),
+}
+
+impl InterruptHandlerEntry {
+    pub async unsafe fn handle_raw(
+        &mut self,
+        memory: &mut IpwisMemory<'_>,
+        id: &str,
+        inputs: &[u8],
+    ) -> Result<AlignedVec> {
+        match self {
+            Self::Module(_, handler) => handler.handle_raw(memory, inputs).await,
+            Self::Fallback(handler) => handler.handle_fallback(memory, id, inputs).await,
+        }
+    }
+
+    pub async unsafe fn submit_raw(
+        &mut self,
+        memory: &mut IpwisMemory<'_>,
+        id: &str,
+        inputs: &[u8],
+    ) -> Result<InterruptSubmission> {
+        match self {
+            Self::Module(_, handler) => handler.submit_raw(memory, inputs).await,
+            Self::Fallback(handler) => handler
+                .handle_fallback(memory, id, inputs)
+                .await
+                .map(InterruptSubmission::Ready),
+        }
+    }
+}
+
 pub struct InterruptHandlerStore {
     manager: Arc<InterruptManager>,
-    map: HashMap<InterruptId, Box<dyn InterruptHandler<IpwisMemory<'static>>>>,
-    fallback: Option<Box<dyn InterruptFallbackHandler<IpwisMemory<'static>>>>,
+    task: InterruptTask,
+    map: HashMap<InterruptId, Box<dyn DynInterruptHandler<IpwisMemoryFamily>>>,
+    fallback: Option<Box<dyn DynInterruptFallbackHandler<IpwisMemoryFamily>>>,
+    syscalls: BTreeMap<InterruptId, u64>,
 }
 
 impl InterruptHandlerStore {
-    pub fn with_manager(manager: Arc<InterruptManager>) -> Self {
+    /// The id, which the syscalls to the unregistered handlers are counted by.
+    pub const FALLBACK: InterruptId = InterruptId("fallback");
+
+    pub fn with_manager(manager: Arc<InterruptManager>, task: InterruptTask) -> Self {
         Self {
             manager,
+            task,
             map: Default::default(),
             fallback: Default::default(),
+            syscalls: Default::default(),
         }
     }
 
-    pub async unsafe fn handle_raw(
-        &mut self,
-        memory: &mut IpwisMemory<'static>,
-        id: InterruptId,
-        inputs: &[u8],
-    ) -> Result<AlignedVec> {
-        match self.map.get_mut(&id) {
-            Some(handler) => handler.handle_raw(memory, inputs).await,
-            None => match self.manager.spawn_handler(id).await? {
-                Some(handler) => {
-                    self.map.insert(id, handler);
-                    self.map
-                        .get_mut(&id)
-                        .unwrap()
-                        .handle_raw(memory, inputs)
-                        .await
-                }
-                None => match self.fallback.as_ref() {
-                    Some(handler) => handler.handle_fallback(memory, id, inputs).await,
-                    None => match self.manager.spawn_fallback().await? {
-                        Some(handle) => {
-                            self.fallback.replace(handle);
-                            self.fallback
-                                .as_ref()
-                                .unwrap()
-                                .handle_fallback(memory, id, inputs)
-                                .await
-                        }
-                        None => bail!("failed to find the interrupt handler: {id}"),
-                    },
-                },
-            },
+    /// Takes the handler out, so that the guest memory can be borrowed while it is handling.
+    pub async fn take(&mut self, id: &str) -> Result<InterruptHandlerEntry> {
+        self.count_syscall(id);
+
+        // use the module's own id as a key, as the given one may be borrowed from the guest
+        if let Some(id) = self.manager.resolve(id) {
+            let handler = match self.map.remove(&id) {
+                Some(handler) => Some(handler),
+                None => self.manager.spawn_handler(id, &self.task).await?,
+            };
+            if let Some(handler) = handler {
+                return Ok(InterruptHandlerEntry::Module(id, handler));
+            }
+        }
+
+        let handler = match self.fallback.take() {
+            Some(handler) => handler,
+            None => self
+                .manager
+                .spawn_fallback(&self.task)
+                .await?
+                .ok_or_else(|| {
+                    ExternError::unsupported(format!(
+                        "failed to find the interrupt handler: {id:?}"
+                    ))
+                })?,
+        };
+        Ok(InterruptHandlerEntry::Fallback(handler))
+    }
+
+    /// Puts back the handler, which has been taken out by [`take`](Self::take).
+    pub fn restore(&mut self, entry: InterruptHandlerEntry) {
+        // note: a nested syscall may have spawned another handler meanwhile, which is dropped
+        match entry {
+            InterruptHandlerEntry::Module(id, handler) => {
+                self.map.insert(id, handler);
+            }
+            InterruptHandlerEntry::Fallback(handler) => {
+                self.fallback.replace(handler);
+            }
         }
     }
 
+    fn count_syscall(&mut self, id: &str) {
+        // note: the guest-given ids are not kept, so that the map cannot grow unbounded
+        let id = self.manager.resolve(id).unwrap_or(Self::FALLBACK);
+        *self.syscalls.entry(id).or_default() += 1;
+    }
+
+    pub fn task(&self) -> &InterruptTask {
+        &self.task
+    }
+
+    /// Returns the number of the syscalls, which have been sent to each handler.
+    pub fn syscalls(&self) -> &BTreeMap<InterruptId, u64> {
+        &self.syscalls
+    }
+
+    /// Releases the handlers and drops them, so that their resources are not kept alive.
     pub async fn release(&mut self) -> Result<()> {
-        for handler in self.map.values_mut() {
+        for (_, mut handler) in ::core::mem::take(&mut self.map) {
             handler.release().await?;
         }
-        if let Some(handler) = self.fallback.as_mut() {
+        if let Some(mut handler) = self.fallback.take() {
             handler.release().await?;
         }
         Ok(())
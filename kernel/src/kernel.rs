@@ -1,64 +1,203 @@
+use std::sync::Arc;
+
 use ipis::{
-    core::{account::GuarantorSigned, anyhow::Result},
+    core::{
+        account::{AccountRef, GuarantorSigned},
+        anyhow::{bail, Result},
+    },
     env::Infer,
     tokio,
 };
 use ipwis_kernel_common::{
-    resource::ResourceManager,
-    task::{TaskCtx, TaskId},
+    error::ExternError,
+    interrupt::{InterruptExtensions, TaskParent},
+    resource::{ResourceAlloc, ResourceManager},
+    task::{TaskCtx, TaskId, TaskQueueState},
+    usage::UsageSummary,
 };
 
-use crate::{ctx::IpwisCtx, scheduler::Scheduler};
+use crate::{
+    config::KernelConfig, interrupt::InterruptManager, ledger::UsageLedger, scheduler::Scheduler,
+    task::TaskRecord,
+};
 
 pub struct Kernel<R> {
-    resource_manager: R,
+    resource_manager: Arc<R>,
+    ledger: Arc<UsageLedger>,
     scheduler: Scheduler,
 }
 
 impl<R> Kernel<R>
 where
-    R: ResourceManager,
+    R: ResourceManager + Send + Sync + 'static,
 {
     pub async fn boot() -> Result<Self>
     where
         R: for<'a> Infer<'a> + Send,
     {
+        Self::boot_with_config(Default::default()).await
+    }
+
+    pub async fn boot_with_config(config: KernelConfig) -> Result<Self>
+    where
+        R: for<'a> Infer<'a> + Send,
+    {
+        Self::boot_with_interrupts(config, Default::default()).await
+    }
+
+    pub async fn boot_with_interrupts(
+        config: KernelConfig,
+        interrupt_manager: InterruptManager,
+    ) -> Result<Self>
+    where
+        R: for<'a> Infer<'a> + Send,
+    {
+        Self::boot_with_resource_manager(config, interrupt_manager, R::infer().await).await
+    }
+
+    pub async fn boot_with_resource_manager(
+        config: KernelConfig,
+        interrupt_manager: InterruptManager,
+        resource_manager: R,
+    ) -> Result<Self> {
+        let resource_manager = Arc::new(resource_manager);
+
+        // note: the reservations are released as soon as the tasks are finished
+        let releaser = {
+            let resource_manager = resource_manager.clone();
+            Arc::new(move |id| resource_manager.release(id))
+        };
+
+        let ledger = Arc::new(match &config.ledger_path {
+            Some(path) => UsageLedger::open(path)?,
+            None => UsageLedger::default(),
+        });
+
         Ok(Self {
-            resource_manager: R::infer().await,
-            scheduler: Scheduler::new().await?,
+            resource_manager,
+            ledger: ledger.clone(),
+            scheduler: Scheduler::new(
+                config.retention,
+                config.fair_share,
+                interrupt_manager,
+                releaser,
+                ledger,
+            )
+            .await?,
         })
     }
 
-    pub async fn spawn(
+    pub fn resource_manager(&self) -> &R {
+        &self.resource_manager
+    }
+
+    pub fn ledger(&self) -> &UsageLedger {
+        &self.ledger
+    }
+
+    /// Returns the usage of the finished tasks, which the account is billed for.
+    pub fn usage_summary(&self, account: &AccountRef) -> UsageSummary {
+        self.ledger.summary(account)
+    }
+
+    /// Spawns a task, or fails with `LimitExceeded` if its resources are rejected.
+    pub async fn spawn(&self, ctx: GuarantorSigned<TaskCtx>, program: &[u8]) -> Result<TaskId> {
+        self.spawn_with_extensions(ctx, program, Default::default())
+            .await
+    }
+
+    /// Spawns a task with the per-task states, which are prepared by the host.
+    ///
+    /// e.g. the streams can be attached to the task before it starts.
+    pub async fn spawn_with_extensions(
         &self,
         ctx: GuarantorSigned<TaskCtx>,
         program: &[u8],
-    ) -> Result<Option<TaskId>> {
-        match self.resource_manager.alloc(&ctx.constraints).await? {
-            Some(id) => self.scheduler.spawn(id, ctx, program).await.map(Some),
-            None => Ok(None),
+        extensions: InterruptExtensions,
+    ) -> Result<TaskId> {
+        self.spawn_inner(ctx, program, None, extensions).await
+    }
+
+    /// Spawns a child task of the running task.
+    ///
+    /// The lineage is recorded by the kernel, so the child can access the
+    /// parent's resources, e.g. the channels.
+    pub async fn spawn_child(
+        &self,
+        parent: TaskId,
+        ctx: GuarantorSigned<TaskCtx>,
+        program: &[u8],
+    ) -> Result<TaskId> {
+        let parent = self.scheduler.parent(parent).await?;
+        self.spawn_inner(ctx, program, Some(parent), Default::default())
+            .await
+    }
+
+    async fn spawn_inner(
+        &self,
+        ctx: GuarantorSigned<TaskCtx>,
+        program: &[u8],
+        parent: Option<TaskParent>,
+        extensions: InterruptExtensions,
+    ) -> Result<TaskId> {
+        match self
+            .resource_manager
+            .alloc(&ctx.guarantee.account, &ctx.constraints)
+            .await?
+        {
+            ResourceAlloc::Ready(id) => {
+                match self
+                    .scheduler
+                    .spawn(id, ctx, program, parent, extensions)
+                    .await
+                {
+                    Ok(id) => Ok(id),
+                    Err(error) => {
+                        self.resource_manager.release(id);
+                        Err(error)
+                    }
+                }
+            }
+            ResourceAlloc::Rejected(reason) => bail!(ExternError::limit_exceeded(format!(
+                "rejected to spawn a task: {reason}"
+            ))),
         }
     }
 
+    /// Returns the per-task states of the running task.
+    pub async fn extensions(&self, id: TaskId) -> Result<InterruptExtensions> {
+        self.scheduler.extensions(id).await
+    }
+
     pub async fn spawn_local(
         &self,
         ctx: GuarantorSigned<TaskCtx>,
         local_path: impl AsRef<::std::path::Path>,
-    ) -> Result<Option<TaskId>> {
+    ) -> Result<TaskId> {
         let program = tokio::fs::read(local_path).await?;
         self.spawn(ctx, &program).await
     }
 
-    pub async fn poll(&self, id: TaskId) -> Result<Option<IpwisCtx>> {
+    /// Returns the queue state of the task's guarantee account,
+    /// or `None` if the task is not waiting for an execution slot.
+    pub fn queue_state(&self, id: TaskId) -> Option<TaskQueueState> {
+        self.scheduler.queue_state(id)
+    }
+
+    pub async fn poll(&self, id: TaskId) -> Result<Option<Arc<TaskRecord>>> {
         self.scheduler.poll(id).await
     }
 
-    pub async fn wait(&self, id: TaskId) -> Result<IpwisCtx> {
+    pub async fn wait(&self, id: TaskId) -> Result<Arc<TaskRecord>> {
         loop {
             match self.poll(id).await? {
-                Some(ctx) => break Ok(ctx),
+                Some(record) => break Ok(record),
                 None => tokio::task::yield_now().await,
             }
         }
     }
+
+    pub async fn acknowledge(&self, id: TaskId) -> Result<()> {
+        self.scheduler.acknowledge(id).await
+    }
 }
@@ -0,0 +1,313 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use ipis::{
+    core::{account::AccountRef, anyhow::Result, signed::IsSigned},
+    log::warn,
+    pin::PinnedInner,
+    rkyv::AlignedVec,
+    tokio,
+};
+use ipwis_kernel_common::usage::{UsageRecord, UsageSummary};
+
+/// The prefix of the frames, which lets the replay skip over a corrupted one.
+const FRAME_MAGIC: &[u8; 4] = b"IPWU";
+const FRAME_HEADER_LEN: usize = FRAME_MAGIC.len() + 8;
+
+/// Collects the usage records of the finished tasks.
+///
+/// The records are appended to the ledger file if given, and summarized per account.
+#[derive(Default)]
+pub struct UsageLedger {
+    file: Option<Arc<Mutex<File>>>,
+    summaries: Mutex<HashMap<AccountRef, UsageSummary>>,
+}
+
+impl UsageLedger {
+    /// Opens the ledger file, replaying its records into the summaries.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let ledger = Self::default();
+        for record in read_records(&mut file)? {
+            ledger.summarize(&record);
+        }
+
+        Ok(Self {
+            file: Some(Arc::new(Mutex::new(file))),
+            ..ledger
+        })
+    }
+
+    pub async fn append(&self, record: UsageRecord) -> Result<()> {
+        if let Some(file) = &self.file {
+            let bytes = record.to_bytes()?;
+
+            // note: a frame is written at once, so that only the last one can be torn
+            let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + bytes.len());
+            frame.extend_from_slice(FRAME_MAGIC);
+            frame.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            frame.extend_from_slice(&bytes);
+
+            let file = file.clone();
+            tokio::task::spawn_blocking(move || file.lock().unwrap().write_all(&frame)).await??;
+        }
+
+        self.summarize(&record);
+        Ok(())
+    }
+
+    /// Returns the sum of the usage records, which the account is billed for.
+    pub fn summary(&self, account: &AccountRef) -> UsageSummary {
+        self.summaries
+            .lock()
+            .unwrap()
+            .get(account)
+            .cloned()
+            .unwrap_or_else(|| UsageSummary::new(*account))
+    }
+
+    fn summarize(&self, record: &UsageRecord) {
+        self.summaries
+            .lock()
+            .unwrap()
+            .entry(record.guarantee)
+            .or_insert_with(|| UsageSummary::new(record.guarantee))
+            .add(record);
+    }
+}
+
+fn read_records(file: &mut File) -> Result<Vec<UsageRecord>> {
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)?;
+
+    let mut records = vec![];
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let frame = match read_frame(&bytes[offset..]) {
+            Some(frame) => frame,
+            None => {
+                // note: the corrupted bytes are kept, and the next frame is searched for
+                match find_magic(&bytes[offset + 1..]) {
+                    Some(skipped) => {
+                        warn!(
+                            "skipping the corrupted usage records: {} bytes",
+                            1 + skipped
+                        );
+                        offset += 1 + skipped;
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+        };
+        offset += FRAME_HEADER_LEN + frame.len();
+
+        // note: the archived records should be aligned
+        let mut buf = AlignedVec::with_capacity(frame.len());
+        buf.extend_from_slice(frame);
+        match PinnedInner::deserialize_owned(&buf) {
+            Ok(record) => records.push(record),
+            Err(error) => warn!("skipping the invalid usage record: {error}"),
+        }
+    }
+
+    // note: the torn record is dropped, so that it cannot swallow the next ones
+    let tail = &bytes[offset..];
+    if !tail.is_empty() {
+        let is_torn = (tail.len() < FRAME_MAGIC.len() && FRAME_MAGIC.starts_with(tail))
+            || tail.starts_with(FRAME_MAGIC);
+        if is_torn {
+            warn!("dropping the torn usage record: {} bytes", tail.len());
+            file.set_len(offset as u64)?;
+        } else {
+            warn!("keeping the corrupted usage records: {} bytes", tail.len());
+        }
+    }
+    Ok(records)
+}
+
+fn read_frame(bytes: &[u8]) -> Option<&[u8]> {
+    let header = bytes.get(..FRAME_HEADER_LEN)?;
+    if !header.starts_with(FRAME_MAGIC) {
+        return None;
+    }
+
+    let len = u64::from_le_bytes(header[FRAME_MAGIC.len()..].try_into().ok()?);
+    bytes[FRAME_HEADER_LEN..].get(..len.try_into().ok()?)
+}
+
+fn find_magic(bytes: &[u8]) -> Option<usize> {
+    bytes
+        .windows(FRAME_MAGIC.len())
+        .position(|window| window == FRAME_MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::OpenOptions,
+        io::Write,
+        path::{Path, PathBuf},
+    };
+
+    use ipis::{
+        core::{
+            account::{Account, AccountRef},
+            value::chrono::DateTime,
+        },
+        tokio,
+    };
+    use ipwis_kernel_common::{
+        resource::ResourceId,
+        task::TaskId,
+        usage::{SyscallCount, UsageRecord},
+    };
+
+    use super::UsageLedger;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path =
+            ::std::env::temp_dir().join(format!("ipwis-ledger-{name}-{}", ::std::process::id(),));
+        let _ = ::std::fs::remove_file(&path);
+        path
+    }
+
+    fn record(guarantee: AccountRef, id: u32, fuel: u64, peak_memory_bytes: u64) -> UsageRecord {
+        UsageRecord {
+            task_id: TaskId(id),
+            resource_id: ResourceId(id),
+            guarantee,
+            guarantor: Account::generate().account_ref(),
+            completed_date: DateTime::now(),
+            wall_time_ms: 10,
+            fuel,
+            peak_memory_bytes,
+            syscalls: vec![SyscallCount {
+                id: "stream".into(),
+                count: 2,
+            }],
+            stream_bytes_read: 64,
+            stream_bytes_written: 0,
+        }
+    }
+
+    fn write_raw(path: &Path, bytes: &[u8]) {
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(bytes)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let path = temp_path("replay");
+        let guarantee = Account::generate().account_ref();
+        let guarantor = Account::generate().account_ref();
+
+        {
+            let ledger = UsageLedger::open(&path).unwrap();
+            let mut first = record(guarantee, 1, 100, 1024);
+            first.guarantor = guarantor;
+            ledger.append(first).await.unwrap();
+            ledger
+                .append(record(guarantee, 2, 200, 4096))
+                .await
+                .unwrap();
+        }
+
+        // test the summaries are restored from the ledger file
+        let ledger = UsageLedger::open(&path).unwrap();
+        let summary = ledger.summary(&guarantee);
+        assert_eq!(summary.tasks, 2);
+        assert_eq!(summary.fuel, 300);
+        assert_eq!(summary.peak_memory_bytes, 4096);
+        assert_eq!(summary.syscalls[0].count, 4);
+        assert_eq!(summary.stream_bytes_read, 128);
+
+        // test the other accounts are not billed
+        assert_eq!(ledger.summary(&guarantor).tasks, 0);
+
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_corrupted() {
+        let path = temp_path("corrupted");
+        let guarantee = Account::generate().account_ref();
+
+        {
+            let ledger = UsageLedger::open(&path).unwrap();
+            ledger
+                .append(record(guarantee, 1, 100, 1024))
+                .await
+                .unwrap();
+        }
+
+        // a frame with a bad length, and then an undeserializable one
+        write_raw(&path, b"IPWU\xff\xff\xff\xff\xff\xff\xff\x7fgarbage");
+        write_raw(&path, b"IPWU\x04\x00\x00\x00\x00\x00\x00\x00oops");
+        {
+            let ledger = UsageLedger::open(&path).unwrap();
+            ledger
+                .append(record(guarantee, 2, 200, 4096))
+                .await
+                .unwrap();
+        }
+        let len = ::std::fs::metadata(&path).unwrap().len();
+
+        // test the valid records after the corrupted ones are replayed
+        let ledger = UsageLedger::open(&path).unwrap();
+        let summary = ledger.summary(&guarantee);
+        assert_eq!(summary.tasks, 2);
+        assert_eq!(summary.fuel, 300);
+
+        // test the corrupted records are not dropped
+        assert_eq!(::std::fs::metadata(&path).unwrap().len(), len);
+
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_torn() {
+        let path = temp_path("torn");
+        let guarantee = Account::generate().account_ref();
+
+        let len = {
+            let ledger = UsageLedger::open(&path).unwrap();
+            ledger
+                .append(record(guarantee, 1, 100, 1024))
+                .await
+                .unwrap();
+            ::std::fs::metadata(&path).unwrap().len()
+        };
+
+        // a frame, which is torn while being written
+        write_raw(&path, b"IPWU\x40\x00\x00\x00\x00\x00\x00\x00torn");
+        {
+            let ledger = UsageLedger::open(&path).unwrap();
+            assert_eq!(::std::fs::metadata(&path).unwrap().len(), len);
+            ledger
+                .append(record(guarantee, 2, 200, 4096))
+                .await
+                .unwrap();
+        }
+
+        // test the next record is not swallowed by the torn one
+        let ledger = UsageLedger::open(&path).unwrap();
+        assert_eq!(ledger.summary(&guarantee).tasks, 2);
+
+        ::std::fs::remove_file(&path).unwrap();
+    }
+}
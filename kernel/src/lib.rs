@@ -2,10 +2,15 @@
 
 pub extern crate ipwis_kernel_common as common;
 
+pub mod allocation;
+pub(crate) mod completion;
+pub mod config;
 pub mod ctx;
 pub(crate) mod extrinsics;
-pub(crate) mod interrupt;
+pub mod interrupt;
 pub mod kernel;
+pub mod ledger;
 pub mod memory;
+pub mod queue;
 mod scheduler;
-pub(crate) mod task;
+pub mod task;
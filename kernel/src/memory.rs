@@ -1,5 +1,36 @@
-use ipwis_kernel_api::memory::IpwisMemoryInner;
+use ipwis_kernel_api::{
+    memory::{IpwisMemoryInner, StoreMemory},
+    wasmtime::{ResourceLimiter, StoreContextMut},
+};
 
-use crate::ctx::IpwisCaller;
+use crate::ctx::IpwisCtx;
 
-pub type IpwisMemory<'a> = IpwisMemoryInner<&'a mut IpwisCaller<'a>>;
+pub type IpwisMemory<'a> = IpwisMemoryInner<StoreContextMut<'a, IpwisCtx>>;
+
+pub type IpwisMemoryFamily = StoreMemory<IpwisCtx>;
+
+/// Records the peak size of the task's linear memories, without limiting them.
+#[derive(Debug, Default)]
+pub struct PeakMemoryLimiter {
+    peak_bytes: usize,
+}
+
+impl PeakMemoryLimiter {
+    pub fn peak_bytes(&self) -> u64 {
+        self.peak_bytes as u64
+    }
+}
+
+impl ResourceLimiter for PeakMemoryLimiter {
+    fn memory_growing(&mut self, _current: usize, desired: usize, maximum: Option<usize>) -> bool {
+        // note: the growth beyond the maximum fails anyway
+        if maximum.map(|maximum| desired <= maximum).unwrap_or(true) {
+            self.peak_bytes = self.peak_bytes.max(desired);
+        }
+        true
+    }
+
+    fn table_growing(&mut self, _current: u32, _desired: u32, _maximum: Option<u32>) -> bool {
+        true
+    }
+}
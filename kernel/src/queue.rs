@@ -0,0 +1,198 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use ipis::{
+    core::{account::AccountRef, anyhow::Result},
+    tokio::sync::oneshot,
+};
+use ipwis_kernel_common::task::{TaskId, TaskQueueState};
+
+use crate::config::FairShareConfig;
+
+/// The virtual time, which a task of the weight 1 takes.
+const COST: u64 = 1 << 20;
+
+/// Divides the execution slots between the guarantee accounts with weighted fair queueing.
+pub struct FairQueue {
+    config: FairShareConfig,
+    state: Mutex<QueueState>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    running: usize,
+    virtual_time: u64,
+    accounts: HashMap<AccountRef, AccountQueue>,
+}
+
+#[derive(Default)]
+struct AccountQueue {
+    waiters: VecDeque<(TaskId, oneshot::Sender<SlotPermit>)>,
+    running: u32,
+    // the virtual time when the account's last dispatched task is finished
+    finish: u64,
+}
+
+impl FairQueue {
+    pub fn new(config: FairShareConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            state: Default::default(),
+        })
+    }
+
+    /// Waits for an execution slot, which is released when the permit is dropped.
+    pub async fn acquire(self: &Arc<Self>, account: AccountRef, id: TaskId) -> Result<SlotPermit> {
+        let rx = {
+            let (tx, rx) = oneshot::channel();
+            let mut state = self.state.lock().unwrap();
+            state
+                .accounts
+                .entry(account)
+                .or_default()
+                .waiters
+                .push_back((id, tx));
+            self.dispatch(&mut state);
+            rx
+        };
+        rx.await.map_err(Into::into)
+    }
+
+    /// Returns the queue state of the task's account, or `None` if the task is not queued.
+    pub fn queue_state(&self, id: TaskId) -> Option<TaskQueueState> {
+        let state = self.state.lock().unwrap();
+        state.accounts.iter().find_map(|(account, queue)| {
+            let position = queue.waiters.iter().position(|(waiter, _)| *waiter == id)?;
+            Some(TaskQueueState {
+                position: position as u32,
+                queued: queue.waiters.len() as u32,
+                running: queue.running,
+                weight: self.config.weight(account),
+            })
+        })
+    }
+
+    fn dispatch(self: &Arc<Self>, state: &mut QueueState) {
+        while state.running < self.config.slots() {
+            // pick the account, whose next task starts the earliest in the virtual time
+            let virtual_time = state.virtual_time;
+            let next = state
+                .accounts
+                .iter()
+                .filter(|(_, queue)| !queue.waiters.is_empty())
+                .map(|(account, queue)| (queue.finish.max(virtual_time), *account))
+                .min_by_key(|(start, _)| *start);
+            let (start, account) = match next {
+                Some(next) => next,
+                None => break,
+            };
+
+            let weight = self.config.weight(&account) as u64;
+            let queue = state.accounts.get_mut(&account).unwrap();
+            let (_, tx) = queue.waiters.pop_front().unwrap();
+
+            let permit = SlotPermit {
+                queue: Some(self.clone()),
+                account,
+            };
+            match tx.send(permit) {
+                Ok(()) => {
+                    queue.running += 1;
+                    queue.finish = start + COST / weight;
+                    state.running += 1;
+                    state.virtual_time = start;
+                }
+                // note: the task has been dropped while waiting
+                Err(mut permit) => {
+                    permit.queue.take();
+                    if queue.waiters.is_empty() && queue.running == 0 {
+                        state.accounts.remove(&account);
+                    }
+                }
+            }
+        }
+    }
+
+    fn release(self: &Arc<Self>, account: &AccountRef) {
+        let mut state = self.state.lock().unwrap();
+        state.running -= 1;
+        if let Some(queue) = state.accounts.get_mut(account) {
+            queue.running -= 1;
+
+            // note: the idle accounts start again from the current virtual time
+            if queue.waiters.is_empty() && queue.running == 0 {
+                state.accounts.remove(account);
+            }
+        }
+        self.dispatch(&mut state);
+    }
+}
+
+pub struct SlotPermit {
+    queue: Option<Arc<FairQueue>>,
+    account: AccountRef,
+}
+
+impl Drop for SlotPermit {
+    fn drop(&mut self) {
+        if let Some(queue) = self.queue.take() {
+            queue.release(&self.account);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ipis::{core::account::Account, tokio};
+    use ipwis_kernel_common::task::TaskId;
+
+    use super::FairQueue;
+    use crate::config::FairShareConfig;
+
+    #[tokio::test]
+    async fn test_no_starvation() {
+        let noisy = Account::generate().account_ref();
+        let quiet = Account::generate().account_ref();
+
+        let queue = FairQueue::new(FairShareConfig {
+            slots: 1,
+            ..Default::default()
+        });
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        // the noisy tenant occupies the slot, and then floods the queue before the quiet one
+        let permit = queue.acquire(noisy, TaskId(0)).await.unwrap();
+        let mut handlers = vec![];
+        for (index, account) in (0..16).map(|_| noisy).chain([quiet]).enumerate() {
+            let queue = queue.clone();
+            let tx = tx.clone();
+            let id = TaskId(index as u32 + 1);
+            handlers.push(tokio::spawn(async move {
+                let _permit = queue.acquire(account, id).await.unwrap();
+                tx.send(account).unwrap();
+            }));
+            tokio::task::yield_now().await;
+        }
+        drop(tx);
+
+        let state = queue.queue_state(TaskId(17)).unwrap();
+        assert_eq!(state.position, 0);
+        assert_eq!(state.queued, 1);
+        assert_eq!(queue.queue_state(TaskId(16)).unwrap().position, 15);
+
+        // test the quiet tenant is not starved by the noisy one
+        drop(permit);
+        let mut order = vec![];
+        while let Some(account) = rx.recv().await {
+            order.push(account);
+        }
+        assert_eq!(order.len(), 17);
+        assert_eq!(order[0], quiet);
+
+        for handler in handlers {
+            handler.await.unwrap();
+        }
+    }
+}
@@ -3,25 +3,37 @@ use std::sync::Arc;
 use ipis::core::{account::GuarantorSigned, anyhow::Result};
 use ipwis_kernel_api::wasmtime::{Config, Engine, Module};
 use ipwis_kernel_common::{
+    interrupt::{InterruptExtensions, TaskParent},
     resource::ResourceId,
-    task::{TaskCtx, TaskId},
+    task::{TaskCtx, TaskId, TaskQueueState},
 };
 
 use crate::{
-    ctx::{IpwisCtx, IpwisLinker},
+    config::{FairShareConfig, RetentionPolicy},
+    ctx::IpwisLinker,
     interrupt::InterruptManager,
-    task::{Entry, TaskStore},
+    ledger::UsageLedger,
+    queue::FairQueue,
+    task::{EntryState, ResourceReleaser, TaskRecord, TaskStore},
 };
 
 pub struct Scheduler {
     linker: IpwisLinker,
-    tasks: TaskStore<Entry>,
+    retention: RetentionPolicy,
+    queue: Arc<FairQueue>,
+    tasks: Arc<TaskStore<EntryState>>,
 }
 
 impl Scheduler {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(
+        retention: RetentionPolicy,
+        fair_share: FairShareConfig,
+        interrupt_manager: InterruptManager,
+        releaser: ResourceReleaser,
+        ledger: Arc<UsageLedger>,
+    ) -> Result<Self> {
         // define the WASI functions globally on the `Config`.
-        let engine = Engine::new(Config::new().async_support(true))?;
+        let engine = Engine::new(Config::new().async_support(true).consume_fuel(true))?;
 
         let mut linker = IpwisLinker::new(&engine);
         ::ipwis_kernel_api::wasmtime_wasi::add_to_linker(&mut linker, |ctx| &mut ctx.wasi)?;
@@ -30,10 +42,23 @@ impl Scheduler {
         crate::extrinsics::register(&mut linker)?;
 
         // create the other modules
-        let interrupt_manager: Arc<InterruptManager> = Default::default();
-        let tasks = TaskStore::try_new(&engine, interrupt_manager)?;
+        let queue = FairQueue::new(fair_share);
+        let tasks = Arc::new(
+            TaskStore::try_new(&engine, interrupt_manager.into())?
+                .with_releaser(releaser)
+                .with_queue(queue.clone())
+                .with_ledger(ledger),
+        );
 
-        Ok(Self { linker, tasks })
+        // collect the finished tasks and the expired results in background
+        tasks.spawn_reaper(retention);
+
+        Ok(Self {
+            linker,
+            retention,
+            queue,
+            tasks,
+        })
     }
 
     pub async fn spawn(
@@ -41,17 +66,35 @@ impl Scheduler {
         id: ResourceId,
         ctx: GuarantorSigned<TaskCtx>,
         program: &[u8],
+        parent: Option<TaskParent>,
+        extensions: InterruptExtensions,
     ) -> Result<TaskId> {
         // load a module from given binary
         let module = Module::from_binary(self.linker.engine(), program)?;
 
         // spawn
         self.tasks
-            .spawn_entry(&self.linker, &module, id, ctx.into())
+            .spawn_entry(&self.linker, &module, id, ctx.into(), parent, extensions)
             .await
     }
 
-    pub async fn poll(&self, id: TaskId) -> Result<Option<IpwisCtx>> {
-        self.tasks.poll_entry(id).await
+    pub async fn parent(&self, id: TaskId) -> Result<TaskParent> {
+        self.tasks.entry_parent(id).await
+    }
+
+    pub async fn extensions(&self, id: TaskId) -> Result<InterruptExtensions> {
+        self.tasks.entry_extensions(id).await
+    }
+
+    pub fn queue_state(&self, id: TaskId) -> Option<TaskQueueState> {
+        self.queue.queue_state(id)
+    }
+
+    pub async fn poll(&self, id: TaskId) -> Result<Option<Arc<TaskRecord>>> {
+        self.tasks.poll_entry(id, &self.retention).await
+    }
+
+    pub async fn acknowledge(&self, id: TaskId) -> Result<()> {
+        self.tasks.ack_entry(id).await
     }
 }
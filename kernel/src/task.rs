@@ -1,38 +1,56 @@
 use core::sync::atomic::{AtomicU32, Ordering};
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use ipis::{
     core::{
         account::GuarantorSigned,
-        anyhow::{bail, Result},
-        value::chrono::DateTime,
+        anyhow::{anyhow, bail, Result},
+        value::{chrono::DateTime, text::Text},
     },
+    log::warn,
+    object::IntoObjectData,
     tokio::{self, sync::Mutex},
 };
 use ipwis_kernel_api::{
     memory::IpwisMemoryInner,
-    wasmtime::{Engine, Module, Trap},
+    wasmtime::{Engine, Instance, Module, TypedFunc},
 };
 use ipwis_kernel_common::{
-    data::ExternDataRef,
+    data::{ExternData, ExternDataRef},
     extrinsics::InterruptArgs,
+    interrupt::{InterruptExtensions, InterruptTaskOutcome, InterruptTaskUsage, TaskParent},
     memory::Memory,
     modules::{FUNC_NAME_SYSCALL, MODULE_NAME_API},
     protection::ProtectionMode,
     resource::ResourceId,
-    task::{TaskCtx, TaskId, TaskState},
+    task::{TaskCtx, TaskId, TaskPoll, TaskState},
+    usage::{SyscallCount, UsageRecord},
 };
 
 use crate::{
+    allocation::AllocationOwner,
+    config::RetentionPolicy,
     ctx::{IpwisCtx, IpwisLinker, IpwisStore},
     interrupt::InterruptManager,
+    ledger::UsageLedger,
+    queue::FairQueue,
 };
 
+/// Releases the reserved resources of a task, as soon as the task is finished.
+pub type ResourceReleaser = Arc<dyn Fn(ResourceId) + Send + Sync>;
+
 pub struct TaskStore<T> {
     api: Module,
     seed: TaskIdSeed,
-    map: Mutex<BTreeMap<TaskId, T>>,
+    map: Arc<Mutex<BTreeMap<TaskId, T>>>,
     interrupt_manager: Arc<InterruptManager>,
+    releaser: Option<ResourceReleaser>,
+    queue: Option<Arc<FairQueue>>,
+    ledger: Option<Arc<UsageLedger>>,
 }
 
 impl<T> TaskStore<T> {
@@ -42,15 +60,37 @@ impl<T> TaskStore<T> {
             seed: Default::default(),
             map: Default::default(),
             interrupt_manager,
+            releaser: None,
+            queue: None,
+            ledger: None,
         })
     }
 
+    pub fn with_releaser(mut self, releaser: ResourceReleaser) -> Self {
+        self.releaser = Some(releaser);
+        self
+    }
+
+    /// Runs the tasks only if they are given the execution slots.
+    pub fn with_queue(mut self, queue: Arc<FairQueue>) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    /// Records the usage of the tasks, as soon as they are finished.
+    pub fn with_ledger(mut self, ledger: Arc<UsageLedger>) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
+
     async fn spawn_inner<F>(
         &self,
-        linker: &mut IpwisLinker,
+        mut linker: IpwisLinker,
         module: &Module,
         resource_id: ResourceId,
         ctx: Arc<GuarantorSigned<TaskCtx>>,
+        parent: Option<TaskParent>,
+        extensions: InterruptExtensions,
         f: F,
     ) -> Result<TaskId>
     where
@@ -73,55 +113,82 @@ impl<T> TaskStore<T> {
         // create a new store
         let mut store = IpwisStore::new(
             linker.engine(),
-            IpwisCtx::new(linker.engine(), ctx, state, self.interrupt_manager.clone())?,
+            IpwisCtx::new(
+                linker.engine(),
+                ctx,
+                state,
+                self.interrupt_manager.clone(),
+                extensions.clone(),
+                parent,
+            )?,
         );
         let ctx = store.data().task.clone();
         let state = store.data().state.clone();
+        store.limiter(|ctx| &mut ctx.limiter);
 
-        // register API module
-        let api = linker.instantiate_async(&mut store, &self.api).await?;
-        linker.instance(&mut store, MODULE_NAME_API, api)?;
-
-        // create an instance with given module and store
-        let instance = linker.instantiate_async(&mut store, module).await?;
-
-        // find main function
-        let func = instance
-            .get_func(&mut store, FUNC_NAME_SYSCALL)
-            .expect("failed to find `syscall` func")
-            .typed::<InterruptArgs, ExternDataRef, _>(&mut store)
-            .expect("failed to parse `syscall` func");
+        // note: the fuel is metered in `i64` by the engine
+        let fuel = ctx.constraints.resources.fuel.unwrap_or(i64::MAX as u64);
+        store.add_fuel(fuel.min(i64::MAX as u64))?;
 
         // external call
         // note: the inner schedule is controlled by `wasmtime` engine, not by this scheduler
         let handler = {
             let state = state.clone();
-            let (inputs, outputs, errors) = {
-                let mut memory = IpwisMemoryInner::with_instance(&instance, &mut store)?;
-
-                let inputs = memory.dump_doubled_object(&ctx.constraints.inputs).await?;
-                let outputs = memory.dump_doubled_null().await?;
-                let errors = memory.dump_doubled_null().await?;
-                (inputs, outputs, errors)
-            };
+            let api = self.api.clone();
+            let module = module.clone();
+            let releaser = self.releaser.clone();
+            let queue = self.queue.clone();
+            let ledger = self.ledger.clone();
+            let guarantee = ctx.guarantee.account;
 
             tokio::spawn(async move {
-                func.call_async(
-                    &mut store,
-                    (0 /* nullptr */, inputs.ptr, outputs.ptr, errors.ptr),
-                )
-                .await?;
+                // note: the task is instantiated only after given a slot,
+                //       so that the queued tasks do not hold their memories
+                let permit = match &queue {
+                    Some(queue) => queue.acquire(guarantee, task_id).await.map(Some),
+                    None => Ok(None),
+                };
+
+                let (permit, poll, wall_time) = match permit {
+                    Ok(permit) => {
+                        let (poll, wall_time) =
+                            run(&mut linker, &api, &module, &mut store, &state).await;
+                        (permit, poll, wall_time)
+                    }
+                    // note: the task is failed rather than running beyond the slots
+                    Err(error) => (
+                        None,
+                        trap(format!("failed to acquire a slot: {error}"), &[]),
+                        Duration::ZERO,
+                    ),
+                };
+
+                if let Some(ledger) = ledger {
+                    let record = usage_record(&store, resource_id, task_id, wall_time);
+                    if let Err(error) = ledger.append(record).await {
+                        warn!("failed to record the task's usage: {error}");
+                    }
+                }
+
+                // note: the handlers are released as soon as the task is finished,
+                //       e.g. so that the readers of its pipes see the end at once
+                release_handlers(&mut store, &poll).await;
 
                 state.lock().await.is_working = false;
+                if let Some(releaser) = releaser {
+                    releaser(resource_id);
+                }
+                drop(permit);
 
-                Ok(store)
+                TaskResult { store, poll }
             })
         };
 
-        // instantiate and store the task
+        // store the task
         let task = f(Task {
             ctx,
             state,
+            extensions,
             handler,
         });
         {
@@ -132,33 +199,366 @@ impl<T> TaskStore<T> {
     }
 }
 
-impl TaskStore<Entry> {
+async fn release_handlers(store: &mut IpwisStore, poll: &TaskPoll) {
+    // note: the handlers may release their resources differently on trap
+    if let TaskPoll::Trap(_) = poll {
+        InterruptTaskOutcome::with_task(store.data().interrupt_handlers.task()).set_trapped();
+    }
+
+    if let Err(error) = store.data_mut().release().await {
+        warn!("{}", error);
+    }
+
+    // note: the states left by the lazy handlers, e.g. the attached pipes, are dropped as well
+    store.data().interrupt_handlers.task().extensions.clear();
+}
+
+async fn run(
+    linker: &mut IpwisLinker,
+    api: &Module,
+    module: &Module,
+    store: &mut IpwisStore,
+    state: &Mutex<TaskState>,
+) -> (TaskPoll, Duration) {
+    let (instance, func, [inputs, outputs, errors]) =
+        match instantiate(linker, api, module, store, state).await {
+            Ok(instantiated) => instantiated,
+            Err(error) => return (trap(error, &store.data().diagnostics), Duration::ZERO),
+        };
+
+    let args = (0 /* nullptr */, inputs.ptr, outputs.ptr, errors.ptr);
+    let started_date = Instant::now();
+    let result = func.call_async(&mut *store, args).await;
+    let wall_time = started_date.elapsed();
+    let has_returned = result.is_ok();
+    let poll = match result {
+        Ok(_) => load_poll(&instance, store, outputs, errors),
+        Err(trap) => Err(trap.into()),
+    }
+    .unwrap_or_else(|error| trap(error, &store.data().diagnostics));
+
+    // release the host-owned data, so that only the leaked ones are left
+    if let Err(error) = release_allocations(&instance, store, outputs, errors, has_returned).await {
+        warn!("failed to release the task's data: {error}");
+    }
+    (poll, wall_time)
+}
+
+async fn instantiate(
+    linker: &mut IpwisLinker,
+    api: &Module,
+    module: &Module,
+    store: &mut IpwisStore,
+    state: &Mutex<TaskState>,
+) -> Result<(
+    Instance,
+    TypedFunc<InterruptArgs, ExternDataRef>,
+    [ExternData; 3],
+)> {
+    // register API module
+    let api = linker.instantiate_async(&mut *store, api).await?;
+    linker.instance(&mut *store, MODULE_NAME_API, api)?;
+
+    // create an instance with given module and store
+    let instance = linker.instantiate_async(&mut *store, module).await?;
+
+    // find main function
+    let func = instance
+        .get_func(&mut *store, FUNC_NAME_SYSCALL)
+        .ok_or_else(|| anyhow!("failed to find `syscall` func"))?
+        .typed::<InterruptArgs, ExternDataRef, _>(&mut *store)?;
+
+    let ctx = store.data().task.clone();
+    let (inputs, outputs, errors, owned) = {
+        let mut memory = IpwisMemoryInner::with_instance(&instance, &mut *store)?;
+
+        let inputs = memory.dump_doubled_object(&ctx.constraints.inputs).await?;
+        let outputs = memory.dump_doubled_null().await?;
+        let errors = memory.dump_doubled_null().await?;
+
+        let inputs_data: ExternData = memory.read_value(inputs.ptr)?;
+        (
+            inputs,
+            outputs,
+            errors,
+            [inputs_data, inputs, outputs, errors],
+        )
+    };
+    // note: the I/O placeholders are owned by the host
+    for data in owned {
+        store
+            .data_mut()
+            .allocations
+            .insert(data, AllocationOwner::Host);
+    }
+    {
+        let mut state = state.lock().await;
+        state.inputs = inputs;
+        state.outputs = outputs;
+        state.errors = errors;
+    }
+    Ok((instance, func, [inputs, outputs, errors]))
+}
+
+fn trap(error: impl ::core::fmt::Display, diagnostics: &[String]) -> TaskPoll {
+    let mut message = error.to_string();
+    for diagnostic in diagnostics {
+        message.push_str("\ncaused by fatal syscall: ");
+        message.push_str(diagnostic);
+    }
+    TaskPoll::Trap(Text::with_en_us(message))
+}
+
+fn usage_record(
+    store: &IpwisStore,
+    resource_id: ResourceId,
+    task_id: TaskId,
+    wall_time: Duration,
+) -> UsageRecord {
+    let data = store.data();
+    let usage = InterruptTaskUsage::with_task(data.interrupt_handlers.task());
+
+    UsageRecord {
+        task_id,
+        resource_id,
+        guarantee: data.task.guarantee.account,
+        guarantor: data.task.guarantor.account,
+        completed_date: DateTime::now(),
+        wall_time_ms: wall_time.as_millis().try_into().unwrap_or(u64::MAX),
+        fuel: store.fuel_consumed().unwrap_or_default(),
+        peak_memory_bytes: data.limiter.peak_bytes(),
+        syscalls: data
+            .interrupt_handlers
+            .syscalls()
+            .iter()
+            .map(|(id, &count)| SyscallCount {
+                id: id.0.to_string(),
+                count,
+            })
+            .collect(),
+        stream_bytes_read: usage.stream_bytes_read(),
+        stream_bytes_written: usage.stream_bytes_written(),
+    }
+}
+
+fn load_poll(
+    instance: &Instance,
+    store: &mut IpwisStore,
+    outputs: ExternData,
+    errors: ExternData,
+) -> Result<TaskPoll> {
+    let memory = IpwisMemoryInner::with_instance(instance, store)?;
+
+    // the guest has reported an error
+    let errors: ExternData = memory.read_value(errors.ptr)?;
+    if !errors.is_null() {
+        let message = String::from_utf8_lossy(&memory.read_raw(errors)?);
+        return Ok(TaskPoll::Trap(Text::with_en_us(message.into_owned())));
+    }
+
+    // the guest may return nothing
+    let outputs: ExternData = memory.read_value(outputs.ptr)?;
+    if outputs.is_null() {
+        return Ok(TaskPoll::Ready(Box::new(().__into_object_data())));
+    }
+
+    memory
+        .load_object_raw(outputs)
+        .map(Box::new)
+        .map(TaskPoll::Ready)
+}
+
+async fn release_allocations(
+    instance: &Instance,
+    store: &mut IpwisStore,
+    outputs: ExternData,
+    errors: ExternData,
+    has_returned: bool,
+) -> Result<()> {
+    let owned = store.data_mut().allocations.drain_host();
+    let mut memory = IpwisMemoryInner::with_instance(instance, store)?;
+
+    // note: the results are given to the host only if the task has returned
+    if has_returned {
+        for data in [outputs, errors] {
+            let data: ExternData = memory.read_value(data.ptr)?;
+            if !data.is_null() {
+                unsafe { memory.free(data) }.await?;
+            }
+        }
+    }
+
+    for data in owned {
+        unsafe { memory.free(data) }.await?;
+    }
+    Ok(())
+}
+
+impl TaskStore<EntryState> {
     pub async fn spawn_entry(
         &self,
         linker: &IpwisLinker,
         module: &Module,
         id: ResourceId,
         ctx: Arc<GuarantorSigned<TaskCtx>>,
+        parent: Option<TaskParent>,
+        extensions: InterruptExtensions,
     ) -> Result<TaskId> {
-        self.spawn_inner(&mut linker.clone(), module, id, ctx.clone(), |task| Entry {
-            ctx,
-            task,
-        })
+        self.spawn_inner(
+            linker.clone(),
+            module,
+            id,
+            ctx.clone(),
+            parent,
+            extensions,
+            |task| EntryState::Running(Entry { ctx, task }),
+        )
         .await
     }
 
-    pub async fn poll_entry(&self, id: TaskId) -> Result<Option<IpwisCtx>> {
+    pub async fn entry_extensions(&self, id: TaskId) -> Result<InterruptExtensions> {
+        match self.map.lock().await.get(&id) {
+            Some(EntryState::Running(entry)) => Ok(entry.task.extensions.clone()),
+            Some(EntryState::Finishing | EntryState::Finished(_)) => {
+                bail!("the task is already finished: {id:x}")
+            }
+            None => bail!("failed to find the task: {id:x}"),
+        }
+    }
+
+    /// Returns the lineage of the running task, which spawns a child.
+    pub async fn entry_parent(&self, id: TaskId) -> Result<TaskParent> {
+        match self.map.lock().await.get(&id) {
+            Some(EntryState::Running(entry)) => Ok(TaskParent {
+                id,
+                guarantee: entry.ctx.guarantee.account,
+            }),
+            Some(EntryState::Finishing | EntryState::Finished(_)) => {
+                bail!("the task is already finished: {id:x}")
+            }
+            None => bail!("failed to find the task: {id:x}"),
+        }
+    }
+
+    pub async fn poll_entry(
+        &self,
+        id: TaskId,
+        policy: &RetentionPolicy,
+    ) -> Result<Option<Arc<TaskRecord>>> {
+        let entry = {
+            let mut map = self.map.lock().await;
+            match map.get(&id) {
+                Some(EntryState::Running(entry)) => {
+                    if entry.task.state.lock().await.is_working {
+                        return Ok(None);
+                    }
+                }
+                Some(EntryState::Finishing) => return Ok(None),
+                Some(EntryState::Finished(record)) => return Ok(Some(record.clone())),
+                None => bail!("failed to find the task: {id:x}"),
+            }
+            match Self::take_entry(&mut map, id) {
+                Some(entry) => entry,
+                None => return Ok(None),
+            }
+        };
+
+        self.finish_entry(id, entry, policy).await.map(Some)
+    }
+
+    pub async fn ack_entry(&self, id: TaskId) -> Result<()> {
         let mut map = self.map.lock().await;
         match map.get(&id) {
-            Some(entry) if !entry.task.state.lock().await.is_working => {
-                let mut task = map.remove(&id).unwrap().await??.into_data();
-                task.release().await?;
-                Ok(Some(task))
+            Some(EntryState::Running(_) | EntryState::Finishing) => {
+                bail!("the task is still running: {id:x}")
+            }
+            Some(EntryState::Finished(_)) => {
+                map.remove(&id);
+                Ok(())
             }
-            Some(_) => Ok(None),
             None => bail!("failed to find the task: {id:x}"),
         }
     }
+
+    pub async fn reap_entries(&self, policy: &RetentionPolicy) {
+        let finished = {
+            let mut map = self.map.lock().await;
+
+            // drop the expired results
+            let now = Instant::now();
+            map.retain(|_, entry| match entry {
+                EntryState::Running(_) | EntryState::Finishing => true,
+                EntryState::Finished(record) => record.expires_date > now,
+            });
+
+            // collect the finished tasks, even though nobody has polled them
+            let mut finished = vec![];
+            for (id, entry) in map.iter() {
+                if let EntryState::Running(entry) = entry {
+                    if !entry.task.state.lock().await.is_working {
+                        finished.push(*id);
+                    }
+                }
+            }
+            finished
+                .into_iter()
+                .filter_map(|id| Self::take_entry(&mut map, id).map(|entry| (id, entry)))
+                .collect::<Vec<_>>()
+        };
+
+        for (id, entry) in finished {
+            if let Err(error) = self.finish_entry(id, entry, policy).await {
+                warn!("{}", error);
+            }
+        }
+    }
+
+    pub fn spawn_reaper(self: &Arc<Self>, policy: RetentionPolicy) {
+        let store = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(policy.reap_interval);
+            loop {
+                interval.tick().await;
+                match store.upgrade() {
+                    Some(store) => store.reap_entries(&policy).await,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    // note: the entry is completed out of the map, so that the other tasks are not blocked
+    fn take_entry(map: &mut BTreeMap<TaskId, EntryState>, id: TaskId) -> Option<Entry> {
+        if !matches!(map.get(&id), Some(EntryState::Running(_))) {
+            return None;
+        }
+        match map.insert(id, EntryState::Finishing) {
+            Some(EntryState::Running(entry)) => Some(entry),
+            _ => None,
+        }
+    }
+
+    async fn finish_entry(
+        &self,
+        id: TaskId,
+        entry: Entry,
+        policy: &RetentionPolicy,
+    ) -> Result<Arc<TaskRecord>> {
+        let map = self.map.clone();
+        let policy = *policy;
+
+        // note: the completion outlives the caller, e.g. a disconnected client,
+        //       so that the entry cannot be left finishing
+        tokio::spawn(async move {
+            let record = Arc::new(TaskRecord::complete(entry, &policy).await);
+            map.lock()
+                .await
+                .insert(id, EntryState::Finished(record.clone()));
+            record
+        })
+        .await
+        .map_err(|error| anyhow!("failed to finish the task {id:x}: {error}"))
+    }
 }
 
 impl TaskStore<Task> {
@@ -168,14 +568,25 @@ impl TaskStore<Task> {
         module: &Module,
         id: ResourceId,
         ctx: Arc<GuarantorSigned<TaskCtx>>,
+        parent: Option<TaskParent>,
+        extensions: InterruptExtensions,
     ) -> Result<TaskId> {
-        self.spawn_inner(linker, module, id, ctx, |task| task).await
+        self.spawn_inner(
+            linker.clone(),
+            module,
+            id,
+            ctx,
+            parent,
+            extensions,
+            |task| task,
+        )
+        .await
     }
 
     pub async fn release(&mut self) {
         // order: Task Seed -> SubTasks
         self.seed.release();
-        for task in self.map.get_mut().values() {
+        for task in self.map.lock().await.values() {
             task.handler.abort();
         }
     }
@@ -207,6 +618,60 @@ impl TaskIdSeed {
     }
 }
 
+pub enum EntryState {
+    Running(Entry),
+    /// The task is being completed, e.g. releasing its handlers.
+    Finishing,
+    Finished(Arc<TaskRecord>),
+}
+
+// a compact result of the finished task, which outlives its store
+#[derive(Clone, Debug)]
+pub struct TaskRecord {
+    pub ctx: Arc<GuarantorSigned<TaskCtx>>,
+    pub state: TaskState,
+    pub poll: TaskPoll,
+    pub diagnostics: Vec<String>,
+    pub leaked_bytes: u64,
+    pub completed_date: Instant,
+    pub expires_date: Instant,
+}
+
+impl TaskRecord {
+    async fn complete(entry: Entry, policy: &RetentionPolicy) -> Self {
+        let ctx = entry.ctx.clone();
+        let state = *entry.task.state.lock().await;
+
+        let (poll, diagnostics, leaked_bytes) = match entry.await {
+            Ok(TaskResult { mut store, poll }) => {
+                // the store is dropped here, releasing the whole linear memory
+                let data = store.data_mut();
+                (
+                    poll,
+                    ::core::mem::take(&mut data.diagnostics),
+                    data.allocations.live_bytes(),
+                )
+            }
+            Err(error) => (trap(error, &[]), Default::default(), Default::default()),
+        };
+
+        let completed_date = Instant::now();
+        Self {
+            ctx,
+            state,
+            poll,
+            diagnostics,
+            leaked_bytes,
+            completed_date,
+            expires_date: completed_date + policy.ttl,
+        }
+    }
+}
+
+pub struct TaskResult {
+    pub store: IpwisStore,
+    pub poll: TaskPoll,
+}
+
 pub type Entry = ::ipwis_kernel_common::task::Entry<TaskResult>;
 pub type Task = ::ipwis_kernel_common::task::Task<TaskResult>;
-type TaskResult = Result<IpwisStore, Trap>;
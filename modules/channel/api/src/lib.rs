@@ -0,0 +1,619 @@
+#![allow(clippy::missing_safety_doc)]
+
+pub extern crate ipwis_modules_channel_common as common;
+
+use core::future::Future;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::AccountRef,
+        anyhow::{bail, Result},
+        signed::IsSigned,
+    },
+    pin::PinnedInner,
+    rkyv::AlignedVec,
+    tokio::sync::{broadcast, mpsc, Mutex as AsyncMutex},
+};
+use ipwis_kernel_common::{
+    error::ExternError,
+    interrupt::{
+        DynInterruptHandler, InterruptHandler, InterruptId, InterruptModule, InterruptSubmission,
+        InterruptTask, TaskParent,
+    },
+    memory::{Memory, MemoryFamily},
+    resource::{ResourceId, ResourceStore},
+    task::TaskId,
+};
+use ipwis_modules_channel_common::{io, ChannelKind};
+
+/// The named channels, which are shared between the tasks of a kernel.
+///
+/// The names are scoped by the guarantee accounts, so that the other accounts
+/// cannot take or probe them.
+#[derive(Clone, Default)]
+pub struct ChannelRegistry {
+    map: Arc<Mutex<HashMap<ChannelKey, Arc<Channel>>>>,
+}
+
+type ChannelKey = (AccountRef, String);
+
+impl ChannelRegistry {
+    fn create(&self, task: &InterruptTask, req: io::request::Create) -> Result<Arc<Channel>> {
+        if req.capacity == 0 {
+            bail!(ExternError::invalid_input(
+                "the capacity should not be zero"
+            ));
+        }
+
+        let key = (task.guarantee(), req.name);
+        let mut map = self.map.lock().unwrap();
+        if map.contains_key(&key) {
+            bail!(ExternError::invalid_input(format!(
+                "the channel already exists: {:?}",
+                &key.1,
+            )));
+        }
+
+        let capacity = req.capacity as usize;
+        let (tx, rx) = match req.kind {
+            ChannelKind::Channel => {
+                let (tx, rx) = mpsc::channel(capacity);
+                (Sender::Channel(tx), Some(Arc::new(AsyncMutex::new(rx))))
+            }
+            ChannelKind::Topic => (Sender::Topic(broadcast::channel(capacity).0), None),
+        };
+
+        let channel = Arc::new(Channel {
+            name: key.1.clone(),
+            kind: req.kind,
+            owner: task.id,
+            guarantee: key.0,
+            parent: task.parent,
+            tx: Mutex::new(Some(tx)),
+            rx,
+        });
+        map.insert(key, channel.clone());
+        Ok(channel)
+    }
+
+    fn open(&self, task: &InterruptTask, name: &str) -> Result<Arc<Channel>> {
+        let map = self.map.lock().unwrap();
+
+        // note: the own channels come first, then the parent's and the children's ones
+        let own = map.get(&(task.guarantee(), name.to_string()));
+        let parent = task
+            .parent
+            .and_then(|parent| map.get(&(parent.guarantee, name.to_string())));
+        let children = map
+            .values()
+            .filter(|channel| channel.name == name && channel.guarantee != task.guarantee());
+
+        match own
+            .into_iter()
+            .chain(parent)
+            .chain(children)
+            .find(|channel| channel.is_accessible(task))
+        {
+            Some(channel) => Ok(channel.clone()),
+            // note: the inaccessible channels are hidden to avoid probing the names
+            None => bail!(ExternError::not_found(format!(
+                "failed to find the channel: {name:?}"
+            ))),
+        }
+    }
+
+    fn close(&self, channel: &Arc<Channel>) {
+        let mut map = self.map.lock().unwrap();
+        let key = (channel.guarantee, channel.name.clone());
+        if let Some(current) = map.get(&key) {
+            // note: the name may be taken by a new channel
+            if Arc::ptr_eq(current, channel) {
+                map.remove(&key);
+            }
+        }
+        channel.close();
+    }
+
+    /// Closes all the channels, which are owned by the task.
+    pub fn close_owned(&self, owner: TaskId) {
+        let mut map = self.map.lock().unwrap();
+        map.retain(|_, channel| {
+            if channel.owner == owner {
+                channel.close();
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+struct Channel {
+    name: String,
+    kind: ChannelKind,
+    owner: TaskId,
+    guarantee: AccountRef,
+    /// The lineage of the owner, which is recorded by the kernel.
+    parent: Option<TaskParent>,
+    tx: Mutex<Option<Sender>>,
+    rx: Option<SharedChannelReceiver>,
+}
+
+impl Channel {
+    fn is_accessible(&self, task: &InterruptTask) -> bool {
+        if self.guarantee == task.guarantee() {
+            return true;
+        }
+
+        // note: the parent and the children can access each other's channels
+        task.parent.map(|parent| parent.id) == Some(self.owner)
+            || self.parent.map(|parent| parent.id) == Some(task.id)
+    }
+
+    fn sender(&self) -> Result<Sender> {
+        match self.tx.lock().unwrap().as_ref() {
+            Some(tx) => Ok(tx.clone()),
+            None => bail!(ExternError::not_found(format!(
+                "the channel is closed: {:?}",
+                &self.name,
+            ))),
+        }
+    }
+
+    fn receiver(&self) -> Result<Receiver> {
+        match &self.rx {
+            Some(rx) => Ok(Receiver::Channel(rx.clone())),
+            None => match self.sender()? {
+                Sender::Topic(tx) => {
+                    let rx = tx.subscribe();
+                    Ok(Receiver::Topic(Arc::new(AsyncMutex::new(rx))))
+                }
+                Sender::Channel(_) => unreachable!("the channel should have a receiver"),
+            },
+        }
+    }
+
+    fn close(&self) {
+        // note: the receivers are finished once the pending messages are received
+        self.tx.lock().unwrap().take();
+    }
+}
+
+type SharedChannelReceiver = Arc<AsyncMutex<mpsc::Receiver<Vec<u8>>>>;
+
+#[derive(Clone)]
+enum Sender {
+    Channel(mpsc::Sender<Vec<u8>>),
+    Topic(broadcast::Sender<Vec<u8>>),
+}
+
+impl Sender {
+    async fn send(self, data: Vec<u8>) -> Result<()> {
+        match self {
+            Self::Channel(tx) => tx
+                .send(data)
+                .await
+                .map_err(|_| ExternError::not_found("the channel is closed").into()),
+            Self::Topic(tx) => {
+                // note: the messages without any receivers are dropped
+                let _ = tx.send(data);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Receiver {
+    Channel(SharedChannelReceiver),
+    Topic(Arc<AsyncMutex<broadcast::Receiver<Vec<u8>>>>),
+}
+
+impl Receiver {
+    async fn recv(self) -> Option<Vec<u8>> {
+        match self {
+            Self::Channel(rx) => rx.lock().await.recv().await,
+            Self::Topic(rx) => {
+                let mut rx = rx.lock().await;
+                loop {
+                    match rx.recv().await {
+                        Ok(data) => break Some(data),
+                        // note: the slow receivers skip the overwritten messages
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break None,
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Handle {
+    channel: Arc<Channel>,
+    receiver: Receiver,
+}
+
+impl Handle {
+    fn new(channel: Arc<Channel>) -> Result<Self> {
+        Ok(Self {
+            receiver: channel.receiver()?,
+            channel,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct ChannelModule {
+    registry: ChannelRegistry,
+}
+
+impl ChannelModule {
+    pub fn registry(&self) -> &ChannelRegistry {
+        &self.registry
+    }
+}
+
+#[async_trait]
+impl<F> InterruptModule<F> for ChannelModule
+where
+    F: MemoryFamily,
+{
+    fn id(&self) -> InterruptId {
+        io::OpCode::ID
+    }
+
+    async fn spawn_handler(&self, task: &InterruptTask) -> Result<Box<dyn DynInterruptHandler<F>>> {
+        Ok(Box::new(ChannelHandler::with_task(
+            self.registry.clone(),
+            task,
+        )))
+    }
+}
+
+pub struct ChannelHandler {
+    registry: ChannelRegistry,
+    task: InterruptTask,
+    handles: ResourceStore<Handle>,
+}
+
+impl ChannelHandler {
+    pub const KIND: &'static str = "channels";
+
+    pub fn with_task(registry: ChannelRegistry, task: &InterruptTask) -> Self {
+        Self {
+            registry,
+            task: task.clone(),
+            handles: ResourceStore::with_limit(
+                Self::KIND,
+                task.ctx.constraints.resources.handle_limit(Self::KIND),
+            ),
+        }
+    }
+
+    fn get(&self, id: &ResourceId) -> Result<&Handle> {
+        self.handles.get(id)
+    }
+
+    fn handle_create(&mut self, req: io::request::Create) -> Result<io::response::Create> {
+        // note: the channel is created only if the handle is available
+        let id = self
+            .handles
+            .insert(|_| Handle::new(self.registry.create(&self.task, req)?))?;
+        Ok(io::response::Create { id })
+    }
+
+    fn handle_open(&mut self, req: io::request::Open) -> Result<io::response::Open> {
+        let id = self
+            .handles
+            .insert(|_| Handle::new(self.registry.open(&self.task, &req.name)?))?;
+        Ok(io::response::Open {
+            id,
+            kind: self.get(&id)?.channel.kind,
+        })
+    }
+
+    fn send(
+        &self,
+        req: io::request::Send,
+    ) -> Result<impl Future<Output = Result<io::response::Send>> + Send + 'static> {
+        let tx = self.get(&req.id)?.channel.sender()?;
+        Ok(async move {
+            tx.send(req.data).await?;
+            Ok(io::response::Send {})
+        })
+    }
+
+    fn receive(
+        &self,
+        req: io::request::Receive,
+    ) -> Result<impl Future<Output = Result<io::response::Receive>> + Send + 'static> {
+        let rx = self.get(&req.id)?.receiver.clone();
+        Ok(async move {
+            Ok(io::response::Receive {
+                data: rx.recv().await,
+            })
+        })
+    }
+
+    fn handle_close(&mut self, req: io::request::Close) -> Result<io::response::Close> {
+        let handle = self.handles.remove(&req.id)?;
+
+        if handle.channel.owner == self.task.id {
+            self.registry.close(&handle.channel);
+        }
+        Ok(io::response::Close {})
+    }
+}
+
+#[async_trait]
+impl<M> InterruptHandler<M> for ChannelHandler
+where
+    M: Memory,
+{
+    async unsafe fn handle_raw(&mut self, _memory: &mut M, inputs: &[u8]) -> Result<AlignedVec> {
+        match PinnedInner::deserialize_owned(inputs)? {
+            io::OpCode::Create(req) => self.handle_create(req)?.to_bytes().map_err(Into::into),
+            io::OpCode::Open(req) => self.handle_open(req)?.to_bytes().map_err(Into::into),
+            io::OpCode::Send(req) => self.send(req)?.await?.to_bytes().map_err(Into::into),
+            io::OpCode::Receive(req) => self.receive(req)?.await?.to_bytes().map_err(Into::into),
+            io::OpCode::Close(req) => self.handle_close(req)?.to_bytes().map_err(Into::into),
+        }
+    }
+
+    async unsafe fn submit_raw(
+        &mut self,
+        memory: &mut M,
+        inputs: &[u8],
+    ) -> Result<InterruptSubmission> {
+        match PinnedInner::deserialize_owned(inputs)? {
+            io::OpCode::Send(req) => {
+                let send = self.send(req)?;
+
+                Ok(InterruptSubmission::pending(async move {
+                    send.await?.to_bytes().map(Into::into).map_err(Into::into)
+                }))
+            }
+            io::OpCode::Receive(req) => {
+                let receive = self.receive(req)?;
+
+                Ok(InterruptSubmission::pending(async move {
+                    receive
+                        .await?
+                        .to_bytes()
+                        .map(Into::into)
+                        .map_err(Into::into)
+                }))
+            }
+            _ => self
+                .handle_raw(memory, inputs)
+                .await
+                .map(InterruptSubmission::Ready),
+        }
+    }
+
+    async fn release(&mut self) -> Result<()> {
+        self.handles.clear();
+        self.registry.close_owned(self.task.id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ipiis_api::{client::IpiisClient, common::Ipiis};
+    use ipis::{env::Infer, tokio};
+    use ipwis_kernel_common::{
+        error::{ErrorCode, ExternError},
+        interrupt::{InterruptHandler, TaskParent},
+        resource::HandleLimit,
+        task::{TaskCtx, TaskId},
+    };
+    use ipwis_modules_channel_common::{io, ChannelKind};
+    use ipwis_testkit::{
+        handler::{call, task, task_with_ctx},
+        memory::VecMemory,
+    };
+
+    use super::{ChannelHandler, ChannelRegistry};
+
+    #[tokio::test]
+    async fn test_send_and_receive() {
+        let mut memory = VecMemory::default();
+
+        let client = IpiisClient::infer().await;
+        let registry = ChannelRegistry::default();
+
+        let owner = task(&client).unwrap();
+        let mut peer = task(&client).unwrap();
+        peer.id = TaskId(1);
+
+        let mut owner = ChannelHandler::with_task(registry.clone(), &owner);
+        let mut peer = ChannelHandler::with_task(registry, &peer);
+
+        let outputs: io::response::Create = call(
+            &mut owner,
+            &mut memory,
+            &io::OpCode::Create(io::request::Create {
+                name: "hello".into(),
+                kind: ChannelKind::Channel,
+                capacity: 4,
+            }),
+        )
+        .await
+        .unwrap();
+        let sender = outputs.id;
+
+        // test the tasks with the same guarantee can open the channel
+        let outputs: io::response::Open = call(
+            &mut peer,
+            &mut memory,
+            &io::OpCode::Open(io::request::Open {
+                name: "hello".into(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outputs.kind, ChannelKind::Channel);
+        let receiver = outputs.id;
+
+        let data = b"hello world".to_vec();
+        let _: io::response::Send = call(
+            &mut owner,
+            &mut memory,
+            &io::OpCode::Send(io::request::Send {
+                id: sender,
+                data: data.clone(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        let receive = io::OpCode::Receive(io::request::Receive { id: receiver });
+        let outputs: io::response::Receive = call(&mut peer, &mut memory, &receive).await.unwrap();
+        assert_eq!(outputs.data, Some(data));
+
+        // test the channel is closed when the owner is finished
+        InterruptHandler::<VecMemory>::release(&mut owner)
+            .await
+            .unwrap();
+
+        let outputs: io::response::Receive = call(&mut peer, &mut memory, &receive).await.unwrap();
+        assert_eq!(outputs.data, None);
+    }
+
+    #[tokio::test]
+    async fn test_open_across_guarantees() {
+        let mut memory = VecMemory::default();
+
+        let client = IpiisClient::infer().await;
+        let stranger = IpiisClient::genesis(None).await.unwrap();
+        assert_ne!(
+            client.account_me().account_ref(),
+            stranger.account_me().account_ref(),
+        );
+        let registry = ChannelRegistry::default();
+
+        let parent = task(&client).unwrap();
+        // note: the lineage is recorded by the kernel when the child is spawned
+        let mut child = task(&stranger).unwrap();
+        child.id = TaskId(1);
+        child.parent = Some(TaskParent {
+            id: parent.id,
+            guarantee: parent.guarantee(),
+        });
+        // note: the stranger cannot be related by copying the parent's ctx
+        let ctx: &TaskCtx = &parent.ctx;
+        let mut other = task_with_ctx(&stranger, ctx.clone()).unwrap();
+        other.id = TaskId(2);
+
+        let mut parent = ChannelHandler::with_task(registry.clone(), &parent);
+        let mut child = ChannelHandler::with_task(registry.clone(), &child);
+        let mut other = ChannelHandler::with_task(registry, &other);
+
+        let create = |name: &str| {
+            io::OpCode::Create(io::request::Create {
+                name: name.into(),
+                kind: ChannelKind::Topic,
+                capacity: 4,
+            })
+        };
+        let open = |name: &str| io::OpCode::Open(io::request::Open { name: name.into() });
+
+        let _: io::response::Create = call(&mut parent, &mut memory, &create("hello"))
+            .await
+            .unwrap();
+
+        // test the child can open the parent's channel, though it has another guarantee
+        let outputs: io::response::Open =
+            call(&mut child, &mut memory, &open("hello")).await.unwrap();
+        assert_eq!(outputs.kind, ChannelKind::Topic);
+
+        // test the unrelated account can neither open nor probe the channel
+        let error = call::<_, _, io::response::Open>(&mut other, &mut memory, &open("hello"))
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::NotFound);
+
+        // test the names are scoped by the guarantees
+        let _: io::response::Create = call(&mut other, &mut memory, &create("hello"))
+            .await
+            .unwrap();
+
+        // test the parent can open the child's channel
+        let _: io::response::Create = call(&mut child, &mut memory, &create("world"))
+            .await
+            .unwrap();
+        let _: io::response::Open = call(&mut parent, &mut memory, &open("world"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handle_limit() {
+        let mut memory = VecMemory::default();
+
+        let client = IpiisClient::infer().await;
+        let mut ctx = TaskCtx::new_sandbox();
+        ctx.constraints.resources.handle_limits = vec![HandleLimit::new(ChannelHandler::KIND, 1)];
+        let task = task_with_ctx(&client, ctx).unwrap();
+        let mut handler = ChannelHandler::with_task(Default::default(), &task);
+
+        let create = |name: &str| {
+            io::OpCode::Create(io::request::Create {
+                name: name.into(),
+                kind: ChannelKind::Topic,
+                capacity: 4,
+            })
+        };
+
+        let outputs: io::response::Create = call(&mut handler, &mut memory, &create("hello"))
+            .await
+            .unwrap();
+        let stale = outputs.id;
+
+        // test the handles are limited for each task
+        let error = call::<_, _, io::response::Create>(&mut handler, &mut memory, &create("world"))
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::LimitExceeded);
+
+        let _: io::response::Close = call(
+            &mut handler,
+            &mut memory,
+            &io::OpCode::Close(io::request::Close { id: stale }),
+        )
+        .await
+        .unwrap();
+
+        // test the slot is reused with the next generation
+        let outputs: io::response::Create = call(&mut handler, &mut memory, &create("world"))
+            .await
+            .unwrap();
+        assert_eq!(outputs.id.index(), stale.index());
+        assert_ne!(outputs.id, stale);
+
+        // test the stale handle is rejected
+        let error = call::<_, _, io::response::Send>(
+            &mut handler,
+            &mut memory,
+            &io::OpCode::Send(io::request::Send {
+                id: stale,
+                data: vec![],
+            }),
+        )
+        .await
+        .unwrap_err()
+        .downcast::<ExternError>()
+        .unwrap();
+        assert_eq!(error.code, ErrorCode::NotFound);
+    }
+}
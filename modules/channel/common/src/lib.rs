@@ -0,0 +1,186 @@
+use bytecheck::CheckBytes;
+use ipis::core::anyhow::Result;
+use ipwis_kernel_common::{
+    interrupt::{InterruptId, InterruptTicket},
+    resource::ResourceId,
+};
+use rkyv::{
+    de::deserializers::SharedDeserializeMap, validation::validators::DefaultValidator, Archive,
+    Deserialize, Serialize,
+};
+
+/// Creates a named channel, which is owned by the current task.
+///
+/// The channel is closed when the task is finished.
+pub unsafe fn create(name: impl ToString, kind: ChannelKind, capacity: u32) -> Result<ResourceId> {
+    let res: io::response::Create = io::OpCode::Create(io::request::Create {
+        name: name.to_string(),
+        kind,
+        capacity,
+    })
+    .syscall()?;
+    Ok(res.id)
+}
+
+/// Opens a named channel, which is created by a task with the same guarantee,
+/// by the parent task, or by one of the child tasks.
+pub unsafe fn open(name: impl ToString) -> Result<(ResourceId, ChannelKind)> {
+    let res: io::response::Open = io::OpCode::Open(io::request::Open {
+        name: name.to_string(),
+    })
+    .syscall()?;
+    Ok((res.id, res.kind))
+}
+
+pub unsafe fn send(id: ResourceId, data: Vec<u8>) -> Result<()> {
+    let _: io::response::Send = io::OpCode::Send(io::request::Send { id, data }).syscall()?;
+    Ok(())
+}
+
+/// Receives a message, or `None` if the channel is closed.
+pub unsafe fn receive(id: ResourceId) -> Result<Option<Vec<u8>>> {
+    let res: io::response::Receive = io::OpCode::Receive(io::request::Receive { id }).syscall()?;
+    Ok(res.data)
+}
+
+pub unsafe fn submit_receive(id: ResourceId) -> Result<InterruptTicket> {
+    io::OpCode::ID.submit(&mut io::OpCode::Receive(io::request::Receive { id }))
+}
+
+/// Closes the handle.
+///
+/// If the current task owns the channel, the channel itself is closed as well.
+pub unsafe fn close(id: ResourceId) -> Result<()> {
+    let _: io::response::Close = io::OpCode::Close(io::request::Close { id }).syscall()?;
+    Ok(())
+}
+
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Archive, Serialize, Deserialize,
+)]
+#[archive(compare(PartialEq, PartialOrd))]
+#[archive_attr(derive(CheckBytes, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash))]
+#[repr(C)]
+pub enum ChannelKind {
+    /// Each message is received by only one of the receivers.
+    Channel,
+    /// Each message is received by all of the receivers.
+    Topic,
+}
+
+pub mod io {
+    use super::*;
+
+    #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+    #[archive_attr(derive(CheckBytes))]
+    pub enum OpCode {
+        Create(self::request::Create),
+        Open(self::request::Open),
+        Send(self::request::Send),
+        Receive(self::request::Receive),
+        Close(self::request::Close),
+    }
+
+    impl ::ipis::core::signed::IsSigned for OpCode {}
+
+    impl OpCode {
+        pub const ID: InterruptId = InterruptId("ipwis_modules_channel");
+
+        pub(crate) unsafe fn syscall<O>(mut self) -> Result<O>
+        where
+            O: Archive,
+            <O as Archive>::Archived:
+                for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+        {
+            Self::ID.syscall(&mut self)
+        }
+    }
+
+    pub mod request {
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Create {
+            pub name: String,
+            pub kind: ChannelKind,
+            pub capacity: u32,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Create {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Open {
+            pub name: String,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Open {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Send {
+            pub id: ResourceId,
+            pub data: Vec<u8>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Send {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Receive {
+            pub id: ResourceId,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Receive {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Close {
+            pub id: ResourceId,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Close {}
+    }
+
+    pub mod response {
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Create {
+            pub id: ResourceId,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Create {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Open {
+            pub id: ResourceId,
+            pub kind: ChannelKind,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Open {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Send {}
+
+        impl ::ipis::core::signed::IsSigned for Send {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Receive {
+            pub data: Option<Vec<u8>>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Receive {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Close {}
+
+        impl ::ipis::core::signed::IsSigned for Close {}
+    }
+}
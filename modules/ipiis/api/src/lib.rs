@@ -0,0 +1,221 @@
+#![allow(clippy::missing_safety_doc)]
+
+pub extern crate ipwis_modules_ipiis_common as common;
+
+use std::sync::Arc;
+
+use ipis::{
+    async_trait::async_trait,
+    core::{account::AccountRef, anyhow::Result},
+    pin::PinnedInner,
+    rkyv::AlignedVec,
+};
+use ipwis_kernel_common::{
+    interrupt::{
+        DynInterruptFallbackHandler, DynInterruptHandler, InterruptFallbackHandler,
+        InterruptFallbackModule, InterruptHandler, InterruptId, InterruptModule, InterruptTask,
+    },
+    memory::{Memory, MemoryFamily},
+};
+use ipwis_modules_ipiis_common::{IpwisSyscall, SyscallRequest, ID};
+
+/// Forwards the syscalls to the target account over IPIIS.
+///
+/// Registered as a fallback, it also receives the syscalls which the kernel doesn't handle.
+pub struct IpiisModule<C> {
+    client: Arc<C>,
+    target: AccountRef,
+}
+
+impl<C> IpiisModule<C> {
+    pub fn new(client: Arc<C>, target: AccountRef) -> Self {
+        Self { client, target }
+    }
+
+    fn spawn(&self) -> IpiisHandler<C> {
+        IpiisHandler {
+            client: self.client.clone(),
+            target: self.target,
+        }
+    }
+}
+
+#[async_trait]
+impl<C, F> InterruptModule<F> for IpiisModule<C>
+where
+    C: IpwisSyscall + Send + Sync + 'static,
+    F: MemoryFamily,
+{
+    fn id(&self) -> InterruptId {
+        ID
+    }
+
+    async fn spawn_handler(
+        &self,
+        _task: &InterruptTask,
+    ) -> Result<Box<dyn DynInterruptHandler<F>>> {
+        Ok(Box::new(self.spawn()))
+    }
+}
+
+#[async_trait]
+impl<C, F> InterruptFallbackModule<F> for IpiisModule<C>
+where
+    C: IpwisSyscall + Send + Sync + 'static,
+    F: MemoryFamily,
+{
+    async fn spawn_fallback(
+        &self,
+        _task: &InterruptTask,
+    ) -> Result<Box<dyn DynInterruptFallbackHandler<F>>> {
+        Ok(Box::new(self.spawn()))
+    }
+}
+
+pub struct IpiisHandler<C> {
+    client: Arc<C>,
+    target: AccountRef,
+}
+
+impl<C> IpiisHandler<C>
+where
+    C: IpwisSyscall + Send + Sync,
+{
+    async fn forward(&self, request: SyscallRequest) -> Result<AlignedVec> {
+        let outputs = self.client.syscall(&self.target, request).await?;
+
+        // note: the outputs are archived by the remote kernel, so keep them aligned
+        let mut buf = AlignedVec::with_capacity(outputs.len());
+        buf.extend_from_slice(&outputs);
+        Ok(buf)
+    }
+}
+
+#[async_trait]
+impl<C, M> InterruptHandler<M> for IpiisHandler<C>
+where
+    C: IpwisSyscall + Send + Sync,
+    M: Memory,
+{
+    async unsafe fn handle_raw(&mut self, _memory: &mut M, inputs: &[u8]) -> Result<AlignedVec> {
+        // note: the guest may forward a syscall explicitly
+        self.forward(PinnedInner::deserialize_owned(inputs)?).await
+    }
+
+    async fn release(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<C, M> InterruptFallbackHandler<M> for IpiisHandler<C>
+where
+    C: IpwisSyscall + Send + Sync,
+    M: Memory,
+{
+    async fn handle_fallback(
+        &self,
+        _memory: &mut M,
+        id: &str,
+        inputs: &[u8],
+    ) -> Result<AlignedVec> {
+        self.forward(SyscallRequest {
+            id: id.to_string(),
+            inputs: inputs.to_vec(),
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use ipis::{
+        async_trait::async_trait,
+        core::{
+            account::{Account, AccountRef},
+            anyhow::{bail, Result},
+        },
+        tokio,
+    };
+    use ipwis_kernel_common::{
+        error::ExternError,
+        interrupt::{InterruptFallbackHandler, InterruptHandler},
+    };
+    use ipwis_modules_ipiis_common::{IpwisSyscall, SyscallRequest};
+    use ipwis_testkit::{handler::encode, memory::VecMemory};
+
+    use super::IpiisModule;
+
+    /// An in-process stand-in of the remote kernel, which reverses the inputs.
+    #[derive(Default)]
+    struct LocalServer {
+        requests: Mutex<Vec<(AccountRef, SyscallRequest)>>,
+    }
+
+    #[async_trait]
+    impl IpwisSyscall for LocalServer {
+        async fn syscall(&self, target: &AccountRef, request: SyscallRequest) -> Result<Vec<u8>> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((*target, request.clone()));
+
+            if request.id == "unknown" {
+                bail!(ExternError::not_found("no such module: unknown"));
+            }
+            Ok(request.inputs.into_iter().rev().collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward() {
+        let mut memory = VecMemory::default();
+
+        let server = Arc::new(LocalServer::default());
+        let target = Account::generate().account_ref();
+        let module = IpiisModule::new(server.clone(), target);
+
+        // forward an unhandled syscall
+        let handler = module.spawn();
+        let outputs = InterruptFallbackHandler::<VecMemory>::handle_fallback(
+            &handler,
+            &mut memory,
+            "foo",
+            b"bar",
+        )
+        .await
+        .unwrap();
+        assert_eq!(outputs.as_slice(), b"rab");
+
+        // forward a syscall explicitly
+        let mut handler = module.spawn();
+        let request = SyscallRequest {
+            id: "foo".to_string(),
+            inputs: b"bar".to_vec(),
+        };
+        let inputs = encode(&request).unwrap();
+        let outputs = unsafe {
+            InterruptHandler::<VecMemory>::handle_raw(&mut handler, &mut memory, &inputs)
+        }
+        .await
+        .unwrap();
+        assert_eq!(outputs.as_slice(), b"rab");
+
+        // the remote errors are returned to the guest
+        assert!(InterruptFallbackHandler::<VecMemory>::handle_fallback(
+            &handler,
+            &mut memory,
+            "unknown",
+            &[],
+        )
+        .await
+        .is_err());
+
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|(account, _)| *account == target));
+        assert_eq!(requests[1].1, request);
+    }
+}
@@ -0,0 +1,43 @@
+use ipiis_common::{external_call, Ipiis, ServerResult};
+use ipis::{
+    async_trait::async_trait,
+    core::{account::AccountRef, anyhow::Result},
+};
+use ipwis_common::KIND;
+use ipwis_kernel_common::interrupt::InterruptId;
+
+pub use ipwis_common::SyscallRequest;
+
+pub const ID: InterruptId = InterruptId("ipwis_modules_ipiis");
+
+#[async_trait]
+pub trait IpwisSyscall {
+    async fn syscall(&self, target: &AccountRef, request: SyscallRequest) -> Result<Vec<u8>>;
+}
+
+#[async_trait]
+impl<IpiisClient> IpwisSyscall for IpiisClient
+where
+    IpiisClient: Ipiis + Send + Sync,
+{
+    async fn syscall(&self, target: &AccountRef, request: SyscallRequest) -> Result<Vec<u8>> {
+        // external call
+        let (outputs,) = external_call!(
+            client: self,
+            target: KIND.as_ref() => target,
+            request: ::ipwis_common::io => Syscall,
+            sign: self.sign(*target, request)?,
+            inputs: { },
+            outputs: { outputs, },
+        );
+
+        // unpack response
+        Ok(outputs)
+    }
+}
+
+/// Handles the syscalls, which are forwarded from the remote kernels.
+#[async_trait]
+pub trait IpwisSyscallReceiver {
+    async fn receive(&self, guarantee: &AccountRef, request: SyscallRequest) -> Result<Vec<u8>>;
+}
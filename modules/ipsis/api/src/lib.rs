@@ -0,0 +1,653 @@
+#![allow(clippy::missing_safety_doc)]
+
+pub extern crate ipwis_modules_ipsis_common as common;
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+use std::{io::Cursor, sync::Arc};
+
+use ipiis_common::Ipiis;
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::{AccountRef, GuaranteeSigned},
+        anyhow::{bail, Result},
+        signed::IsSigned,
+        value::hash::Hash,
+    },
+    path::Path,
+    pin::PinnedInner,
+    rkyv::AlignedVec,
+    tokio::{
+        self,
+        io::{AsyncRead, AsyncWrite, ReadBuf},
+        task::JoinHandle,
+    },
+};
+use ipsis_common::Ipsis;
+use ipwis_kernel_common::{
+    error::ExternError,
+    interrupt::{
+        DynInterruptHandler, InterruptHandler, InterruptId, InterruptModule, InterruptTask,
+    },
+    memory::{Memory, MemoryFamily},
+};
+use ipwis_modules_ipsis_common::io;
+use ipwis_modules_stream_api::{SharedStreamTable, StreamTable};
+
+/// The IPSIS operations, which are signed by the runtime.
+#[async_trait]
+pub trait IpsisStore {
+    fn account_me(&self) -> AccountRef;
+
+    async fn sign_path(&self, path: Path) -> Result<GuaranteeSigned<Path>>;
+
+    async fn get_raw(&self, path: &GuaranteeSigned<Path>) -> Result<Vec<u8>>;
+
+    async fn put_raw(&self, path: &GuaranteeSigned<Path>, data: Vec<u8>) -> Result<()>;
+
+    async fn contains(&self, path: &GuaranteeSigned<Path>) -> Result<bool>;
+}
+
+#[async_trait]
+impl<IpiisClient> IpsisStore for IpiisClient
+where
+    IpiisClient: Ipiis + Ipsis + Send + Sync,
+{
+    fn account_me(&self) -> AccountRef {
+        Ipiis::account_me(self).account_ref()
+    }
+
+    async fn sign_path(&self, path: Path) -> Result<GuaranteeSigned<Path>> {
+        // next target
+        let target = self
+            .get_account_primary(::ipsis_common::KIND.as_ref())
+            .await?;
+
+        // sign data
+        self.sign(target, path)
+    }
+
+    async fn get_raw(&self, path: &GuaranteeSigned<Path>) -> Result<Vec<u8>> {
+        Ipsis::get_raw(self, path).await
+    }
+
+    async fn put_raw(&self, path: &GuaranteeSigned<Path>, data: Vec<u8>) -> Result<()> {
+        Ipsis::put_raw(self, path, ::std::io::Cursor::new(data)).await
+    }
+
+    async fn contains(&self, path: &GuaranteeSigned<Path>) -> Result<bool> {
+        Ipsis::contains(self, path).await
+    }
+}
+
+/// The maximum size of the objects, which are buffered by the host.
+pub const DEFAULT_MAX_OBJECT_LEN: u64 = 64 * 1024 * 1024;
+
+/// The maximum size of all the objects, which are buffered by the host for a task.
+pub const DEFAULT_MAX_BUFFERED_LEN: u64 = 2 * DEFAULT_MAX_OBJECT_LEN;
+
+pub struct IpsisModule<S> {
+    store: Arc<S>,
+    max_object_len: u64,
+    max_buffered_len: u64,
+}
+
+impl<S> IpsisModule<S> {
+    pub fn new(store: Arc<S>) -> Self {
+        Self {
+            store,
+            max_object_len: DEFAULT_MAX_OBJECT_LEN,
+            max_buffered_len: DEFAULT_MAX_BUFFERED_LEN,
+        }
+    }
+
+    /// Sets the maximum size of the objects, which the tasks can get or put.
+    pub fn with_max_object_len(mut self, len: u64) -> Self {
+        self.max_object_len = len;
+        self
+    }
+
+    /// Sets the maximum size of all the objects, which each task can open at once.
+    pub fn with_max_buffered_len(mut self, len: u64) -> Self {
+        self.max_buffered_len = len;
+        self
+    }
+}
+
+#[async_trait]
+impl<S, F> InterruptModule<F> for IpsisModule<S>
+where
+    S: IpsisStore + Send + Sync + 'static,
+    F: MemoryFamily,
+{
+    fn id(&self) -> InterruptId {
+        io::OpCode::ID
+    }
+
+    async fn spawn_handler(&self, task: &InterruptTask) -> Result<Box<dyn DynInterruptHandler<F>>> {
+        IpsisHandler::with_task(self.store.clone(), task)
+            .map(|handler| {
+                handler
+                    .with_max_object_len(self.max_object_len)
+                    .with_max_buffered_len(self.max_buffered_len)
+            })
+            .map(|handler| Box::new(handler) as Box<dyn DynInterruptHandler<F>>)
+    }
+}
+
+pub struct IpsisHandler<S> {
+    store: Arc<S>,
+    streams: SharedStreamTable,
+    max_object_len: u64,
+    max_buffered_len: u64,
+    /// The bytes, which are buffered by the opened readers and writers.
+    buffered: Arc<AtomicU64>,
+}
+
+impl<S> IpsisHandler<S>
+where
+    S: IpsisStore + Send + Sync + 'static,
+{
+    pub fn with_task(store: Arc<S>, task: &InterruptTask) -> Result<Self> {
+        // note: the runtime can sign only for the tasks which it guarantees
+        if task.ctx.guarantor.account != store.account_me() {
+            bail!(ExternError::permission_denied(
+                "the task is not guaranteed by this runtime",
+            ));
+        }
+
+        Ok(Self {
+            store,
+            streams: StreamTable::with_task(task),
+            max_object_len: DEFAULT_MAX_OBJECT_LEN,
+            max_buffered_len: DEFAULT_MAX_BUFFERED_LEN,
+            buffered: Default::default(),
+        })
+    }
+
+    pub fn with_max_object_len(mut self, len: u64) -> Self {
+        self.max_object_len = len;
+        self
+    }
+
+    pub fn with_max_buffered_len(mut self, len: u64) -> Self {
+        self.max_buffered_len = len;
+        self
+    }
+
+    /// Reserves the buffer, which is released when the reader or writer is dropped.
+    fn reserve(&self, len: u64) -> Result<Reservation> {
+        let max = self.max_buffered_len;
+        match self
+            .buffered
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |buffered| {
+                buffered
+                    .checked_add(len)
+                    .filter(|&buffered| buffered <= max)
+            }) {
+            Ok(_) => Ok(Reservation {
+                buffered: self.buffered.clone(),
+                len,
+            }),
+            Err(buffered) => bail!(ExternError::limit_exceeded(format!(
+                "too many objects are buffered: {buffered} + {len} > {max}"
+            ))),
+        }
+    }
+
+    fn check_object_len(&self, len: u64) -> Result<()> {
+        if len > self.max_object_len {
+            bail!(ExternError::limit_exceeded(format!(
+                "the object is too large: {len} > {}",
+                self.max_object_len,
+            )));
+        }
+        Ok(())
+    }
+
+    async fn handle_get(&mut self, req: io::request::Get) -> Result<io::response::Get> {
+        self.check_object_len(req.path.len)?;
+        let reservation = self.reserve(req.path.len)?;
+        let path = self.store.sign_path(req.path).await?;
+        let data = self.store.get_raw(&path).await?;
+        // note: the path may lie about the length
+        if data.len() as u64 > reservation.len {
+            bail!(ExternError::limit_exceeded(format!(
+                "the object is larger than its path: {} > {}",
+                data.len(),
+                reservation.len,
+            )));
+        }
+
+        let len = data.len();
+        let reader = self.streams.lock().unwrap().reader_new(
+            BufferedReader {
+                inner: Cursor::new(data),
+                _reservation: reservation,
+            },
+            len,
+        )?;
+
+        Ok(io::response::Get {
+            id: reader.id(),
+            len: len.try_into()?,
+        })
+    }
+
+    async fn handle_put(&mut self, req: io::request::Put) -> Result<io::response::Put> {
+        // note: the object is buffered by the host until it is shut down
+        self.check_object_len(req.path.len)?;
+        let len = req.path.len;
+        let reservation = self.reserve(len)?;
+        let path = self.store.sign_path(req.path).await?;
+
+        let writer = self.streams.lock().unwrap().writer_new(UploadWriter {
+            store: self.store.clone(),
+            path: Some(path),
+            len,
+            buf: Default::default(),
+            upload: None,
+            _reservation: reservation,
+        })?;
+
+        Ok(io::response::Put { id: writer.id() })
+    }
+
+    async fn handle_contains(
+        &mut self,
+        req: io::request::Contains,
+    ) -> Result<io::response::Contains> {
+        let path = self.store.sign_path(req.path).await?;
+
+        Ok(io::response::Contains {
+            contains: self.store.contains(&path).await?,
+        })
+    }
+}
+
+#[async_trait]
+impl<S, M> InterruptHandler<M> for IpsisHandler<S>
+where
+    S: IpsisStore + Send + Sync + 'static,
+    M: Memory,
+{
+    async unsafe fn handle_raw(&mut self, _memory: &mut M, inputs: &[u8]) -> Result<AlignedVec> {
+        match PinnedInner::deserialize_owned(inputs)? {
+            io::OpCode::Get(req) => self.handle_get(req).await?.to_bytes().map_err(Into::into),
+            io::OpCode::Put(req) => self.handle_put(req).await?.to_bytes().map_err(Into::into),
+            io::OpCode::Contains(req) => self
+                .handle_contains(req)
+                .await?
+                .to_bytes()
+                .map_err(Into::into),
+        }
+    }
+
+    async fn release(&mut self) -> Result<()> {
+        // note: the streams are released by the stream module
+        Ok(())
+    }
+}
+
+/// The buffered bytes of a task, which are released on drop.
+struct Reservation {
+    buffered: Arc<AtomicU64>,
+    len: u64,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.buffered.fetch_sub(self.len, Ordering::SeqCst);
+    }
+}
+
+/// Reads the object, which is buffered by the host.
+struct BufferedReader {
+    inner: Cursor<Vec<u8>>,
+    _reservation: Reservation,
+}
+
+impl AsyncRead for BufferedReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<tokio::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+/// Buffers the object, and then stores it when it is shut down and verified.
+struct UploadWriter<S> {
+    store: Arc<S>,
+    path: Option<GuaranteeSigned<Path>>,
+    len: u64,
+    buf: Vec<u8>,
+    upload: Option<JoinHandle<Result<()>>>,
+    _reservation: Reservation,
+}
+
+impl<S> AsyncWrite for UploadWriter<S>
+where
+    S: IpsisStore + Send + Sync + 'static,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<tokio::io::Result<usize>> {
+        let this = self.get_mut();
+
+        if this.path.is_none() {
+            return Poll::Ready(Err(tokio::io::Error::new(
+                tokio::io::ErrorKind::BrokenPipe,
+                "the object is already stored",
+            )));
+        }
+        // note: the guest cannot buffer more than the object
+        if this.buf.len() as u64 + buf.len() as u64 > this.len {
+            return Poll::Ready(Err(tokio::io::Error::new(
+                tokio::io::ErrorKind::InvalidInput,
+                "the data overflows the object",
+            )));
+        }
+
+        this.buf.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<tokio::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<tokio::io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.upload.is_none() {
+            let path = match this.path.as_ref() {
+                Some(path) => path,
+                None => return Poll::Ready(Ok(())),
+            };
+
+            // note: only the object, which matches the path, is stored
+            if this.buf.len() as u64 != this.len {
+                return Poll::Ready(Err(tokio::io::Error::new(
+                    tokio::io::ErrorKind::UnexpectedEof,
+                    "the object is incomplete",
+                )));
+            }
+            if Hash::with_bytes(&this.buf) != path.data.data.value {
+                return Poll::Ready(Err(tokio::io::Error::new(
+                    tokio::io::ErrorKind::InvalidData,
+                    "the object mismatches the path",
+                )));
+            }
+
+            let path = this.path.take().unwrap();
+            let store = this.store.clone();
+            let data = ::core::mem::take(&mut this.buf);
+
+            this.upload.replace(tokio::spawn(
+                async move { store.put_raw(&path, data).await },
+            ));
+        }
+
+        let result = match Pin::new(this.upload.as_mut().unwrap()).poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        this.upload.take();
+
+        Poll::Ready(match result {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(error)) => Err(tokio::io::Error::new(tokio::io::ErrorKind::Other, error)),
+            Err(error) => Err(error.into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use ipiis_api::{client::IpiisClient, common::Ipiis};
+    use ipis::{
+        async_trait::async_trait,
+        core::{
+            account::{AccountRef, GuaranteeSigned},
+            anyhow::{bail, Result},
+            value::hash::Hash,
+        },
+        env::Infer,
+        path::Path,
+        tokio,
+    };
+    use ipwis_kernel_common::error::{ErrorCode, ExternError};
+    use ipwis_modules_ipsis_common::io;
+    use ipwis_modules_stream_api::{common::io as stream_io, StreamHandler};
+    use ipwis_testkit::{
+        handler::{call, task},
+        memory::VecMemory,
+    };
+
+    use super::{IpsisHandler, IpsisStore};
+
+    /// An in-memory stand-in of IPSIS.
+    struct MemoryIpsis {
+        client: IpiisClient,
+        objects: Mutex<Vec<(Path, Vec<u8>)>>,
+    }
+
+    #[async_trait]
+    impl IpsisStore for MemoryIpsis {
+        fn account_me(&self) -> AccountRef {
+            self.client.account_me().account_ref()
+        }
+
+        async fn sign_path(&self, path: Path) -> Result<GuaranteeSigned<Path>> {
+            self.client.sign(self.account_me(), path)
+        }
+
+        async fn get_raw(&self, path: &GuaranteeSigned<Path>) -> Result<Vec<u8>> {
+            match self
+                .objects
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(key, _)| *key == path.data.data)
+            {
+                Some((_, data)) => Ok(data.clone()),
+                None => bail!(ExternError::not_found("no such object")),
+            }
+        }
+
+        async fn put_raw(&self, path: &GuaranteeSigned<Path>, data: Vec<u8>) -> Result<()> {
+            self.objects
+                .lock()
+                .unwrap()
+                .push((path.data.data.clone(), data));
+            Ok(())
+        }
+
+        async fn contains(&self, path: &GuaranteeSigned<Path>) -> Result<bool> {
+            Ok(self
+                .objects
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|(key, _)| *key == path.data.data))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_put_and_get() {
+        let mut memory = VecMemory::default();
+
+        let client = IpiisClient::infer().await;
+        let task = task(&client).unwrap();
+        let store = Arc::new(MemoryIpsis {
+            client,
+            objects: Default::default(),
+        });
+
+        // note: the streams are shared between the modules of the task
+        let mut handler = IpsisHandler::with_task(store.clone(), &task).unwrap();
+        let mut streams = StreamHandler::with_task(&task);
+
+        let data = b"hello world";
+        let path = Path {
+            value: Hash::with_bytes(data),
+            len: data.len() as u64,
+        };
+
+        let contains = io::OpCode::Contains(io::request::Contains { path: path.clone() });
+        let outputs: io::response::Contains =
+            call(&mut handler, &mut memory, &contains).await.unwrap();
+        assert!(!outputs.contains);
+
+        // put the object via a writer
+        let opcode = io::OpCode::Put(io::request::Put { path: path.clone() });
+        let outputs: io::response::Put = call(&mut handler, &mut memory, &opcode).await.unwrap();
+        let writer = outputs.id;
+
+        let buf = memory.insert(data).unwrap();
+        let opcode =
+            stream_io::OpCode::WriterNext(stream_io::request::WriterNext { id: writer, buf });
+        let outputs: stream_io::response::WriterNext =
+            call(&mut streams, &mut memory, &opcode).await.unwrap();
+        assert_eq!(outputs.len as usize, data.len());
+
+        let opcode =
+            stream_io::OpCode::WriterShutdown(stream_io::request::WriterShutdown { id: writer });
+        let _: stream_io::response::WriterShutdown =
+            call(&mut streams, &mut memory, &opcode).await.unwrap();
+
+        let outputs: io::response::Contains =
+            call(&mut handler, &mut memory, &contains).await.unwrap();
+        assert!(outputs.contains);
+
+        // get the object via a reader
+        let opcode = io::OpCode::Get(io::request::Get { path });
+        let outputs: io::response::Get = call(&mut handler, &mut memory, &opcode).await.unwrap();
+        assert_eq!(outputs.len as usize, data.len());
+
+        let buf = memory.alloc(outputs.len, 1).unwrap();
+        let opcode = stream_io::OpCode::ReaderNext(stream_io::request::ReaderNext {
+            id: outputs.id,
+            buf,
+        });
+        let outputs: stream_io::response::ReaderNext =
+            call(&mut streams, &mut memory, &opcode).await.unwrap();
+        assert_eq!(outputs.len as usize, data.len());
+        assert_eq!(memory.read_raw(buf).unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_put_verified() {
+        let mut memory = VecMemory::default();
+
+        let client = IpiisClient::infer().await;
+        let task = task(&client).unwrap();
+        let store = Arc::new(MemoryIpsis {
+            client,
+            objects: Default::default(),
+        });
+
+        let mut handler = IpsisHandler::with_task(store.clone(), &task)
+            .unwrap()
+            .with_max_object_len(8);
+        let mut streams = StreamHandler::with_task(&task);
+
+        // test the objects over the limit are rejected before being buffered
+        let opcode = io::OpCode::Put(io::request::Put {
+            path: Path {
+                value: Hash::with_bytes(b"hello world"),
+                len: 11,
+            },
+        });
+        let error = call::<_, _, io::response::Put>(&mut handler, &mut memory, &opcode)
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::LimitExceeded);
+
+        // test the object mismatching the path is not stored
+        let path = Path {
+            value: Hash::with_bytes(b"hello"),
+            len: 5,
+        };
+        let opcode = io::OpCode::Put(io::request::Put { path: path.clone() });
+        let outputs: io::response::Put = call(&mut handler, &mut memory, &opcode).await.unwrap();
+        let writer = outputs.id;
+
+        let buf = memory.insert(b"world").unwrap();
+        let opcode =
+            stream_io::OpCode::WriterNext(stream_io::request::WriterNext { id: writer, buf });
+        let _: stream_io::response::WriterNext =
+            call(&mut streams, &mut memory, &opcode).await.unwrap();
+
+        let opcode =
+            stream_io::OpCode::WriterShutdown(stream_io::request::WriterShutdown { id: writer });
+        assert!(call::<_, _, stream_io::response::WriterShutdown>(
+            &mut streams,
+            &mut memory,
+            &opcode
+        )
+        .await
+        .is_err());
+
+        let contains = io::OpCode::Contains(io::request::Contains { path });
+        let outputs: io::response::Contains =
+            call(&mut handler, &mut memory, &contains).await.unwrap();
+        assert!(!outputs.contains);
+    }
+
+    #[tokio::test]
+    async fn test_buffered_limit() {
+        let mut memory = VecMemory::default();
+
+        let client = IpiisClient::infer().await;
+        let task = task(&client).unwrap();
+        let store = Arc::new(MemoryIpsis {
+            client,
+            objects: Default::default(),
+        });
+
+        let mut handler = IpsisHandler::with_task(store.clone(), &task)
+            .unwrap()
+            .with_max_buffered_len(8);
+        let mut streams = StreamHandler::with_task(&task);
+
+        let opcode = io::OpCode::Put(io::request::Put {
+            path: Path {
+                value: Hash::with_bytes(b"hello"),
+                len: 5,
+            },
+        });
+        let outputs: io::response::Put = call(&mut handler, &mut memory, &opcode).await.unwrap();
+        let writer = outputs.id;
+
+        // test the buffers are limited across the writers of the task
+        let error = call::<_, _, io::response::Put>(&mut handler, &mut memory, &opcode)
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::LimitExceeded);
+
+        // test the buffer is released when the writer is closed, though it is incomplete
+        let close = stream_io::OpCode::WriterClose(stream_io::request::WriterClose { id: writer });
+        assert!(
+            call::<_, _, stream_io::response::WriterClose>(&mut streams, &mut memory, &close)
+                .await
+                .is_err()
+        );
+        let _: io::response::Put = call(&mut handler, &mut memory, &opcode).await.unwrap();
+    }
+}
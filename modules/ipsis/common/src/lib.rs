@@ -0,0 +1,110 @@
+use bytecheck::CheckBytes;
+use ipis::{core::anyhow::Result, path::Path};
+use ipwis_kernel_common::{data::ExternDataRef, interrupt::InterruptId, resource::ResourceId};
+use ipwis_modules_stream_common::{ExternReader, ExternWriter};
+use rkyv::{
+    de::deserializers::SharedDeserializeMap, validation::validators::DefaultValidator, Archive,
+    Deserialize, Serialize,
+};
+
+/// Opens a reader of the object.
+pub unsafe fn get(path: Path) -> Result<ExternReader> {
+    let res: io::response::Get = io::OpCode::Get(io::request::Get { path }).syscall()?;
+    Ok(ExternReader::new(res.id, res.len))
+}
+
+/// Opens a writer of the object, which is stored when the writer is shut down.
+pub unsafe fn put(path: Path) -> Result<ExternWriter> {
+    let res: io::response::Put = io::OpCode::Put(io::request::Put { path }).syscall()?;
+    Ok(ExternWriter::new(res.id))
+}
+
+pub unsafe fn contains(path: Path) -> Result<bool> {
+    let res: io::response::Contains =
+        io::OpCode::Contains(io::request::Contains { path }).syscall()?;
+    Ok(res.contains)
+}
+
+pub mod io {
+    use super::*;
+
+    #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+    #[archive_attr(derive(CheckBytes))]
+    pub enum OpCode {
+        Get(self::request::Get),
+        Put(self::request::Put),
+        Contains(self::request::Contains),
+    }
+
+    impl ::ipis::core::signed::IsSigned for OpCode {}
+
+    impl OpCode {
+        pub const ID: InterruptId = InterruptId("ipwis_modules_ipsis");
+
+        pub(crate) unsafe fn syscall<O>(mut self) -> Result<O>
+        where
+            O: Archive,
+            <O as Archive>::Archived:
+                for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+        {
+            Self::ID.syscall(&mut self)
+        }
+    }
+
+    pub mod request {
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Get {
+            pub path: Path,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Get {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Put {
+            pub path: Path,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Put {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Contains {
+            pub path: Path,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Contains {}
+    }
+
+    pub mod response {
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Get {
+            pub id: ResourceId,
+            pub len: ExternDataRef,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Get {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Put {
+            pub id: ResourceId,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Put {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Contains {
+            pub contains: bool,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Contains {}
+    }
+}
@@ -0,0 +1,464 @@
+#![allow(clippy::missing_safety_doc)]
+
+pub extern crate ipwis_modules_kv_common as common;
+
+use core::ops::Bound;
+
+use bytecheck::CheckBytes;
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::AccountRef,
+        anyhow::{bail, Result},
+        signed::IsSigned,
+    },
+    path::Path,
+    pin::PinnedInner,
+    rkyv::AlignedVec,
+    tokio,
+};
+use ipwis_kernel_common::{
+    error::ExternError,
+    interrupt::{
+        DynInterruptHandler, InterruptHandler, InterruptId, InterruptModule, InterruptTask,
+    },
+    memory::{Memory, MemoryFamily},
+};
+use ipwis_modules_kv_common::io;
+use rkyv::{Archive, Deserialize, Serialize};
+use sled::{
+    transaction::{abort, TransactionError},
+    Db, IVec, Transactional, Tree,
+};
+
+const TREE_NAME_SIZES: &str = "__ipwis_modules_kv_sizes";
+
+const MAX_SCAN_LIMIT: u32 = 1024;
+
+/// The key-value store, which is shared by the tasks of the runtime.
+pub struct KvModule {
+    db: Db,
+    quota: Option<u64>,
+}
+
+impl KvModule {
+    pub fn open(path: impl AsRef<::std::path::Path>) -> Result<Self> {
+        sled::open(path).map(Self::with_db).map_err(Into::into)
+    }
+
+    pub fn with_db(db: Db) -> Self {
+        Self { db, quota: None }
+    }
+
+    /// Sets the quota of the namespaces, which also caps the quotas the tasks have specified.
+    pub fn with_quota(mut self, quota: u64) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+}
+
+#[async_trait]
+impl<F> InterruptModule<F> for KvModule
+where
+    F: MemoryFamily,
+{
+    fn id(&self) -> InterruptId {
+        io::OpCode::ID
+    }
+
+    async fn spawn_handler(&self, task: &InterruptTask) -> Result<Box<dyn DynInterruptHandler<F>>> {
+        KvHandler::with_task(&self.db, task, self.quota)
+            .map(|handler| Box::new(handler) as Box<dyn DynInterruptHandler<F>>)
+    }
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+struct Namespace {
+    guarantee: AccountRef,
+    program: Path,
+}
+
+impl IsSigned for Namespace {}
+
+#[derive(Clone)]
+pub struct KvHandler {
+    namespace: Vec<u8>,
+    data: Tree,
+    sizes: Tree,
+    quota: Option<u64>,
+}
+
+impl KvHandler {
+    pub fn with_task(db: &Db, task: &InterruptTask, quota: Option<u64>) -> Result<Self> {
+        let program = match task.ctx.program.as_ref() {
+            Some(program) => program.data.data.clone(),
+            None => bail!(ExternError::permission_denied(
+                "the anonymous programs have no key-value namespace",
+            )),
+        };
+        let namespace = Namespace {
+            guarantee: task.guarantee(),
+            program,
+        }
+        .to_bytes()?
+        .to_vec();
+
+        Ok(Self {
+            data: db.open_tree(&namespace)?,
+            sizes: db.open_tree(TREE_NAME_SIZES)?,
+            namespace,
+            // note: the tasks cannot exceed the runtime quota
+            quota: match (task.ctx.constraints.resources.kv_quota, quota) {
+                (Some(task), Some(runtime)) => Some(task.min(runtime)),
+                (task, runtime) => task.or(runtime),
+            },
+        })
+    }
+
+    /// Returns the total size of the keys and values in the namespace.
+    pub fn size(&self) -> Result<u64> {
+        Ok(self
+            .sizes
+            .get(&self.namespace)?
+            .map(|size| decode_size(&size))
+            .unwrap_or_default())
+    }
+
+    fn handle(&self, opcode: io::OpCode) -> Result<AlignedVec> {
+        match opcode {
+            io::OpCode::Get(req) => self.handle_get(req)?.to_bytes().map_err(Into::into),
+            io::OpCode::Put(req) => self.handle_put(req)?.to_bytes().map_err(Into::into),
+            io::OpCode::Delete(req) => self.handle_delete(req)?.to_bytes().map_err(Into::into),
+            io::OpCode::Scan(req) => self.handle_scan(req)?.to_bytes().map_err(Into::into),
+            io::OpCode::CompareAndSwap(req) => self
+                .handle_compare_and_swap(req)?
+                .to_bytes()
+                .map_err(Into::into),
+        }
+    }
+
+    fn handle_get(&self, req: io::request::Get) -> Result<io::response::Get> {
+        Ok(io::response::Get {
+            value: self.data.get(&req.key)?.map(|value| value.to_vec()),
+        })
+    }
+
+    fn handle_put(&self, req: io::request::Put) -> Result<io::response::Put> {
+        match self.swap(&req.key, None, Some(&req.value))? {
+            Ok(old) => Ok(io::response::Put {
+                old: old.map(|old| old.to_vec()),
+            }),
+            Err(_) => unreachable!("unconditional swap"),
+        }
+    }
+
+    fn handle_delete(&self, req: io::request::Delete) -> Result<io::response::Delete> {
+        match self.swap(&req.key, None, None)? {
+            Ok(old) => Ok(io::response::Delete {
+                old: old.map(|old| old.to_vec()),
+            }),
+            Err(_) => unreachable!("unconditional swap"),
+        }
+    }
+
+    fn handle_scan(&self, req: io::request::Scan) -> Result<io::response::Scan> {
+        // note: the cursor may sort before the prefix
+        let start = match req.after.as_ref() {
+            Some(after) if *after >= req.prefix => Bound::Excluded(after.as_slice()),
+            _ => Bound::Included(req.prefix.as_slice()),
+        };
+
+        let mut entries = vec![];
+        for entry in self.data.range::<&[u8], _>((start, Bound::Unbounded)) {
+            let (key, value) = entry?;
+            if !key.starts_with(&req.prefix)
+                || entries.len() >= req.limit.min(MAX_SCAN_LIMIT) as usize
+            {
+                break;
+            }
+            entries.push(io::response::Entry {
+                key: key.to_vec(),
+                value: value.to_vec(),
+            });
+        }
+        Ok(io::response::Scan { entries })
+    }
+
+    fn handle_compare_and_swap(
+        &self,
+        req: io::request::CompareAndSwap,
+    ) -> Result<io::response::CompareAndSwap> {
+        match self.swap(&req.key, Some(req.old.as_deref()), req.new.as_deref())? {
+            Ok(_) => Ok(io::response::CompareAndSwap {
+                swapped: true,
+                current: req.new,
+            }),
+            Err(current) => Ok(io::response::CompareAndSwap {
+                swapped: false,
+                current: current.map(|current| current.to_vec()),
+            }),
+        }
+    }
+
+    /// Replaces the value and the namespace size at once.
+    ///
+    /// If the `expected` value mismatches, the current value is returned as an error.
+    fn swap(
+        &self,
+        key: &[u8],
+        expected: Option<Option<&[u8]>>,
+        new: Option<&[u8]>,
+    ) -> Result<::core::result::Result<Option<IVec>, Option<IVec>>> {
+        let entry_size = |value: Option<&[u8]>| {
+            value
+                .map(|value| (key.len() + value.len()) as u64)
+                .unwrap_or_default()
+        };
+
+        let result = (&self.data, &self.sizes).transaction(|(data, sizes)| {
+            let current = data.get(key)?;
+            if let Some(expected) = expected {
+                if current.as_deref() != expected {
+                    return Ok(Err(current));
+                }
+            }
+
+            let size = sizes
+                .get(&self.namespace)?
+                .map(|size| decode_size(&size))
+                .unwrap_or_default();
+            let (old_size, new_size) = (entry_size(current.as_deref()), entry_size(new));
+            let size = size.saturating_sub(old_size) + new_size;
+
+            // note: the shrinking updates are always allowed
+            if let Some(quota) = self.quota {
+                if new_size > old_size && size > quota {
+                    return abort(ExternError::limit_exceeded(format!(
+                        "the key-value quota is exceeded: {size} > {quota}",
+                    )));
+                }
+            }
+
+            match new {
+                Some(value) => data.insert(key, value)?,
+                None => data.remove(key)?,
+            };
+            sizes.insert(self.namespace.as_slice(), &size.to_be_bytes()[..])?;
+            Ok(Ok(current))
+        });
+
+        match result {
+            Ok(result) => Ok(result),
+            Err(TransactionError::Abort(error)) => bail!(error),
+            Err(TransactionError::Storage(error)) => Err(error.into()),
+        }
+    }
+}
+
+fn decode_size(size: &[u8]) -> u64 {
+    size.try_into().map(u64::from_be_bytes).unwrap_or_default()
+}
+
+#[async_trait]
+impl<M> InterruptHandler<M> for KvHandler
+where
+    M: Memory,
+{
+    async unsafe fn handle_raw(&mut self, _memory: &mut M, inputs: &[u8]) -> Result<AlignedVec> {
+        let opcode = PinnedInner::deserialize_owned(inputs)?;
+
+        // note: the sled transactions may block on IO, so they are moved out of the async runtime
+        let handler = self.clone();
+        tokio::task::spawn_blocking(move || handler.handle(opcode)).await?
+    }
+
+    async fn release(&mut self) -> Result<()> {
+        self.data.flush_async().await?;
+        self.sizes.flush_async().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ipiis_api::{client::IpiisClient, common::Ipiis};
+    use ipis::{core::value::hash::Hash, env::Infer, path::Path, tokio};
+    use ipwis_kernel_common::task::TaskCtx;
+    use ipwis_modules_kv_common::io;
+    use ipwis_testkit::{
+        handler::{call, task_with_ctx},
+        memory::VecMemory,
+    };
+
+    use super::KvHandler;
+
+    fn program(client: &IpiisClient, name: &str, quota: Option<u64>) -> TaskCtx {
+        let path = Path {
+            value: Hash::with_str(name),
+            len: 0,
+        };
+
+        let mut ctx = TaskCtx::new_sandbox();
+        ctx.program = Some(
+            client
+                .sign(client.account_me().account_ref(), path)
+                .unwrap(),
+        );
+        ctx.constraints.resources.kv_quota = quota;
+        ctx
+    }
+
+    #[tokio::test]
+    async fn test_namespace_and_quota() {
+        let mut memory = VecMemory::default();
+
+        let client = IpiisClient::infer().await;
+        let db = sled::Config::new().temporary(true).open().unwrap();
+
+        let task_a = task_with_ctx(&client, program(&client, "a", Some(16))).unwrap();
+        let task_b = task_with_ctx(&client, program(&client, "b", None)).unwrap();
+        let mut handler_a = KvHandler::with_task(&db, &task_a, None).unwrap();
+        let mut handler_b = KvHandler::with_task(&db, &task_b, None).unwrap();
+
+        let put = |key: &[u8], value: &[u8]| {
+            io::OpCode::Put(io::request::Put {
+                key: key.to_vec(),
+                value: value.to_vec(),
+            })
+        };
+        let get = |key: &[u8]| io::OpCode::Get(io::request::Get { key: key.to_vec() });
+
+        let _: io::response::Put = call(&mut handler_a, &mut memory, &put(b"key", b"value"))
+            .await
+            .unwrap();
+        assert_eq!(handler_a.size().unwrap(), 8);
+
+        // test the namespaces are isolated
+        let outputs: io::response::Get = call(&mut handler_b, &mut memory, &get(b"key"))
+            .await
+            .unwrap();
+        assert_eq!(outputs.value, None);
+
+        // test the quota is enforced
+        assert!(call::<_, _, io::response::Put>(
+            &mut handler_a,
+            &mut memory,
+            &put(b"foo", b"barbaz")
+        )
+        .await
+        .is_err());
+        assert_eq!(handler_a.size().unwrap(), 8);
+
+        // test compare-and-swap
+        let cas = |old: &[u8], new: &[u8]| {
+            io::OpCode::CompareAndSwap(io::request::CompareAndSwap {
+                key: b"key".to_vec(),
+                old: Some(old.to_vec()),
+                new: Some(new.to_vec()),
+            })
+        };
+        let outputs: io::response::CompareAndSwap =
+            call(&mut handler_a, &mut memory, &cas(b"other", b"new"))
+                .await
+                .unwrap();
+        assert!(!outputs.swapped);
+        assert_eq!(outputs.current.as_deref(), Some(&b"value"[..]));
+
+        let outputs: io::response::CompareAndSwap =
+            call(&mut handler_a, &mut memory, &cas(b"value", b"new"))
+                .await
+                .unwrap();
+        assert!(outputs.swapped);
+        assert_eq!(handler_a.size().unwrap(), 6);
+
+        // test scan
+        for key in [&b"k1"[..], b"k2", b"l1"] {
+            let _: io::response::Put = call(&mut handler_b, &mut memory, &put(key, b"v"))
+                .await
+                .unwrap();
+        }
+        let scan = |after: Option<&[u8]>| {
+            io::OpCode::Scan(io::request::Scan {
+                prefix: b"k".to_vec(),
+                after: after.map(<[u8]>::to_vec),
+                limit: 1,
+            })
+        };
+        let outputs: io::response::Scan = call(&mut handler_b, &mut memory, &scan(None))
+            .await
+            .unwrap();
+        assert_eq!(outputs.entries.len(), 1);
+        assert_eq!(outputs.entries[0].key, b"k1");
+
+        let outputs: io::response::Scan = call(&mut handler_b, &mut memory, &scan(Some(b"k1")))
+            .await
+            .unwrap();
+        assert_eq!(outputs.entries.len(), 1);
+        assert_eq!(outputs.entries[0].key, b"k2");
+
+        let outputs: io::response::Scan = call(&mut handler_b, &mut memory, &scan(Some(b"k2")))
+            .await
+            .unwrap();
+        assert!(outputs.entries.is_empty());
+
+        // test the cursor before the prefix starts at the prefix
+        let outputs: io::response::Scan = call(&mut handler_b, &mut memory, &scan(Some(b"a")))
+            .await
+            .unwrap();
+        assert_eq!(outputs.entries.len(), 1);
+        assert_eq!(outputs.entries[0].key, b"k1");
+
+        // test delete
+        let delete = io::OpCode::Delete(io::request::Delete {
+            key: b"key".to_vec(),
+        });
+        let outputs: io::response::Delete =
+            call(&mut handler_a, &mut memory, &delete).await.unwrap();
+        assert_eq!(outputs.old.as_deref(), Some(&b"new"[..]));
+        assert_eq!(handler_a.size().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_runtime_quota() {
+        let mut memory = VecMemory::default();
+
+        let client = IpiisClient::infer().await;
+        let db = sled::Config::new().temporary(true).open().unwrap();
+
+        // test the runtime quota caps the larger task quota, and applies to the others
+        for quota in [Some(1024), None] {
+            let task = task_with_ctx(&client, program(&client, "a", quota)).unwrap();
+            let mut handler = KvHandler::with_task(&db, &task, Some(8)).unwrap();
+
+            let put = |value: &[u8]| {
+                io::OpCode::Put(io::request::Put {
+                    key: b"key".to_vec(),
+                    value: value.to_vec(),
+                })
+            };
+            assert!(
+                call::<_, _, io::response::Put>(&mut handler, &mut memory, &put(b"values"))
+                    .await
+                    .is_err()
+            );
+            let _: io::response::Put = call(&mut handler, &mut memory, &put(b"value"))
+                .await
+                .unwrap();
+            assert_eq!(handler.size().unwrap(), 8);
+        }
+
+        // test the smaller task quota is kept
+        let task = task_with_ctx(&client, program(&client, "b", Some(4))).unwrap();
+        let mut handler = KvHandler::with_task(&db, &task, Some(8)).unwrap();
+        let put = io::OpCode::Put(io::request::Put {
+            key: b"key".to_vec(),
+            value: b"vv".to_vec(),
+        });
+        assert!(
+            call::<_, _, io::response::Put>(&mut handler, &mut memory, &put)
+                .await
+                .is_err()
+        );
+    }
+}
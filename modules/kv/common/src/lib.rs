@@ -0,0 +1,188 @@
+use bytecheck::CheckBytes;
+use ipis::core::anyhow::Result;
+use ipwis_kernel_common::interrupt::InterruptId;
+use rkyv::{
+    de::deserializers::SharedDeserializeMap, validation::validators::DefaultValidator, Archive,
+    Deserialize, Serialize,
+};
+
+pub unsafe fn get(key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+    let res: io::response::Get = io::OpCode::Get(io::request::Get { key }).syscall()?;
+    Ok(res.value)
+}
+
+/// Stores the value, and then returns the former one.
+pub unsafe fn put(key: Vec<u8>, value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+    let res: io::response::Put = io::OpCode::Put(io::request::Put { key, value }).syscall()?;
+    Ok(res.old)
+}
+
+/// Removes the value, and then returns the former one.
+pub unsafe fn delete(key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+    let res: io::response::Delete = io::OpCode::Delete(io::request::Delete { key }).syscall()?;
+    Ok(res.old)
+}
+
+/// Returns the entries which start with the prefix, in the order of their keys.
+///
+/// Pass the last key of the former page as `after` to get the next page.
+pub unsafe fn scan(
+    prefix: Vec<u8>,
+    after: Option<Vec<u8>>,
+    limit: u32,
+) -> Result<Vec<io::response::Entry>> {
+    let res: io::response::Scan = io::OpCode::Scan(io::request::Scan {
+        prefix,
+        after,
+        limit,
+    })
+    .syscall()?;
+    Ok(res.entries)
+}
+
+/// Replaces the value only if the current one equals `old`.
+///
+/// On a mismatch, the current value is returned as an error.
+pub unsafe fn compare_and_swap(
+    key: Vec<u8>,
+    old: Option<Vec<u8>>,
+    new: Option<Vec<u8>>,
+) -> Result<::core::result::Result<(), Option<Vec<u8>>>> {
+    let res: io::response::CompareAndSwap =
+        io::OpCode::CompareAndSwap(io::request::CompareAndSwap { key, old, new }).syscall()?;
+    if res.swapped {
+        Ok(Ok(()))
+    } else {
+        Ok(Err(res.current))
+    }
+}
+
+pub mod io {
+    use super::*;
+
+    #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+    #[archive_attr(derive(CheckBytes))]
+    pub enum OpCode {
+        Get(self::request::Get),
+        Put(self::request::Put),
+        Delete(self::request::Delete),
+        Scan(self::request::Scan),
+        CompareAndSwap(self::request::CompareAndSwap),
+    }
+
+    impl ::ipis::core::signed::IsSigned for OpCode {}
+
+    impl OpCode {
+        pub const ID: InterruptId = InterruptId("ipwis_modules_kv");
+
+        pub(crate) unsafe fn syscall<O>(mut self) -> Result<O>
+        where
+            O: Archive,
+            <O as Archive>::Archived:
+                for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+        {
+            Self::ID.syscall(&mut self)
+        }
+    }
+
+    pub mod request {
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Get {
+            pub key: Vec<u8>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Get {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Put {
+            pub key: Vec<u8>,
+            pub value: Vec<u8>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Put {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Delete {
+            pub key: Vec<u8>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Delete {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Scan {
+            pub prefix: Vec<u8>,
+            pub after: Option<Vec<u8>>,
+            pub limit: u32,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Scan {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct CompareAndSwap {
+            pub key: Vec<u8>,
+            pub old: Option<Vec<u8>>,
+            pub new: Option<Vec<u8>>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for CompareAndSwap {}
+    }
+
+    pub mod response {
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Get {
+            pub value: Option<Vec<u8>>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Get {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Put {
+            pub old: Option<Vec<u8>>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Put {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Delete {
+            pub old: Option<Vec<u8>>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Delete {}
+
+        #[derive(Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Entry {
+            pub key: Vec<u8>,
+            pub value: Vec<u8>,
+        }
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Scan {
+            pub entries: Vec<Entry>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Scan {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct CompareAndSwap {
+            pub swapped: bool,
+            pub current: Option<Vec<u8>>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for CompareAndSwap {}
+    }
+}
@@ -0,0 +1,364 @@
+#![allow(clippy::missing_safety_doc)]
+
+pub extern crate ipwis_modules_log_common as common;
+
+use core::time::Duration;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use ipis::{
+    async_trait::async_trait,
+    core::{anyhow::Result, signed::IsSigned, value::chrono::DateTime},
+    log,
+    pin::PinnedInner,
+    rkyv::AlignedVec,
+};
+use ipwis_kernel_common::{
+    error::ExternError,
+    interrupt::{
+        DynInterruptHandler, InterruptHandler, InterruptId, InterruptModule, InterruptTask,
+    },
+    memory::{Memory, MemoryFamily},
+    task::TaskId,
+};
+use ipwis_modules_log_common::io::{self, response::Record};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LogPolicy {
+    /// The maximum number of the records per second, for each task.
+    pub max_records_per_sec: u32,
+    /// The maximum number of the records, which are kept for each task.
+    pub capacity: usize,
+    /// The maximum number of the released tasks, whose records are kept until the host takes them.
+    pub released_capacity: usize,
+}
+
+impl Default for LogPolicy {
+    fn default() -> Self {
+        Self {
+            max_records_per_sec: 100,
+            capacity: 1024,
+            released_capacity: 256,
+        }
+    }
+}
+
+/// The records of the tasks, which can be retrieved by the host.
+#[derive(Clone, Default)]
+pub struct LogStore {
+    policy: LogPolicy,
+    tasks: Arc<Mutex<HashMap<TaskId, TaskLog>>>,
+    released: Arc<Mutex<VecDeque<TaskId>>>,
+}
+
+struct TaskLog {
+    records: VecDeque<Record>,
+    window: Instant,
+    count: u32,
+    dropped: u64,
+}
+
+impl LogStore {
+    pub fn new(policy: LogPolicy) -> Self {
+        Self {
+            policy,
+            tasks: Default::default(),
+            released: Default::default(),
+        }
+    }
+
+    /// Returns the kept records of the task.
+    pub fn records(&self, id: TaskId) -> Vec<Record> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|log| log.records.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Removes the task, and then returns its kept records.
+    pub fn take(&self, id: TaskId) -> Vec<Record> {
+        let records = self
+            .tasks
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .map(|log| log.records.into())
+            .unwrap_or_default();
+        self.released
+            .lock()
+            .unwrap()
+            .retain(|released| *released != id);
+        records
+    }
+
+    /// Returns the number of the records, which are dropped by the rate limit.
+    pub fn dropped(&self, id: TaskId) -> u64 {
+        self.tasks
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|log| log.dropped)
+            .unwrap_or_default()
+    }
+
+    fn release(&self, id: TaskId) {
+        let evicted: Vec<_> = {
+            let mut released = self.released.lock().unwrap();
+            released.push_back(id);
+
+            // note: the oldest released tasks are evicted, as the host may never take them
+            let len = released.len().saturating_sub(self.policy.released_capacity);
+            released.drain(..len).collect()
+        };
+
+        let mut tasks = self.tasks.lock().unwrap();
+        for id in evicted {
+            tasks.remove(&id);
+        }
+    }
+
+    fn push(&self, record: Record) -> Result<()> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let task = tasks.entry(record.task_id).or_insert_with(|| TaskLog {
+            records: Default::default(),
+            window: Instant::now(),
+            count: 0,
+            dropped: 0,
+        });
+
+        // note: the rate limit is applied on a fixed window of a second
+        if task.window.elapsed() >= Duration::from_secs(1) {
+            task.window = Instant::now();
+            task.count = 0;
+        }
+        if task.count >= self.policy.max_records_per_sec {
+            task.dropped += 1;
+            return Err(ExternError::limit_exceeded(format!(
+                "too many records: > {}/s",
+                self.policy.max_records_per_sec,
+            ))
+            .into());
+        }
+        task.count += 1;
+
+        // note: the guest cannot pose as the host, nor inject the fake lines
+        log::log!(
+            target: &format!("ipwis_guest::{}", &record.target),
+            record.level.into(),
+            "[{:?}] [{:?}] [{:?}] {:?} {:?}",
+            record.task_id,
+            record.resource_id,
+            record.guarantee,
+            &record.message,
+            &record.fields,
+        );
+
+        if task.records.len() >= self.policy.capacity {
+            task.records.pop_front();
+        }
+        task.records.push_back(record);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct LogModule {
+    store: LogStore,
+}
+
+impl LogModule {
+    pub fn new(policy: LogPolicy) -> Self {
+        Self {
+            store: LogStore::new(policy),
+        }
+    }
+
+    pub fn store(&self) -> &LogStore {
+        &self.store
+    }
+}
+
+#[async_trait]
+impl<F> InterruptModule<F> for LogModule
+where
+    F: MemoryFamily,
+{
+    fn id(&self) -> InterruptId {
+        io::OpCode::ID
+    }
+
+    async fn spawn_handler(&self, task: &InterruptTask) -> Result<Box<dyn DynInterruptHandler<F>>> {
+        Ok(Box::new(LogHandler::with_task(self.store.clone(), task)))
+    }
+}
+
+pub struct LogHandler {
+    store: LogStore,
+    task: InterruptTask,
+}
+
+impl LogHandler {
+    pub fn with_task(store: LogStore, task: &InterruptTask) -> Self {
+        Self {
+            store,
+            task: task.clone(),
+        }
+    }
+
+    fn handle_log(&self, req: io::request::Log) -> Result<io::response::Log> {
+        self.store.push(Record {
+            task_id: self.task.id,
+            resource_id: self.task.resource_id,
+            guarantee: self.task.guarantee(),
+            timestamp: DateTime::now(),
+            level: req.level,
+            target: req.target,
+            message: req.message,
+            fields: req.fields,
+        })?;
+        Ok(io::response::Log {})
+    }
+
+    fn handle_records(&self) -> Result<io::response::Records> {
+        Ok(io::response::Records {
+            records: self.store.records(self.task.id),
+        })
+    }
+}
+
+#[async_trait]
+impl<M> InterruptHandler<M> for LogHandler
+where
+    M: Memory,
+{
+    async unsafe fn handle_raw(&mut self, _memory: &mut M, inputs: &[u8]) -> Result<AlignedVec> {
+        match PinnedInner::deserialize_owned(inputs)? {
+            io::OpCode::Log(req) => self.handle_log(req)?.to_bytes().map_err(Into::into),
+            io::OpCode::Records(_) => self.handle_records()?.to_bytes().map_err(Into::into),
+        }
+    }
+
+    async fn release(&mut self) -> Result<()> {
+        // note: the records are kept until the host takes them, or they are evicted
+        self.store.release(self.task.id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ipiis_api::client::IpiisClient;
+    use ipis::{env::Infer, tokio};
+    use ipwis_kernel_common::{
+        error::{ErrorCode, ExternError},
+        interrupt::InterruptHandler,
+        task::TaskId,
+    };
+    use ipwis_modules_log_common::{io, Field, Level};
+    use ipwis_testkit::{
+        handler::{call, task},
+        memory::VecMemory,
+    };
+
+    use super::{LogHandler, LogPolicy, LogStore};
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let mut memory = VecMemory::default();
+
+        let client = IpiisClient::infer().await;
+        let task = task(&client).unwrap();
+        let store = LogStore::new(LogPolicy {
+            max_records_per_sec: 2,
+            capacity: 16,
+            ..Default::default()
+        });
+        let mut handler = LogHandler::with_task(store.clone(), &task);
+
+        let log = |message: &str| {
+            io::OpCode::Log(io::request::Log {
+                level: Level::Info,
+                target: "test".into(),
+                message: message.into(),
+                fields: vec![Field::new("key", "value")],
+            })
+        };
+
+        for message in ["hello", "world"] {
+            let _: io::response::Log = call(&mut handler, &mut memory, &log(message))
+                .await
+                .unwrap();
+        }
+
+        // test the records are dropped beyond the rate limit
+        let error = call::<_, _, io::response::Log>(&mut handler, &mut memory, &log("dropped"))
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::LimitExceeded);
+        assert_eq!(store.dropped(task.id), 1);
+
+        // test the records are tagged with the task
+        let outputs: io::response::Records = call(
+            &mut handler,
+            &mut memory,
+            &io::OpCode::Records(io::request::Records {}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outputs.records, store.records(task.id));
+        assert_eq!(outputs.records.len(), 2);
+        assert!(outputs
+            .records
+            .iter()
+            .all(|record| { record.task_id == task.id && record.guarantee == task.guarantee() }));
+        assert_eq!(outputs.records[1].message, "world");
+    }
+
+    #[tokio::test]
+    async fn test_release() {
+        let mut memory = VecMemory::default();
+
+        let client = IpiisClient::infer().await;
+        let store = LogStore::new(LogPolicy {
+            released_capacity: 1,
+            ..Default::default()
+        });
+
+        let mut handlers = vec![];
+        for id in [1, 2] {
+            let mut task = task(&client).unwrap();
+            task.id = TaskId(id);
+
+            let mut handler = LogHandler::with_task(store.clone(), &task);
+            let log = io::OpCode::Log(io::request::Log {
+                level: Level::Info,
+                target: "test".into(),
+                message: "hello".into(),
+                fields: vec![],
+            });
+            let _: io::response::Log = call(&mut handler, &mut memory, &log).await.unwrap();
+            handlers.push(handler);
+        }
+
+        // test the records of the released task are kept
+        InterruptHandler::<VecMemory>::release(&mut handlers[0])
+            .await
+            .unwrap();
+        assert_eq!(store.records(TaskId(1)).len(), 1);
+
+        // test the oldest released task is evicted beyond the capacity
+        InterruptHandler::<VecMemory>::release(&mut handlers[1])
+            .await
+            .unwrap();
+        assert!(store.records(TaskId(1)).is_empty());
+        assert_eq!(store.take(TaskId(2)).len(), 1);
+        assert!(store.records(TaskId(2)).is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+use bytecheck::CheckBytes;
+use ipis::core::anyhow::Result;
+use ipwis_kernel_common::interrupt::InterruptId;
+use rkyv::{
+    de::deserializers::SharedDeserializeMap, validation::validators::DefaultValidator, Archive,
+    Deserialize, Serialize,
+};
+
+/// Sends the record to the host, tagged with the task's identity.
+pub unsafe fn log(
+    level: Level,
+    target: impl ToString,
+    message: impl ToString,
+    fields: Vec<Field>,
+) -> Result<()> {
+    let _: io::response::Log = io::OpCode::Log(io::request::Log {
+        level,
+        target: target.to_string(),
+        message: message.to_string(),
+        fields,
+    })
+    .syscall()?;
+    Ok(())
+}
+
+/// Returns the records of the current task, which are still kept by the host.
+pub unsafe fn records() -> Result<Vec<io::response::Record>> {
+    let res: io::response::Records = io::OpCode::Records(io::request::Records {}).syscall()?;
+    Ok(res.records)
+}
+
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Archive, Serialize, Deserialize,
+)]
+#[archive(compare(PartialEq, PartialOrd))]
+#[archive_attr(derive(CheckBytes, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash))]
+#[repr(C)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<Level> for ::ipis::log::Level {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => Self::Error,
+            Level::Warn => Self::Warn,
+            Level::Info => Self::Info,
+            Level::Debug => Self::Debug,
+            Level::Trace => Self::Trace,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+pub struct Field {
+    pub key: String,
+    pub value: String,
+}
+
+impl Field {
+    pub fn new(key: impl ToString, value: impl ToString) -> Self {
+        Self {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+pub mod io {
+    use super::*;
+
+    #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+    #[archive_attr(derive(CheckBytes))]
+    pub enum OpCode {
+        Log(self::request::Log),
+        Records(self::request::Records),
+    }
+
+    impl ::ipis::core::signed::IsSigned for OpCode {}
+
+    impl OpCode {
+        pub const ID: InterruptId = InterruptId("ipwis_modules_log");
+
+        pub(crate) unsafe fn syscall<O>(mut self) -> Result<O>
+        where
+            O: Archive,
+            <O as Archive>::Archived:
+                for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+        {
+            Self::ID.syscall(&mut self)
+        }
+    }
+
+    pub mod request {
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Log {
+            pub level: Level,
+            pub target: String,
+            pub message: String,
+            pub fields: Vec<Field>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Log {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Records {}
+
+        impl ::ipis::core::signed::IsSigned for Records {}
+    }
+
+    pub mod response {
+        use ipis::core::{account::AccountRef, value::chrono::DateTime};
+        use ipwis_kernel_common::{resource::ResourceId, task::TaskId};
+
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Log {}
+
+        impl ::ipis::core::signed::IsSigned for Log {}
+
+        #[derive(Clone, Debug, PartialEq, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Record {
+            pub task_id: TaskId,
+            pub resource_id: ResourceId,
+            pub guarantee: AccountRef,
+            /// The time when the record is received by the host.
+            pub timestamp: DateTime,
+            pub level: Level,
+            pub target: String,
+            pub message: String,
+            pub fields: Vec<Field>,
+        }
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Records {
+            pub records: Vec<Record>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Records {}
+    }
+}
@@ -0,0 +1,229 @@
+#![allow(clippy::missing_safety_doc)]
+
+pub extern crate ipwis_modules_sign_common as common;
+
+use std::sync::Arc;
+
+use ipiis_common::Ipiis;
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::Verifier,
+        anyhow::{bail, Result},
+        signed::IsSigned,
+        value::hash::Hash,
+    },
+    pin::PinnedInner,
+    rkyv::AlignedVec,
+};
+use ipwis_kernel_common::{
+    error::ExternError,
+    interrupt::{
+        DynInterruptHandler, InterruptHandler, InterruptId, InterruptModule, InterruptTask,
+    },
+    memory::{Memory, MemoryFamily},
+    protection::ProtectionMode,
+};
+use ipwis_modules_sign_common::io;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SignPolicy {
+    /// The least privileged mode, which is allowed to counter-sign.
+    pub min_protection_mode: ProtectionMode,
+}
+
+impl Default for SignPolicy {
+    fn default() -> Self {
+        Self {
+            min_protection_mode: ProtectionMode::Entry,
+        }
+    }
+}
+
+pub struct SignModule<C> {
+    client: Arc<C>,
+    policy: SignPolicy,
+}
+
+impl<C> SignModule<C> {
+    pub fn new(client: Arc<C>) -> Self {
+        Self::with_policy(client, Default::default())
+    }
+
+    pub fn with_policy(client: Arc<C>, policy: SignPolicy) -> Self {
+        Self { client, policy }
+    }
+}
+
+#[async_trait]
+impl<C, F> InterruptModule<F> for SignModule<C>
+where
+    C: Ipiis + Send + Sync + 'static,
+    F: MemoryFamily,
+{
+    fn id(&self) -> InterruptId {
+        io::OpCode::ID
+    }
+
+    async fn spawn_handler(&self, task: &InterruptTask) -> Result<Box<dyn DynInterruptHandler<F>>> {
+        Ok(Box::new(SignHandler::with_task(
+            self.client.clone(),
+            self.policy,
+            task,
+        )))
+    }
+}
+
+pub struct SignHandler<C> {
+    client: Arc<C>,
+    policy: SignPolicy,
+    task: InterruptTask,
+}
+
+impl<C> SignHandler<C>
+where
+    C: Ipiis,
+{
+    pub fn with_task(client: Arc<C>, policy: SignPolicy, task: &InterruptTask) -> Self {
+        Self {
+            client,
+            policy,
+            task: task.clone(),
+        }
+    }
+
+    fn handle_hash(&self, req: io::request::Hash) -> Result<io::response::Hash> {
+        Ok(io::response::Hash {
+            hash: Hash::with_bytes(&req.data),
+        })
+    }
+
+    fn handle_verify(&self, req: io::request::Verify) -> Result<io::response::Verify> {
+        if let Err(error) = req.signed.verify(None) {
+            bail!(ExternError::invalid_input(format!(
+                "failed to verify the signature: {error}",
+            )));
+        }
+
+        Ok(io::response::Verify {
+            guarantee: req.signed.guarantee.account,
+        })
+    }
+
+    fn handle_counter_sign(
+        &self,
+        req: io::request::CounterSign,
+    ) -> Result<io::response::CounterSign> {
+        if self.task.protection_mode < self.policy.min_protection_mode {
+            bail!(ExternError::permission_denied(format!(
+                "counter-signing is not allowed in the protection mode: {:?}",
+                self.task.protection_mode,
+            )));
+        }
+
+        // note: the runtime can sign only for the tasks which it guarantees
+        if self.task.ctx.guarantor.account != self.client.account_me().account_ref() {
+            bail!(ExternError::permission_denied(
+                "the task is not guaranteed by this runtime",
+            ));
+        }
+
+        // note: the keys never leave the runtime; only the signed data does
+        Ok(io::response::CounterSign {
+            signed: self.client.sign(self.task.guarantee(), req.data)?,
+        })
+    }
+}
+
+#[async_trait]
+impl<C, M> InterruptHandler<M> for SignHandler<C>
+where
+    C: Ipiis + Send + Sync,
+    M: Memory,
+{
+    async unsafe fn handle_raw(&mut self, _memory: &mut M, inputs: &[u8]) -> Result<AlignedVec> {
+        match PinnedInner::deserialize_owned(inputs)? {
+            io::OpCode::Hash(req) => self.handle_hash(req)?.to_bytes().map_err(Into::into),
+            io::OpCode::Verify(req) => self.handle_verify(req)?.to_bytes().map_err(Into::into),
+            io::OpCode::CounterSign(req) => self
+                .handle_counter_sign(req)?
+                .to_bytes()
+                .map_err(Into::into),
+        }
+    }
+
+    async fn release(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ipiis_api::{client::IpiisClient, common::Ipiis};
+    use ipis::{core::value::hash::Hash, env::Infer, tokio};
+    use ipwis_kernel_common::{
+        error::{ErrorCode, ExternError},
+        protection::ProtectionMode,
+    };
+    use ipwis_modules_sign_common::io;
+    use ipwis_testkit::{
+        handler::{call, task},
+        memory::VecMemory,
+    };
+
+    use super::SignHandler;
+
+    #[tokio::test]
+    async fn test_counter_sign() {
+        let mut memory = VecMemory::default();
+
+        let client = Arc::new(IpiisClient::infer().await);
+        let mut task = task(&client).unwrap();
+        let mut handler = SignHandler::with_task(client.clone(), Default::default(), &task);
+
+        let data = b"hello world".to_vec();
+
+        // test hashing
+        let outputs: io::response::Hash = call(
+            &mut handler,
+            &mut memory,
+            &io::OpCode::Hash(io::request::Hash { data: data.clone() }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outputs.hash, Hash::with_bytes(&data));
+
+        // test counter-signing
+        let counter_sign = io::OpCode::CounterSign(io::request::CounterSign { data: data.clone() });
+        let outputs: io::response::CounterSign = call(&mut handler, &mut memory, &counter_sign)
+            .await
+            .unwrap();
+        assert_eq!(outputs.signed.data.data, data);
+
+        // test verifying the counter-signed data
+        let outputs: io::response::Verify = call(
+            &mut handler,
+            &mut memory,
+            &io::OpCode::Verify(io::request::Verify {
+                signed: outputs.signed,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outputs.guarantee, client.account_me().account_ref());
+
+        // test the workers are not allowed to counter-sign
+        task.protection_mode = ProtectionMode::Worker;
+        let mut handler = SignHandler::with_task(client, Default::default(), &task);
+
+        let error =
+            call::<_, _, io::response::CounterSign>(&mut handler, &mut memory, &counter_sign)
+                .await
+                .unwrap_err()
+                .downcast::<ExternError>()
+                .unwrap();
+        assert_eq!(error.code, ErrorCode::PermissionDenied);
+    }
+}
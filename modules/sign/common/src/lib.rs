@@ -0,0 +1,112 @@
+use bytecheck::CheckBytes;
+use ipis::core::{
+    account::{AccountRef, GuaranteeSigned},
+    anyhow::Result,
+    value::hash::Hash,
+};
+use ipwis_kernel_common::interrupt::InterruptId;
+use rkyv::{
+    de::deserializers::SharedDeserializeMap, validation::validators::DefaultValidator, Archive,
+    Deserialize, Serialize,
+};
+
+pub unsafe fn hash(data: Vec<u8>) -> Result<Hash> {
+    let res: io::response::Hash = io::OpCode::Hash(io::request::Hash { data }).syscall()?;
+    Ok(res.hash)
+}
+
+/// Verifies the signature, and then returns the signer.
+pub unsafe fn verify(signed: GuaranteeSigned<Vec<u8>>) -> Result<AccountRef> {
+    let res: io::response::Verify = io::OpCode::Verify(io::request::Verify { signed }).syscall()?;
+    Ok(res.guarantee)
+}
+
+/// Asks the runtime to sign the data on the guarantor's behalf, for the task's caller.
+pub unsafe fn counter_sign(data: Vec<u8>) -> Result<GuaranteeSigned<Vec<u8>>> {
+    let res: io::response::CounterSign =
+        io::OpCode::CounterSign(io::request::CounterSign { data }).syscall()?;
+    Ok(res.signed)
+}
+
+pub mod io {
+    use super::*;
+
+    #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+    #[archive_attr(derive(CheckBytes))]
+    pub enum OpCode {
+        Hash(self::request::Hash),
+        Verify(self::request::Verify),
+        CounterSign(self::request::CounterSign),
+    }
+
+    impl ::ipis::core::signed::IsSigned for OpCode {}
+
+    impl OpCode {
+        pub const ID: InterruptId = InterruptId("ipwis_modules_sign");
+
+        pub(crate) unsafe fn syscall<O>(mut self) -> Result<O>
+        where
+            O: Archive,
+            <O as Archive>::Archived:
+                for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+        {
+            Self::ID.syscall(&mut self)
+        }
+    }
+
+    pub mod request {
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Hash {
+            pub data: Vec<u8>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Hash {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Verify {
+            pub signed: GuaranteeSigned<Vec<u8>>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Verify {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct CounterSign {
+            pub data: Vec<u8>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for CounterSign {}
+    }
+
+    pub mod response {
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Hash {
+            pub hash: ::ipis::core::value::hash::Hash,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Hash {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Verify {
+            pub guarantee: AccountRef,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Verify {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct CounterSign {
+            pub signed: GuaranteeSigned<Vec<u8>>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for CounterSign {}
+    }
+}
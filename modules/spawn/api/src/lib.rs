@@ -7,7 +7,7 @@ use core::pin::Pin;
 use ipis::{
     async_trait::async_trait,
     core::{
-        anyhow::{anyhow, Result},
+        anyhow::Result,
         signed::IsSigned,
     },
     pin::PinnedInner,
@@ -15,8 +15,8 @@ use ipis::{
     tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
 };
 use ipwis_kernel_common::{
-        interrupt::{InterruptHandler, InterruptId, InterruptModule},
-        memory::Memory,
+        interrupt::{DynInterruptHandler, InterruptHandler, InterruptId, InterruptModule},
+        memory::{Memory, MemoryFamily},
         resource::{ResourceId,ResourceStore},
 };
 use ipwis_modules_spawn_common::{io, ExternReader, ExternWriter};
@@ -25,15 +25,15 @@ use ipwis_modules_spawn_common::{io, ExternReader, ExternWriter};
 pub struct StreamModule;
 
 #[async_trait]
-impl<M> InterruptModule<M> for StreamHandler
+impl<F> InterruptModule<F> for StreamHandler
 where
-    M: Memory,
+    F: MemoryFamily,
 {
     fn id(&self) -> InterruptId {
         io::OpCode::ID
     }
 
-    async fn spawn_handler(&self) -> Result<Box<dyn InterruptHandler<M>>> {
+    async fn spawn_handler(&self) -> Result<Box<dyn DynInterruptHandler<F>>> {
         Ok(Box::new(StreamHandler::default()))
     }
 }
@@ -75,11 +75,11 @@ where
     }
 
     async fn release(&mut self) -> Result<()> {
-        self.readers.map.clear();
-        for writer in self.writers.map.values_mut() {
+        self.readers.clear();
+        for writer in self.writers.values_mut() {
             writer.shutdown().await?;
         }
-        self.writers.map.clear();
+        self.writers.clear();
         Ok(())
     }
 }
@@ -116,10 +116,7 @@ impl StreamHandler {
         &mut self,
         id: &ResourceId,
     ) -> Result<&mut Pin<Box<dyn AsyncRead + Send + Sync>>> {
-        self.readers
-            .map
-            .get_mut(id)
-            .ok_or_else(|| anyhow!("failed to find the ExternReader: {:x}", id))
+        self.readers.get_mut(id)
     }
 }
 
@@ -179,9 +176,6 @@ impl StreamHandler {
         &mut self,
         id: &ResourceId,
     ) -> Result<&mut Pin<Box<dyn AsyncWrite + Send + Sync>>> {
-        self.writers
-            .map
-            .get_mut(id)
-            .ok_or_else(|| anyhow!("failed to find the ExternWriter: {:x}", id))
+        self.writers.get_mut(id)
     }
 }
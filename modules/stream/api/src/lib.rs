@@ -2,46 +2,324 @@
 
 pub extern crate ipwis_modules_stream_common as common;
 
-use core::pin::Pin;
+mod pipe;
+
+pub use self::pipe::{pipe, PipeReader, PipeWriter};
+
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::{collections::HashMap, sync::Arc};
 
 use ipis::{
     async_trait::async_trait,
     core::{
-        anyhow::{anyhow, Result},
+        anyhow::{bail, Result},
         signed::IsSigned,
     },
     pin::PinnedInner,
     rkyv::AlignedVec,
-    tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    tokio::{
+        self,
+        io::{
+            AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt, ReadBuf,
+        },
+        sync::Mutex,
+    },
 };
 use ipwis_kernel_common::{
-        interrupt::{InterruptHandler, InterruptId, InterruptModule},
-        memory::Memory,
-        resource::{ResourceId,ResourceStore},
+    data::{ExternData, ExternDataRef},
+    error::ExternError,
+    interrupt::{
+        DynInterruptHandler, InterruptCompletion, InterruptExtensions, InterruptHandler,
+        InterruptId, InterruptModule, InterruptSubmission, InterruptTask, InterruptTaskOutcome,
+        InterruptTaskUsage,
+    },
+    memory::{Memory, MemoryFamily},
+    resource::{ResourceId, ResourceStore},
 };
-use ipwis_modules_stream_common::{io, ExternReader, ExternWriter};
+use ipwis_modules_stream_common::{io, ExternReader, ExternWriter, SeekFrom};
 
 #[derive(Copy, Clone, Debug, Default)]
 pub struct StreamModule;
 
 #[async_trait]
-impl<M> InterruptModule<M> for StreamHandler
+impl<F> InterruptModule<F> for StreamModule
 where
-    M: Memory,
+    F: MemoryFamily,
 {
     fn id(&self) -> InterruptId {
         io::OpCode::ID
     }
 
-    async fn spawn_handler(&self) -> Result<Box<dyn InterruptHandler<M>>> {
-        Ok(Box::new(StreamHandler::default()))
+    async fn spawn_handler(&self, task: &InterruptTask) -> Result<Box<dyn DynInterruptHandler<F>>> {
+        Ok(Box::new(StreamHandler::with_task(task)))
+    }
+}
+
+pub trait AsyncReadSeek: AsyncRead + AsyncSeek {}
+
+impl<T> AsyncReadSeek for T where T: AsyncRead + AsyncSeek + ?Sized {}
+
+enum Reader {
+    Read(Pin<Box<dyn AsyncRead + Send + Sync>>),
+    Seek(Pin<Box<dyn AsyncReadSeek + Send + Sync>>),
+}
+
+impl AsyncRead for Reader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<tokio::io::Result<()>> {
+        match self.get_mut() {
+            Self::Read(reader) => reader.as_mut().poll_read(cx, buf),
+            Self::Seek(reader) => reader.as_mut().poll_read(cx, buf),
+        }
+    }
+}
+
+impl Reader {
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        match self {
+            Self::Read(_) => bail!(ExternError::unsupported("the ExternReader is not seekable")),
+            Self::Seek(reader) => reader.seek(pos.into()).await.map_err(Into::into),
+        }
     }
 }
 
+// note: the handles are shared with the pending operations
+type SharedReader = Arc<Mutex<Reader>>;
+type SharedWriter = Arc<Mutex<Pin<Box<dyn AsyncWrite + Send + Sync>>>>;
+
+struct ReaderEntry {
+    reader: SharedReader,
+    len: ExternDataRef,
+    content_type: Option<String>,
+}
+
+/// The streams of a task, which may be opened by the other modules of the task.
+pub struct StreamTable {
+    readers: ResourceStore<ReaderEntry>,
+    writers: ResourceStore<SharedWriter>,
+    // note: the attached streams are discovered by the guests with their names
+    attached_readers: HashMap<String, ResourceId>,
+    attached_writers: HashMap<String, ResourceId>,
+}
+
+pub type SharedStreamTable = Arc<::std::sync::Mutex<StreamTable>>;
+
+impl Default for StreamTable {
+    fn default() -> Self {
+        Self {
+            readers: ResourceStore::new(Self::READERS),
+            writers: ResourceStore::new(Self::WRITERS),
+            attached_readers: Default::default(),
+            attached_writers: Default::default(),
+        }
+    }
+}
+
+impl StreamTable {
+    pub const READERS: &'static str = "stream readers";
+    pub const WRITERS: &'static str = "stream writers";
+
+    /// Returns the streams of the task, limited by the task's constraints.
+    pub fn with_task(task: &InterruptTask) -> SharedStreamTable {
+        let table = Self::with_extensions(&task.extensions);
+        {
+            let resources = &task.ctx.constraints.resources;
+            let mut table = table.lock().unwrap();
+            table
+                .readers
+                .set_limit(resources.handle_limit(Self::READERS));
+            table
+                .writers
+                .set_limit(resources.handle_limit(Self::WRITERS));
+        }
+        table
+    }
+
+    /// Returns the streams of a task, which may be spawned or running on a kernel.
+    pub fn with_extensions(extensions: &InterruptExtensions) -> SharedStreamTable {
+        extensions.get_or_default()
+    }
+
+    /// Names the reader, so that the guest can open it once.
+    pub fn attach_reader(&mut self, name: impl ToString, reader: &ExternReader) -> Result<()> {
+        self.get_reader_entry(&reader.id())?;
+        attach(&mut self.attached_readers, name.to_string(), reader.id())
+    }
+
+    /// Names the writer, so that the guest can open it once.
+    pub fn attach_writer(&mut self, name: impl ToString, writer: &ExternWriter) -> Result<()> {
+        self.get_writer(&writer.id())?;
+        attach(&mut self.attached_writers, name.to_string(), writer.id())
+    }
+
+    pub fn reader_new(
+        &mut self,
+        reader: impl AsyncRead + Send + Sync + 'static,
+        len: usize,
+    ) -> Result<ExternReader> {
+        self.insert_reader(Reader::Read(Box::pin(reader)), len)
+    }
+
+    pub fn reader_new_seekable(
+        &mut self,
+        reader: impl AsyncRead + AsyncSeek + Send + Sync + 'static,
+        len: usize,
+    ) -> Result<ExternReader> {
+        self.insert_reader(Reader::Seek(Box::pin(reader)), len)
+    }
+
+    fn insert_reader(&mut self, reader: Reader, len: usize) -> Result<ExternReader> {
+        let len = len.try_into()?;
+        let id = self.readers.insert(|_| {
+            Ok(ReaderEntry {
+                reader: Arc::new(Mutex::new(reader)),
+                len,
+                content_type: None,
+            })
+        })?;
+
+        Ok(ExternReader::new(id, len))
+    }
+
+    pub fn set_content_type(&mut self, id: &ResourceId, content_type: impl ToString) -> Result<()> {
+        self.get_reader_entry_mut(id)?.content_type = Some(content_type.to_string());
+        Ok(())
+    }
+
+    pub fn writer_new(
+        &mut self,
+        writer: impl AsyncWrite + Send + Sync + 'static,
+    ) -> Result<ExternWriter> {
+        let id = self
+            .writers
+            .insert(|_| Ok(Arc::new(Mutex::new(Box::pin(writer)))))?;
+
+        Ok(ExternWriter::new(id))
+    }
+
+    fn get_reader(&self, id: &ResourceId) -> Result<SharedReader> {
+        self.get_reader_entry(id).map(|entry| entry.reader.clone())
+    }
+
+    fn get_reader_entry(&self, id: &ResourceId) -> Result<&ReaderEntry> {
+        self.readers.get(id)
+    }
+
+    fn get_reader_entry_mut(&mut self, id: &ResourceId) -> Result<&mut ReaderEntry> {
+        self.readers.get_mut(id)
+    }
+
+    fn get_writer(&self, id: &ResourceId) -> Result<SharedWriter> {
+        self.writers.get(id).cloned()
+    }
+
+    fn open_reader(&mut self, name: &str) -> Result<ExternReader> {
+        let id = open(&mut self.attached_readers, name)?;
+        let entry = self.get_reader_entry(&id)?;
+        Ok(ExternReader::new(id, entry.len))
+    }
+
+    fn open_writer(&mut self, name: &str) -> Result<ExternWriter> {
+        let id = open(&mut self.attached_writers, name)?;
+        self.get_writer(&id)?;
+        Ok(ExternWriter::new(id))
+    }
+
+    fn remove_reader(&mut self, id: &ResourceId) -> Result<()> {
+        self.readers.remove(id).map(drop)
+    }
+
+    fn remove_writer(&mut self, id: &ResourceId) -> Result<SharedWriter> {
+        self.writers.remove(id)
+    }
+}
+
+fn attach(names: &mut HashMap<String, ResourceId>, name: String, id: ResourceId) -> Result<()> {
+    if names.contains_key(&name) {
+        bail!(ExternError::invalid_input(format!(
+            "the stream is already attached: {name:?}"
+        )));
+    }
+    names.insert(name, id);
+    Ok(())
+}
+
+fn open(names: &mut HashMap<String, ResourceId>, name: &str) -> Result<ResourceId> {
+    // note: the attached streams are opened only once, as the guests close them on drop
+    match names.remove(name) {
+        Some(id) => Ok(id),
+        None => bail!(ExternError::not_found(format!(
+            "failed to find the attached stream: {name:?}"
+        ))),
+    }
+}
+
+/// Pipes the named writer of a task to the named reader of another one.
+///
+/// The tasks may be spawned with the extensions by `Kernel::spawn_with_extensions`,
+/// or be running on the kernel, e.g. with `Kernel::extensions`.
+pub fn attach_pipe(
+    writer: &InterruptExtensions,
+    writer_name: impl ToString,
+    reader: &InterruptExtensions,
+    reader_name: impl ToString,
+    capacity: usize,
+) -> Result<()> {
+    let (pipe_reader, pipe_writer) = pipe(capacity);
+
+    let writers = StreamTable::with_extensions(writer);
+    let writer = {
+        let mut table = writers.lock().unwrap();
+        let writer = table.writer_new(pipe_writer)?;
+        if let Err(error) = table.attach_writer(writer_name, &writer) {
+            table.remove_writer(&writer.id())?;
+            return Err(error);
+        }
+        writer.id()
+    };
+
+    let readers = StreamTable::with_extensions(reader);
+    let error = {
+        let mut table = readers.lock().unwrap();
+        match table.reader_new(pipe_reader, 0) {
+            Ok(reader) => match table.attach_reader(reader_name, &reader) {
+                Ok(()) => return Ok(()),
+                Err(error) => {
+                    table.remove_reader(&reader.id())?;
+                    error
+                }
+            },
+            Err(error) => error,
+        }
+    };
+
+    // note: the writer is detached as well, so that it is not left without a reader
+    let mut table = writers.lock().unwrap();
+    table.attached_writers.retain(|_, id| id != &writer);
+    table.remove_writer(&writer)?;
+    Err(error)
+}
+
+// note: the guest range is checked before allocating, so that a syscall cannot size the host buffer
+fn read_buf<M>(memory: &M, buf: ExternData) -> Result<Vec<u8>>
+where
+    M: Memory,
+{
+    memory.host_check(buf)?;
+    Ok(vec![0; (buf.len as usize).min(StreamHandler::MAX_READ_LEN)])
+}
+
 #[derive(Default)]
 pub struct StreamHandler {
-    readers: ResourceStore<Pin<Box<dyn AsyncRead + Send + Sync>>>,
-    writers: ResourceStore<Pin<Box<dyn AsyncWrite + Send + Sync>>>,
+    table: SharedStreamTable,
+    outcome: Arc<InterruptTaskOutcome>,
+    usage: Arc<InterruptTaskUsage>,
 }
 
 #[async_trait]
@@ -71,29 +349,177 @@ where
                 .await?
                 .to_bytes()
                 .map_err(Into::into),
+            io::OpCode::ReaderClose(req) => self
+                .handle_reader_close(req)?
+                .to_bytes()
+                .map_err(Into::into),
+            io::OpCode::WriterClose(req) => self
+                .handle_writer_close(req)
+                .await?
+                .to_bytes()
+                .map_err(Into::into),
+            io::OpCode::ReaderSeek(req) => self
+                .handle_reader_seek(req)
+                .await?
+                .to_bytes()
+                .map_err(Into::into),
+            io::OpCode::ReaderMetadata(req) => self
+                .handle_reader_metadata(req)?
+                .to_bytes()
+                .map_err(Into::into),
+            io::OpCode::ReaderOpen(req) => {
+                self.handle_reader_open(req)?.to_bytes().map_err(Into::into)
+            }
+            io::OpCode::WriterOpen(req) => {
+                self.handle_writer_open(req)?.to_bytes().map_err(Into::into)
+            }
+        }
+    }
+
+    async unsafe fn submit_raw(
+        &mut self,
+        memory: &mut M,
+        inputs: &[u8],
+    ) -> Result<InterruptSubmission> {
+        match PinnedInner::deserialize_owned(inputs)? {
+            io::OpCode::ReaderNext(req) => {
+                let reader = self.get_reader(&req.id)?;
+                let usage = self.usage.clone();
+                let mut buf = read_buf(memory, req.buf)?;
+
+                Ok(InterruptSubmission::pending(async move {
+                    let len = reader.lock().await.read(&mut buf).await?;
+                    buf.truncate(len);
+                    usage.add_stream_bytes_read(len as u64);
+
+                    Ok(InterruptCompletion {
+                        outputs: io::response::ReaderNext {
+                            len: len.try_into()?,
+                        }
+                        .to_bytes()?,
+                        // copy-out semantics
+                        copy_to: Some((req.buf, buf)),
+                    })
+                }))
+            }
+            io::OpCode::WriterNext(req) => {
+                let writer = self.get_writer(&req.id)?;
+                let usage = self.usage.clone();
+                // copy-in semantics
+                let buf = memory.read_raw(req.buf)?;
+
+                Ok(InterruptSubmission::pending(async move {
+                    let len = writer.lock().await.write(&buf).await?;
+                    usage.add_stream_bytes_written(len as u64);
+
+                    io::response::WriterNext {
+                        len: len.try_into()?,
+                    }
+                    .to_bytes()
+                    .map(Into::into)
+                    .map_err(Into::into)
+                }))
+            }
+            io::OpCode::WriterFlush(req) => {
+                let writer = self.get_writer(&req.id)?;
+
+                Ok(InterruptSubmission::pending(async move {
+                    writer.lock().await.flush().await?;
+
+                    io::response::WriterFlush {}
+                        .to_bytes()
+                        .map(Into::into)
+                        .map_err(Into::into)
+                }))
+            }
+            io::OpCode::WriterShutdown(req) => {
+                let writer = self.get_writer(&req.id)?;
+
+                Ok(InterruptSubmission::pending(async move {
+                    writer.lock().await.shutdown().await?;
+
+                    io::response::WriterShutdown {}
+                        .to_bytes()
+                        .map(Into::into)
+                        .map_err(Into::into)
+                }))
+            }
+            io::OpCode::WriterClose(req) => {
+                let writer = self.table.lock().unwrap().remove_writer(&req.id)?;
+
+                Ok(InterruptSubmission::pending(async move {
+                    writer.lock().await.shutdown().await?;
+
+                    io::response::WriterClose {}
+                        .to_bytes()
+                        .map(Into::into)
+                        .map_err(Into::into)
+                }))
+            }
+            io::OpCode::ReaderSeek(req) => {
+                let reader = self.get_reader(&req.id)?;
+
+                Ok(InterruptSubmission::pending(async move {
+                    io::response::ReaderSeek {
+                        pos: reader.lock().await.seek(req.pos).await?,
+                    }
+                    .to_bytes()
+                    .map(Into::into)
+                    .map_err(Into::into)
+                }))
+            }
+            _ => self
+                .handle_raw(memory, inputs)
+                .await
+                .map(InterruptSubmission::Ready),
         }
     }
 
     async fn release(&mut self) -> Result<()> {
-        self.readers.map.clear();
-        for writer in self.writers.map.values_mut() {
-            writer.shutdown().await?;
+        let writers: Vec<_> = {
+            let mut table = self.table.lock().unwrap();
+            table.attached_readers.clear();
+            table.attached_writers.clear();
+            table.readers.clear();
+            table.writers.drain()
+        };
+        // note: the writers of the trapped task are dropped without shutdown to break the pipes
+        if self.outcome.is_trapped() {
+            return Ok(());
+        }
+        for writer in writers {
+            writer.lock().await.shutdown().await?;
         }
-        self.writers.map.clear();
         Ok(())
     }
 }
 
 impl StreamHandler {
+    /// The largest chunk, which is read at once; the rest is read by the next calls.
+    pub const MAX_READ_LEN: usize = 64 * 1024;
+
+    pub fn with_task(task: &InterruptTask) -> Self {
+        Self {
+            table: StreamTable::with_task(task),
+            outcome: task.extensions.get_or_default(),
+            usage: task.extensions.get_or_default(),
+        }
+    }
+
+    pub fn with_extensions(extensions: &InterruptExtensions) -> Self {
+        Self {
+            table: StreamTable::with_extensions(extensions),
+            outcome: extensions.get_or_default(),
+            usage: extensions.get_or_default(),
+        }
+    }
+
     pub fn handle_reader_new(
         &mut self,
         reader: impl AsyncRead + Send + Sync + 'static,
         len: usize,
     ) -> Result<ExternReader> {
-        let id = self.readers.insert(|_| Ok(Box::pin(reader)))?;
-        let len = len.try_into()?;
-
-        Ok(ExternReader::new(id, len))
+        self.table.lock().unwrap().reader_new(reader, len)
     }
 
     async unsafe fn handle_reader_next<M>(
@@ -105,21 +531,74 @@ impl StreamHandler {
         M: Memory,
     {
         let reader = self.get_reader(&req.id)?;
-        let mut buf = memory.load_mut_raw(req.buf)?;
+        let mut reader = reader.lock().await;
+        let mut buf = read_buf(memory, req.buf)?;
+
+        // copy-out semantics
+        let len = reader.read(&mut buf).await?;
+        memory.write_raw(req.buf, &buf[..len])?;
+        self.usage.add_stream_bytes_read(len as u64);
 
         Ok(io::response::ReaderNext {
-            len: reader.read_buf(&mut buf).await?.try_into()?,
+            len: len.try_into()?,
+        })
+    }
+
+    pub fn handle_reader_new_seekable(
+        &mut self,
+        reader: impl AsyncRead + AsyncSeek + Send + Sync + 'static,
+        len: usize,
+    ) -> Result<ExternReader> {
+        self.table.lock().unwrap().reader_new_seekable(reader, len)
+    }
+
+    fn handle_reader_close(
+        &mut self,
+        req: io::request::ReaderClose,
+    ) -> Result<io::response::ReaderClose> {
+        self.table.lock().unwrap().remove_reader(&req.id)?;
+        Ok(io::response::ReaderClose {})
+    }
+
+    async fn handle_reader_seek(
+        &mut self,
+        req: io::request::ReaderSeek,
+    ) -> Result<io::response::ReaderSeek> {
+        let reader = self.get_reader(&req.id)?;
+        let mut reader = reader.lock().await;
+
+        Ok(io::response::ReaderSeek {
+            pos: reader.seek(req.pos).await?,
         })
     }
 
-    fn get_reader(
+    fn handle_reader_open(
         &mut self,
-        id: &ResourceId,
-    ) -> Result<&mut Pin<Box<dyn AsyncRead + Send + Sync>>> {
-        self.readers
-            .map
-            .get_mut(id)
-            .ok_or_else(|| anyhow!("failed to find the ExternReader: {:x}", id))
+        req: io::request::ReaderOpen,
+    ) -> Result<io::response::ReaderOpen> {
+        let reader = self.table.lock().unwrap().open_reader(&req.name)?;
+
+        Ok(io::response::ReaderOpen {
+            id: reader.id(),
+            len: reader.len(),
+        })
+    }
+
+    fn handle_reader_metadata(
+        &mut self,
+        req: io::request::ReaderMetadata,
+    ) -> Result<io::response::ReaderMetadata> {
+        let table = self.table.lock().unwrap();
+        let entry = table.get_reader_entry(&req.id)?;
+
+        Ok(io::response::ReaderMetadata {
+            len: entry.len,
+            content_type: entry.content_type.clone(),
+        })
+    }
+
+    fn get_reader(&self, id: &ResourceId) -> Result<SharedReader> {
+        self.table.lock().unwrap().get_reader(id)
     }
 }
 
@@ -128,9 +607,7 @@ impl StreamHandler {
         &mut self,
         writer: impl AsyncWrite + Send + Sync + 'static,
     ) -> Result<ExternWriter> {
-        let id = self.writers.insert(|_| Ok(Box::pin(writer)))?;
-
-        Ok(ExternWriter::new(id))
+        self.table.lock().unwrap().writer_new(writer)
     }
 
     async unsafe fn handle_writer_next<M>(
@@ -142,10 +619,14 @@ impl StreamHandler {
         M: Memory,
     {
         let writer = self.get_writer(&req.id)?;
-        let mut buf = memory.load_raw(req.buf)?;
+        let mut writer = writer.lock().await;
+        // copy-in semantics
+        let buf = memory.read_raw(req.buf)?;
 
+        let len = writer.write(&buf).await?;
+        self.usage.add_stream_bytes_written(len as u64);
         Ok(io::response::WriterNext {
-            len: writer.write_buf(&mut buf).await?.try_into()?,
+            len: len.try_into()?,
         })
     }
 
@@ -154,6 +635,7 @@ impl StreamHandler {
         req: io::request::WriterFlush,
     ) -> Result<io::response::WriterFlush> {
         let writer = self.get_writer(&req.id)?;
+        let mut writer = writer.lock().await;
 
         writer
             .flush()
@@ -167,6 +649,7 @@ impl StreamHandler {
         req: io::request::WriterShutdown,
     ) -> Result<io::response::WriterShutdown> {
         let writer = self.get_writer(&req.id)?;
+        let mut writer = writer.lock().await;
 
         writer
             .shutdown()
@@ -175,13 +658,503 @@ impl StreamHandler {
             .map_err(Into::into)
     }
 
-    fn get_writer(
+    fn handle_writer_open(
+        &mut self,
+        req: io::request::WriterOpen,
+    ) -> Result<io::response::WriterOpen> {
+        let writer = self.table.lock().unwrap().open_writer(&req.name)?;
+
+        Ok(io::response::WriterOpen { id: writer.id() })
+    }
+
+    async fn handle_writer_close(
         &mut self,
-        id: &ResourceId,
-    ) -> Result<&mut Pin<Box<dyn AsyncWrite + Send + Sync>>> {
-        self.writers
-            .map
-            .get_mut(id)
-            .ok_or_else(|| anyhow!("failed to find the ExternWriter: {:x}", id))
+        req: io::request::WriterClose,
+    ) -> Result<io::response::WriterClose> {
+        let writer = self.table.lock().unwrap().remove_writer(&req.id)?;
+        let mut writer = writer.lock().await;
+
+        // note: the pending data should not be lost
+        writer
+            .shutdown()
+            .await
+            .map(|()| io::response::WriterClose {})
+            .map_err(Into::into)
+    }
+
+    fn get_writer(&self, id: &ResourceId) -> Result<SharedWriter> {
+        self.table.lock().unwrap().get_writer(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use ipis::{
+        async_trait::async_trait,
+        core::anyhow::Result,
+        rkyv::AlignedVec,
+        tokio::{
+            self,
+            io::{AsyncReadExt, AsyncWriteExt},
+        },
+    };
+    use ipwis_kernel::{
+        config::{KernelConfig, RetentionPolicy},
+        memory::IpwisMemoryFamily,
+    };
+    use ipwis_kernel_common::{
+        data::ExternData,
+        error::{ErrorCode, ExternError},
+        interrupt::{
+            DynInterruptHandler, InterruptExtensions, InterruptHandler, InterruptId,
+            InterruptModule, InterruptTask, InterruptTaskOutcome,
+        },
+        memory::Memory,
+        task::TaskCtx,
+    };
+    use ipwis_modules_stream_common::{io, SeekFrom};
+    use ipwis_testkit::{
+        guest::build_syscalls,
+        handler::{call, encode, submit},
+        kernel::TestKernel,
+        memory::VecMemory,
+    };
+
+    use super::{attach_pipe, pipe, SharedStreamTable, StreamHandler, StreamModule, StreamTable};
+
+    const READ: InterruptId = InterruptId("ipwis_test_read");
+    const UNKNOWN: InterruptId = InterruptId("ipwis_test_unknown");
+    const WRITE: InterruptId = InterruptId("ipwis_test_write");
+
+    type ReadLog = Arc<::std::sync::Mutex<Vec<Result<Vec<u8>, ErrorCode>>>>;
+
+    /// Reads the readers of the task to the end.
+    struct ReadModule {
+        log: ReadLog,
+    }
+
+    #[async_trait]
+    impl InterruptModule<IpwisMemoryFamily> for ReadModule {
+        fn id(&self) -> InterruptId {
+            READ
+        }
+
+        async fn spawn_handler(
+            &self,
+            task: &InterruptTask,
+        ) -> Result<Box<dyn DynInterruptHandler<IpwisMemoryFamily>>> {
+            Ok(Box::new(ReadHandler {
+                table: StreamTable::with_task(task),
+                log: self.log.clone(),
+            }))
+        }
+    }
+
+    struct ReadHandler {
+        table: SharedStreamTable,
+        log: ReadLog,
+    }
+
+    #[async_trait]
+    impl<M> InterruptHandler<M> for ReadHandler
+    where
+        M: Memory,
+    {
+        async unsafe fn handle_raw(
+            &mut self,
+            _memory: &mut M,
+            _inputs: &[u8],
+        ) -> Result<AlignedVec> {
+            let readers: Vec<_> = {
+                let table = self.table.lock().unwrap();
+                table
+                    .readers
+                    .iter()
+                    .map(|(_, entry)| entry.reader.clone())
+                    .collect()
+            };
+            for reader in readers {
+                let mut buf = Vec::new();
+                let result = match reader.lock().await.read_to_end(&mut buf).await {
+                    Ok(_) => Ok(buf),
+                    Err(error) => Err(error.kind().into()),
+                };
+                self.log.lock().unwrap().push(result);
+            }
+            Ok(Default::default())
+        }
+
+        async fn release(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Writes the inputs to the writers of the task.
+    struct WriteModule;
+
+    #[async_trait]
+    impl InterruptModule<IpwisMemoryFamily> for WriteModule {
+        fn id(&self) -> InterruptId {
+            WRITE
+        }
+
+        async fn spawn_handler(
+            &self,
+            task: &InterruptTask,
+        ) -> Result<Box<dyn DynInterruptHandler<IpwisMemoryFamily>>> {
+            Ok(Box::new(WriteHandler {
+                table: StreamTable::with_task(task),
+            }))
+        }
+    }
+
+    struct WriteHandler {
+        table: SharedStreamTable,
+    }
+
+    #[async_trait]
+    impl<M> InterruptHandler<M> for WriteHandler
+    where
+        M: Memory,
+    {
+        async unsafe fn handle_raw(
+            &mut self,
+            _memory: &mut M,
+            inputs: &[u8],
+        ) -> Result<AlignedVec> {
+            let writers: Vec<_> = {
+                let table = self.table.lock().unwrap();
+                table
+                    .writers
+                    .iter()
+                    .map(|(_, writer)| writer.clone())
+                    .collect()
+            };
+            for writer in writers {
+                writer.lock().await.write_all(inputs).await?;
+            }
+            Ok(Default::default())
+        }
+
+        async fn release(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reader_next() {
+        let mut memory = VecMemory::default();
+        let mut handler = StreamHandler::default();
+
+        let data = b"hello world";
+        let reader = handler
+            .handle_reader_new(::std::io::Cursor::new(data.to_vec()), data.len())
+            .unwrap();
+
+        // read synchronously
+        let buf = memory.alloc(5, 1).unwrap();
+        let opcode = io::OpCode::ReaderNext(io::request::ReaderNext {
+            id: reader.id(),
+            buf,
+        });
+        let outputs: io::response::ReaderNext =
+            call(&mut handler, &mut memory, &opcode).await.unwrap();
+        assert_eq!(outputs.len, 5);
+        assert_eq!(memory.read_raw(buf).unwrap(), b"hello");
+
+        // read asynchronously
+        let buf = memory.alloc(16, 1).unwrap();
+        let opcode = io::OpCode::ReaderNext(io::request::ReaderNext {
+            id: reader.id(),
+            buf,
+        });
+        let outputs: io::response::ReaderNext =
+            submit(&mut handler, &mut memory, &opcode).await.unwrap();
+        assert_eq!(outputs.len, 6);
+        assert_eq!(&memory.read_raw(buf).unwrap()[..6], b" world");
+    }
+
+    #[tokio::test]
+    async fn test_reader_next_out_of_range() {
+        let mut memory = VecMemory::default();
+        let mut handler = StreamHandler::default();
+
+        let data = b"hello world";
+        let reader = handler
+            .handle_reader_new(::std::io::Cursor::new(data.to_vec()), data.len())
+            .unwrap();
+
+        // note: the buffer is never allocated in the guest memory
+        let opcode = io::OpCode::ReaderNext(io::request::ReaderNext {
+            id: reader.id(),
+            buf: ExternData {
+                ptr: 1,
+                len: u32::MAX - 1,
+            },
+        });
+
+        let error = call::<_, _, io::response::ReaderNext>(&mut handler, &mut memory, &opcode)
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidInput);
+
+        let error = submit::<_, _, io::response::ReaderNext>(&mut handler, &mut memory, &opcode)
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn test_reader_seek() {
+        let mut memory = VecMemory::default();
+        let mut handler = StreamHandler::default();
+
+        let data = b"hello world";
+        let reader = handler
+            .handle_reader_new_seekable(::std::io::Cursor::new(data.to_vec()), data.len())
+            .unwrap();
+        let id = reader.id();
+
+        let opcode = io::OpCode::ReaderSeek(io::request::ReaderSeek {
+            id,
+            pos: SeekFrom::End(-5),
+        });
+        let outputs: io::response::ReaderSeek =
+            submit(&mut handler, &mut memory, &opcode).await.unwrap();
+        assert_eq!(outputs.pos, 6);
+
+        let buf = memory.alloc(16, 1).unwrap();
+        let opcode = io::OpCode::ReaderNext(io::request::ReaderNext { id, buf });
+        let outputs: io::response::ReaderNext =
+            call(&mut handler, &mut memory, &opcode).await.unwrap();
+        assert_eq!(
+            &memory.read_raw(buf).unwrap()[..outputs.len as usize],
+            b"world"
+        );
+
+        let opcode = io::OpCode::ReaderMetadata(io::request::ReaderMetadata { id });
+        let outputs: io::response::ReaderMetadata =
+            call(&mut handler, &mut memory, &opcode).await.unwrap();
+        assert_eq!(outputs.len, data.len() as u32);
+        assert_eq!(outputs.content_type, None);
+
+        // test the closed handles are freed
+        let _: io::response::ReaderClose = call(
+            &mut handler,
+            &mut memory,
+            &io::OpCode::ReaderClose(io::request::ReaderClose { id }),
+        )
+        .await
+        .unwrap();
+
+        let error = call::<_, _, io::response::ReaderMetadata>(&mut handler, &mut memory, &opcode)
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_attach_reader() {
+        let mut memory = VecMemory::default();
+
+        // attach a reader before the task is spawned
+        let extensions = InterruptExtensions::default();
+        {
+            let table = StreamTable::with_extensions(&extensions);
+            let mut table = table.lock().unwrap();
+
+            let data = b"hello world";
+            let reader = table
+                .reader_new(::std::io::Cursor::new(data.to_vec()), data.len())
+                .unwrap();
+            table.attach_reader("stdin", &reader).unwrap();
+        }
+
+        let mut handler = StreamHandler::with_extensions(&extensions);
+
+        let opcode = io::OpCode::ReaderOpen(io::request::ReaderOpen {
+            name: "stdin".into(),
+        });
+        let outputs: io::response::ReaderOpen =
+            call(&mut handler, &mut memory, &opcode).await.unwrap();
+        assert_eq!(outputs.len, 11);
+
+        let buf = memory.alloc(16, 1).unwrap();
+        let next = io::OpCode::ReaderNext(io::request::ReaderNext {
+            id: outputs.id,
+            buf,
+        });
+        let outputs: io::response::ReaderNext =
+            call(&mut handler, &mut memory, &next).await.unwrap();
+        assert_eq!(
+            &memory.read_raw(buf).unwrap()[..outputs.len as usize],
+            b"hello world"
+        );
+
+        // test the attached streams are opened only once
+        let error = call::<_, _, io::response::ReaderOpen>(&mut handler, &mut memory, &opcode)
+            .await
+            .unwrap_err()
+            .downcast::<ExternError>()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_pipe() {
+        let mut memory = VecMemory::default();
+
+        // attach the ends of the pipe to the different tasks
+        let (reader, writer) = pipe(4);
+        let producer = InterruptExtensions::default();
+        let writer = StreamTable::with_extensions(&producer)
+            .lock()
+            .unwrap()
+            .writer_new(writer)
+            .unwrap();
+        let consumer = InterruptExtensions::default();
+        let reader = StreamTable::with_extensions(&consumer)
+            .lock()
+            .unwrap()
+            .reader_new(reader, 0)
+            .unwrap();
+
+        let mut producer_handler = StreamHandler::with_extensions(&producer);
+        let mut consumer_handler = StreamHandler::with_extensions(&consumer);
+
+        // test the writes are bounded by the buffer
+        let data = b"hello world";
+        let opcode = io::OpCode::WriterNext(io::request::WriterNext {
+            id: writer.id(),
+            buf: memory.insert(data).unwrap(),
+        });
+        let outputs: io::response::WriterNext = call(&mut producer_handler, &mut memory, &opcode)
+            .await
+            .unwrap();
+        assert_eq!(outputs.len, 4);
+
+        let buf = memory.alloc(16, 1).unwrap();
+        let next = io::OpCode::ReaderNext(io::request::ReaderNext {
+            id: reader.id(),
+            buf,
+        });
+        let outputs: io::response::ReaderNext = call(&mut consumer_handler, &mut memory, &next)
+            .await
+            .unwrap();
+        assert_eq!(
+            &memory.read_raw(buf).unwrap()[..outputs.len as usize],
+            b"hell"
+        );
+
+        // test the reader sees a broken pipe when the producer traps
+        producer
+            .get_or_default::<InterruptTaskOutcome>()
+            .set_trapped();
+        InterruptHandler::<VecMemory>::release(&mut producer_handler)
+            .await
+            .unwrap();
+
+        let error: ExternError =
+            call::<_, _, io::response::ReaderNext>(&mut consumer_handler, &mut memory, &next)
+                .await
+                .unwrap_err()
+                .into();
+        assert_eq!(error.code, ErrorCode::BrokenPipe);
+    }
+
+    async fn run_pipe(
+        kernel: &TestKernel,
+        log: &ReadLog,
+        producer: &[u8],
+    ) -> Result<Vec<u8>, ErrorCode> {
+        let writer = InterruptExtensions::default();
+        let reader = InterruptExtensions::default();
+        attach_pipe(&writer, "stdout", &reader, "stdin", 4).unwrap();
+
+        let consumer = build_syscalls(&[(READ, b"")]).unwrap();
+        let spawn = |program: &[u8], extensions| {
+            let ctx = kernel.sign(TaskCtx::new_sandbox()).unwrap();
+            let program = program.to_vec();
+            async move {
+                kernel
+                    .kernel()
+                    .spawn_with_extensions(ctx, &program, extensions)
+                    .await
+                    .unwrap()
+            }
+        };
+        spawn(producer, writer).await;
+        let id = spawn(&consumer, reader).await;
+
+        // note: only the consumer is waited, so the producer is never polled nor reaped
+        tokio::time::timeout(Duration::from_secs(10), kernel.kernel().wait(id))
+            .await
+            .unwrap()
+            .unwrap();
+        log.lock().unwrap().pop().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_attach_pipe() {
+        let log = ReadLog::default();
+        let kernel = TestKernel::builder()
+            .config(KernelConfig {
+                retention: RetentionPolicy {
+                    ttl: Duration::from_secs(3600),
+                    reap_interval: Duration::from_secs(3600),
+                },
+                ..Default::default()
+            })
+            .module(StreamModule)
+            .unwrap()
+            .module(ReadModule { log: log.clone() })
+            .unwrap()
+            .module(WriteModule)
+            .unwrap()
+            .boot()
+            .await
+            .unwrap();
+
+        // test the consumer sees the end once the producer is finished
+        let open = encode(&io::OpCode::WriterOpen(io::request::WriterOpen {
+            name: "stdout".into(),
+        }))
+        .unwrap();
+        let producer = build_syscalls(&[(io::OpCode::ID, &open), (WRITE, b"hello")]).unwrap();
+        assert_eq!(
+            run_pipe(&kernel, &log, &producer).await,
+            Ok(b"hello".to_vec())
+        );
+
+        // test the consumer sees a broken pipe once the producer traps
+        let producer = build_syscalls(&[(WRITE, b"hello"), (UNKNOWN, b"")]).unwrap();
+        assert_eq!(
+            run_pipe(&kernel, &log, &producer).await,
+            Err(ErrorCode::BrokenPipe)
+        );
+
+        // test the pipe cannot be attached twice to the same name
+        let writer = InterruptExtensions::default();
+        let reader = InterruptExtensions::default();
+        attach_pipe(&writer, "stdout", &reader, "stdin", 4).unwrap();
+        let error = attach_pipe(
+            &InterruptExtensions::default(),
+            "stdout",
+            &reader,
+            "stdin",
+            4,
+        )
+        .unwrap_err()
+        .downcast::<ExternError>()
+        .unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidInput);
     }
 }
@@ -0,0 +1,160 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use ipis::tokio::io::{self, AsyncRead, AsyncWrite, ReadBuf};
+use ipwis_kernel_common::error::ExternError;
+
+/// Creates a pipe with a bounded buffer, whose ends can be attached to different tasks.
+///
+/// The reader sees EOF after the writer is shut down,
+/// and a broken pipe if the writer is dropped without shutdown, e.g. when its task traps.
+pub fn pipe(capacity: usize) -> (PipeReader, PipeWriter) {
+    let state = Arc::new(Mutex::new(PipeState {
+        buf: Default::default(),
+        capacity: capacity.max(1),
+        is_shutdown: false,
+        is_broken: false,
+        reader_waker: None,
+        writer_waker: None,
+    }));
+
+    (
+        PipeReader {
+            state: state.clone(),
+        },
+        PipeWriter { state },
+    )
+}
+
+struct PipeState {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    is_shutdown: bool,
+    is_broken: bool,
+    reader_waker: Option<Waker>,
+    writer_waker: Option<Waker>,
+}
+
+impl PipeState {
+    fn wake_reader(&mut self) {
+        if let Some(waker) = self.reader_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn wake_writer(&mut self) {
+        if let Some(waker) = self.writer_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+fn broken_pipe() -> io::Error {
+    ExternError::broken_pipe("the peer of the pipe has been dropped").into()
+}
+
+pub struct PipeReader {
+    state: Arc<Mutex<PipeState>>,
+}
+
+impl AsyncRead for PipeReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut state = self.state.lock().unwrap();
+
+        if !state.buf.is_empty() {
+            let len = state.buf.len().min(buf.remaining());
+            let (front, back) = state.buf.as_slices();
+            let front_len = front.len().min(len);
+            buf.put_slice(&front[..front_len]);
+            buf.put_slice(&back[..len - front_len]);
+            state.buf.drain(..len);
+
+            // note: the writer may be waiting for the free space
+            state.wake_writer();
+            return Poll::Ready(Ok(()));
+        }
+
+        if state.is_shutdown {
+            Poll::Ready(Ok(()))
+        } else if state.is_broken {
+            Poll::Ready(Err(broken_pipe()))
+        } else {
+            state.reader_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.is_broken = true;
+        state.wake_writer();
+    }
+}
+
+pub struct PipeWriter {
+    state: Arc<Mutex<PipeState>>,
+}
+
+impl AsyncWrite for PipeWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.is_broken {
+            return Poll::Ready(Err(broken_pipe()));
+        }
+        if state.is_shutdown {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::Other,
+                "the pipe is already shut down",
+            )));
+        }
+
+        // backpressure
+        let len = (state.capacity - state.buf.len()).min(buf.len());
+        if len == 0 && !buf.is_empty() {
+            state.writer_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        state.buf.extend(&buf[..len]);
+        state.wake_reader();
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.state.lock().unwrap();
+        state.is_shutdown = true;
+        state.wake_reader();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.is_shutdown {
+            state.is_broken = true;
+        }
+        state.wake_reader();
+    }
+}
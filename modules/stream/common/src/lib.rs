@@ -6,11 +6,12 @@ use core::{
 use bytecheck::CheckBytes;
 use ipis::tokio::{
     self,
-    io::{AsyncRead, AsyncWrite, ReadBuf},
+    io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf},
 };
 use ipwis_kernel_common::{
     data::{ExternData, ExternDataRef},
-    interrupt::InterruptId,
+    error::ExternError,
+    interrupt::{InterruptId, InterruptTicket},
     resource::ResourceId,
 };
 use rkyv::{
@@ -18,103 +19,330 @@ use rkyv::{
     Deserialize, Serialize,
 };
 
-#[repr(C)]
+/// Opens the reader, which is attached to the task by the host.
+pub unsafe fn open_reader(name: impl ToString) -> ::ipis::core::anyhow::Result<ExternReader> {
+    let res: io::response::ReaderOpen = io::OpCode::ReaderOpen(io::request::ReaderOpen {
+        name: name.to_string(),
+    })
+    .syscall()?;
+    Ok(ExternReader::new(res.id, res.len))
+}
+
+/// Opens the writer, which is attached to the task by the host.
+pub unsafe fn open_writer(name: impl ToString) -> ::ipis::core::anyhow::Result<ExternWriter> {
+    let res: io::response::WriterOpen = io::OpCode::WriterOpen(io::request::WriterOpen {
+        name: name.to_string(),
+    })
+    .syscall()?;
+    Ok(ExternWriter::new(res.id))
+}
+
 pub struct ExternReader {
     id: ResourceId,
     len: ExternDataRef,
+    next: Option<InterruptTicket>,
+    // note: the buffer is owned by the reader until the pending request is completed
+    next_buf: Vec<u8>,
+    leftover: Vec<u8>,
+    seek: Option<InterruptTicket>,
+    seek_from: Option<SeekFrom>,
 }
 
 impl ExternReader {
     pub fn new(id: ResourceId, len: ExternDataRef) -> Self {
-        Self { id, len }
+        Self {
+            id,
+            len,
+            next: None,
+            next_buf: Default::default(),
+            leftover: Default::default(),
+            seek: None,
+            seek_from: None,
+        }
+    }
+
+    pub fn id(&self) -> ResourceId {
+        self.id
+    }
+
+    /// Returns the length of the stream, which is known on creation.
+    pub fn len(&self) -> ExternDataRef {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub unsafe fn metadata(&self) -> ::ipis::core::anyhow::Result<io::response::ReaderMetadata> {
+        io::OpCode::ReaderMetadata(io::request::ReaderMetadata { id: self.id }).syscall()
+    }
+
+    fn take_position(&mut self, position: tokio::io::SeekFrom) -> SeekFrom {
+        // note: the leftover has been already read from the host
+        let leftover = ::core::mem::take(&mut self.leftover).len() as i64;
+        match position {
+            tokio::io::SeekFrom::Current(offset) => SeekFrom::Current(offset - leftover),
+            position => position.into(),
+        }
     }
 }
 
 impl AsyncRead for ExternReader {
     fn poll_read(
         self: Pin<&mut Self>,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<tokio::io::Result<()>> {
-        unsafe {
-            // fill in buffer
-            let opcode = self::io::request::ReaderNext {
-                id: self.id,
-                buf: {
-                    let unfilled_buf = buf.unfilled_mut();
-                    let len = unfilled_buf.len();
+        let this = self.get_mut();
+
+        // consume the leftover of the last request first
+        if !this.leftover.is_empty() {
+            let len = this.leftover.len().min(buf.remaining());
+            buf.put_slice(&this.leftover[..len]);
+            this.leftover.drain(..len);
+            return Poll::Ready(Ok(()));
+        }
 
-                    let unfilled_buf = ExternData::from_slice_uninit_mut(unfilled_buf);
+        let id = this.id;
+        let next_buf = &mut this.next_buf;
+        let res: io::response::ReaderNext = match poll_ticket(&mut this.next, cx, || unsafe {
+            // fill in buffer
+            *next_buf = vec![0; buf.remaining()];
+            self::io::request::ReaderNext {
+                id,
+                buf: ExternData::from_slice_mut(next_buf),
+            }
+            .submit()
+        }) {
+            Poll::Ready(res) => res?,
+            Poll::Pending => return Poll::Pending,
+        };
 
-                    // use the whole buffer
-                    buf.assume_init(len);
+        let mut next_buf = ::core::mem::take(&mut this.next_buf);
+        next_buf.truncate(res.len as usize);
 
-                    unfilled_buf
-                },
-            };
-            buf.set_filled(opcode.syscall()?.len as usize);
-        };
+        let len = next_buf.len().min(buf.remaining());
+        buf.put_slice(&next_buf[..len]);
+        next_buf.drain(..len);
+        this.leftover = next_buf;
         Poll::Ready(Ok(()))
     }
 }
 
-#[repr(C)]
+impl AsyncSeek for ExternReader {
+    fn start_seek(self: Pin<&mut Self>, position: tokio::io::SeekFrom) -> tokio::io::Result<()> {
+        let this = self.get_mut();
+        if this.next.is_some() || this.seek.is_some() || this.seek_from.is_some() {
+            return Err(tokio::io::Error::new(
+                tokio::io::ErrorKind::Other,
+                "other operation is in progress",
+            ));
+        }
+
+        this.seek_from = Some(this.take_position(position));
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<tokio::io::Result<u64>> {
+        let this = self.get_mut();
+
+        // note: the current position is returned if no seek has been started
+        if this.seek.is_none() && this.seek_from.is_none() {
+            this.seek_from = Some(this.take_position(tokio::io::SeekFrom::Current(0)));
+        }
+
+        let id = this.id;
+        let seek_from = &mut this.seek_from;
+        poll_ticket(&mut this.seek, cx, || unsafe {
+            self::io::request::ReaderSeek {
+                id,
+                pos: seek_from.take().unwrap(),
+            }
+            .submit()
+        })
+        .map_ok(|res: io::response::ReaderSeek| res.pos)
+    }
+}
+
+// note: the handles are freed only in the guests, as the host does not own them
+#[cfg(target_arch = "wasm32")]
+impl Drop for ExternReader {
+    fn drop(&mut self) {
+        unsafe {
+            // note: the pending request may still write to the buffer
+            if let Some(ticket) = self.next.take() {
+                let _ = ticket.wait_raw();
+            }
+            if let Some(ticket) = self.seek.take() {
+                let _ = ticket.wait_raw();
+            }
+            let _: ::ipis::core::anyhow::Result<io::response::ReaderClose> =
+                io::OpCode::ReaderClose(io::request::ReaderClose { id: self.id }).syscall();
+        }
+    }
+}
+
 pub struct ExternWriter {
     id: ResourceId,
+    next: Option<InterruptTicket>,
+    flush: Option<InterruptTicket>,
+    shutdown: Option<InterruptTicket>,
 }
 
 impl ExternWriter {
     pub fn new(id: ResourceId) -> Self {
-        Self { id }
+        Self {
+            id,
+            next: None,
+            flush: None,
+            shutdown: None,
+        }
+    }
+
+    pub fn id(&self) -> ResourceId {
+        self.id
     }
 }
 
 impl AsyncWrite for ExternWriter {
     fn poll_write(
         self: Pin<&mut Self>,
-        _: &mut Context<'_>,
+        cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, tokio::io::Error>> {
-        let len = unsafe {
-            let opcode = self::io::request::WriterNext {
-                id: self.id,
+        let this = self.get_mut();
+        let id = this.id;
+
+        // note: the host copies the buffer on submission
+        poll_ticket(&mut this.next, cx, || unsafe {
+            self::io::request::WriterNext {
+                id,
                 buf: ExternData::from_slice(buf),
-            };
-            opcode.syscall()?.len
-        };
-        Poll::Ready(Ok(len as usize))
+            }
+            .submit()
+        })
+        .map_ok(|res: io::response::WriterNext| res.len as usize)
     }
 
-    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), tokio::io::Error>> {
-        unsafe {
-            let opcode = self::io::request::WriterFlush { id: self.id };
-            opcode.syscall()?;
-        };
-        Poll::Ready(Ok(()))
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), tokio::io::Error>> {
+        let this = self.get_mut();
+        let id = this.id;
+
+        poll_ticket(&mut this.flush, cx, || unsafe {
+            self::io::request::WriterFlush { id }.submit()
+        })
+        .map_ok(|_: io::response::WriterFlush| ())
     }
 
     fn poll_shutdown(
         self: Pin<&mut Self>,
-        _: &mut Context<'_>,
+        cx: &mut Context<'_>,
     ) -> Poll<Result<(), tokio::io::Error>> {
+        let this = self.get_mut();
+        let id = this.id;
+
+        poll_ticket(&mut this.shutdown, cx, || unsafe {
+            self::io::request::WriterShutdown { id }.submit()
+        })
+        .map_ok(|_: io::response::WriterShutdown| ())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Drop for ExternWriter {
+    fn drop(&mut self) {
         unsafe {
-            let opcode = self::io::request::WriterShutdown { id: self.id };
-            opcode.syscall()?;
-        };
-        Poll::Ready(Ok(()))
+            for ticket in [self.next.take(), self.flush.take(), self.shutdown.take()]
+                .into_iter()
+                .flatten()
+            {
+                let _ = ticket.wait_raw();
+            }
+            // note: the host shuts down the writer on close
+            let _: ::ipis::core::anyhow::Result<io::response::WriterClose> =
+                io::OpCode::WriterClose(io::request::WriterClose { id: self.id }).syscall();
+        }
+    }
+}
+
+fn poll_ticket<O>(
+    pending: &mut Option<InterruptTicket>,
+    _cx: &mut Context<'_>,
+    submit: impl FnOnce() -> Result<InterruptTicket, tokio::io::Error>,
+) -> Poll<Result<O, tokio::io::Error>>
+where
+    O: Archive,
+    <O as Archive>::Archived:
+        for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+{
+    let ticket = match *pending {
+        Some(ticket) => ticket,
+        None => *pending.insert(submit()?),
+    };
+
+    match unsafe { ticket.poll() } {
+        Ok(Poll::Ready(outputs)) => {
+            pending.take();
+            Poll::Ready(Ok(outputs))
+        }
+        // note: the executor polls the task again once any ticket is completed,
+        //       e.g. `ipwis_kernel_common::executor::block_on`
+        Ok(Poll::Pending) => Poll::Pending,
+        Err(error) => {
+            pending.take();
+            Poll::Ready(Err(self::io::into_io_error(error)))
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+impl From<tokio::io::SeekFrom> for SeekFrom {
+    fn from(position: tokio::io::SeekFrom) -> Self {
+        match position {
+            tokio::io::SeekFrom::Start(offset) => Self::Start(offset),
+            tokio::io::SeekFrom::End(offset) => Self::End(offset),
+            tokio::io::SeekFrom::Current(offset) => Self::Current(offset),
+        }
+    }
+}
+
+impl From<SeekFrom> for tokio::io::SeekFrom {
+    fn from(position: SeekFrom) -> Self {
+        match position {
+            SeekFrom::Start(offset) => Self::Start(offset),
+            SeekFrom::End(offset) => Self::End(offset),
+            SeekFrom::Current(offset) => Self::Current(offset),
+        }
     }
 }
 
 pub mod io {
     use super::*;
 
-    #[derive(Archive, Serialize, Deserialize)]
+    #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
     #[archive_attr(derive(CheckBytes))]
     pub enum OpCode {
         ReaderNext(self::request::ReaderNext),
         WriterNext(self::request::WriterNext),
         WriterFlush(self::request::WriterFlush),
         WriterShutdown(self::request::WriterShutdown),
+        ReaderClose(self::request::ReaderClose),
+        WriterClose(self::request::WriterClose),
+        ReaderSeek(self::request::ReaderSeek),
+        ReaderMetadata(self::request::ReaderMetadata),
+        ReaderOpen(self::request::ReaderOpen),
+        WriterOpen(self::request::WriterOpen),
     }
 
     impl ::ipis::core::signed::IsSigned for OpCode {}
@@ -122,22 +350,31 @@ pub mod io {
     impl OpCode {
         pub const ID: InterruptId = InterruptId("ipwis_modules_stream");
 
-        unsafe fn syscall<O>(mut self) -> Result<O, tokio::io::Error>
+        unsafe fn submit(mut self) -> Result<InterruptTicket, tokio::io::Error> {
+            Self::ID.submit(&mut self).map_err(into_io_error)
+        }
+
+        pub(crate) unsafe fn syscall<O>(mut self) -> ::ipis::core::anyhow::Result<O>
         where
             O: Archive,
             <O as Archive>::Archived:
                 for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
         {
-            Self::ID
-                .syscall(&mut self)
-                .map_err(|e| tokio::io::Error::new(tokio::io::ErrorKind::Other, e))
+            Self::ID.syscall(&mut self)
+        }
+    }
+
+    pub(crate) fn into_io_error(error: ::ipis::core::anyhow::Error) -> tokio::io::Error {
+        match error.downcast::<ExternError>() {
+            Ok(error) => error.into(),
+            Err(error) => tokio::io::Error::new(tokio::io::ErrorKind::Other, error),
         }
     }
 
     pub mod request {
         use super::*;
 
-        #[derive(Archive, Serialize, Deserialize)]
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
         #[archive_attr(derive(CheckBytes))]
         pub struct ReaderNext {
             pub id: ResourceId,
@@ -147,14 +384,12 @@ pub mod io {
         impl ::ipis::core::signed::IsSigned for ReaderNext {}
 
         impl ReaderNext {
-            pub(crate) unsafe fn syscall(
-                self,
-            ) -> Result<super::response::ReaderNext, tokio::io::Error> {
-                super::OpCode::ReaderNext(self).syscall()
+            pub(crate) unsafe fn submit(self) -> Result<InterruptTicket, tokio::io::Error> {
+                super::OpCode::ReaderNext(self).submit()
             }
         }
 
-        #[derive(Archive, Serialize, Deserialize)]
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
         #[archive_attr(derive(CheckBytes))]
         pub struct WriterNext {
             pub id: ResourceId,
@@ -164,14 +399,12 @@ pub mod io {
         impl ::ipis::core::signed::IsSigned for WriterNext {}
 
         impl WriterNext {
-            pub(crate) unsafe fn syscall(
-                self,
-            ) -> Result<super::response::WriterNext, tokio::io::Error> {
-                super::OpCode::WriterNext(self).syscall()
+            pub(crate) unsafe fn submit(self) -> Result<InterruptTicket, tokio::io::Error> {
+                super::OpCode::WriterNext(self).submit()
             }
         }
 
-        #[derive(Archive, Serialize, Deserialize)]
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
         #[archive_attr(derive(CheckBytes))]
         pub struct WriterFlush {
             pub id: ResourceId,
@@ -180,14 +413,12 @@ pub mod io {
         impl ::ipis::core::signed::IsSigned for WriterFlush {}
 
         impl WriterFlush {
-            pub(crate) unsafe fn syscall(
-                self,
-            ) -> Result<super::response::WriterFlush, tokio::io::Error> {
-                super::OpCode::WriterFlush(self).syscall()
+            pub(crate) unsafe fn submit(self) -> Result<InterruptTicket, tokio::io::Error> {
+                super::OpCode::WriterFlush(self).submit()
             }
         }
 
-        #[derive(Archive, Serialize, Deserialize)]
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
         #[archive_attr(derive(CheckBytes))]
         pub struct WriterShutdown {
             pub id: ResourceId,
@@ -196,18 +427,71 @@ pub mod io {
         impl ::ipis::core::signed::IsSigned for WriterShutdown {}
 
         impl WriterShutdown {
-            pub(crate) unsafe fn syscall(
-                self,
-            ) -> Result<super::response::WriterShutdown, tokio::io::Error> {
-                super::OpCode::WriterShutdown(self).syscall()
+            pub(crate) unsafe fn submit(self) -> Result<InterruptTicket, tokio::io::Error> {
+                super::OpCode::WriterShutdown(self).submit()
             }
         }
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct ReaderClose {
+            pub id: ResourceId,
+        }
+
+        impl ::ipis::core::signed::IsSigned for ReaderClose {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct WriterClose {
+            pub id: ResourceId,
+        }
+
+        impl ::ipis::core::signed::IsSigned for WriterClose {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct ReaderSeek {
+            pub id: ResourceId,
+            pub pos: SeekFrom,
+        }
+
+        impl ::ipis::core::signed::IsSigned for ReaderSeek {}
+
+        impl ReaderSeek {
+            pub(crate) unsafe fn submit(self) -> Result<InterruptTicket, tokio::io::Error> {
+                super::OpCode::ReaderSeek(self).submit()
+            }
+        }
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct ReaderMetadata {
+            pub id: ResourceId,
+        }
+
+        impl ::ipis::core::signed::IsSigned for ReaderMetadata {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct ReaderOpen {
+            pub name: String,
+        }
+
+        impl ::ipis::core::signed::IsSigned for ReaderOpen {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct WriterOpen {
+            pub name: String,
+        }
+
+        impl ::ipis::core::signed::IsSigned for WriterOpen {}
     }
 
     pub mod response {
         use super::*;
 
-        #[derive(Archive, Serialize, Deserialize)]
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
         #[archive_attr(derive(CheckBytes))]
         pub struct ReaderNext {
             pub len: ExternDataRef,
@@ -215,7 +499,7 @@ pub mod io {
 
         impl ::ipis::core::signed::IsSigned for ReaderNext {}
 
-        #[derive(Archive, Serialize, Deserialize)]
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
         #[archive_attr(derive(CheckBytes))]
         pub struct WriterNext {
             pub len: ExternDataRef,
@@ -223,16 +507,63 @@ pub mod io {
 
         impl ::ipis::core::signed::IsSigned for WriterNext {}
 
-        #[derive(Archive, Serialize, Deserialize)]
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
         #[archive_attr(derive(CheckBytes))]
         pub struct WriterFlush {}
 
         impl ::ipis::core::signed::IsSigned for WriterFlush {}
 
-        #[derive(Archive, Serialize, Deserialize)]
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
         #[archive_attr(derive(CheckBytes))]
         pub struct WriterShutdown {}
 
         impl ::ipis::core::signed::IsSigned for WriterShutdown {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct ReaderClose {}
+
+        impl ::ipis::core::signed::IsSigned for ReaderClose {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct WriterClose {}
+
+        impl ::ipis::core::signed::IsSigned for WriterClose {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct ReaderSeek {
+            /// The new position from the start of the stream.
+            pub pos: u64,
+        }
+
+        impl ::ipis::core::signed::IsSigned for ReaderSeek {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct ReaderMetadata {
+            pub len: ExternDataRef,
+            pub content_type: Option<String>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for ReaderMetadata {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct ReaderOpen {
+            pub id: ResourceId,
+            pub len: ExternDataRef,
+        }
+
+        impl ::ipis::core::signed::IsSigned for ReaderOpen {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct WriterOpen {
+            pub id: ResourceId,
+        }
+
+        impl ::ipis::core::signed::IsSigned for WriterOpen {}
     }
 }
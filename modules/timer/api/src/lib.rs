@@ -0,0 +1,305 @@
+#![allow(clippy::missing_safety_doc)]
+
+pub extern crate ipwis_modules_timer_common as common;
+
+use core::{future::Future, pin::Pin, time::Duration};
+use std::sync::Arc;
+
+use ipis::{
+    async_trait::async_trait,
+    core::{anyhow::Result, signed::IsSigned},
+    pin::PinnedInner,
+    rkyv::AlignedVec,
+    tokio::{
+        sync::watch,
+        time::{self, Instant},
+    },
+};
+use ipwis_kernel_common::{
+    error::ExternError,
+    interrupt::{
+        DynInterruptHandler, InterruptHandler, InterruptId, InterruptModule, InterruptSubmission,
+        InterruptTask,
+    },
+    memory::{Memory, MemoryFamily},
+};
+use ipwis_modules_timer_common::io;
+
+pub trait Clock
+where
+    Self: Send + Sync,
+{
+    /// Returns the monotonic time since the clock has started.
+    fn now(&self) -> Duration;
+
+    fn sleep_until(&self, deadline: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+pub struct SystemClock {
+    epoch: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+
+    fn sleep_until(&self, deadline: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(time::sleep_until(self.epoch + deadline))
+    }
+}
+
+/// A clock, which advances only when it is told to.
+#[derive(Clone)]
+pub struct VirtualClock {
+    now: Arc<watch::Sender<Duration>>,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self {
+            now: Arc::new(watch::channel(Duration::ZERO).0),
+        }
+    }
+}
+
+impl VirtualClock {
+    /// Advances the clock, and then wakes up the finished sleeps.
+    pub fn advance(&self, duration: Duration) {
+        let now = *self.now.borrow() + duration;
+        self.now.send_replace(now);
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        *self.now.borrow()
+    }
+
+    fn sleep_until(&self, deadline: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let mut now = self.now.subscribe();
+        Box::pin(async move {
+            while *now.borrow() < deadline {
+                // note: the sleeps are finished if the clock is dropped
+                if now.changed().await.is_err() {
+                    break;
+                }
+            }
+        })
+    }
+}
+
+pub struct TimerModule {
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for TimerModule {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock::default()))
+    }
+}
+
+impl TimerModule {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self { clock }
+    }
+}
+
+#[async_trait]
+impl<F> InterruptModule<F> for TimerModule
+where
+    F: MemoryFamily,
+{
+    fn id(&self) -> InterruptId {
+        io::OpCode::ID
+    }
+
+    async fn spawn_handler(&self, task: &InterruptTask) -> Result<Box<dyn DynInterruptHandler<F>>> {
+        Ok(Box::new(TimerHandler::with_task(self.clock.clone(), task)))
+    }
+}
+
+pub struct TimerHandler {
+    clock: Arc<dyn Clock>,
+    deadline: Option<Duration>,
+}
+
+impl TimerHandler {
+    /// Creates a handler, which never sleeps beyond the given monotonic deadline.
+    pub fn new(clock: Arc<dyn Clock>, deadline: Option<Duration>) -> Self {
+        Self { clock, deadline }
+    }
+
+    pub fn with_task(clock: Arc<dyn Clock>, task: &InterruptTask) -> Self {
+        let deadline = task
+            .ctx
+            .constraints
+            .resources
+            .remaining()
+            .and_then(|remaining| clock.now().checked_add(remaining));
+
+        Self::new(clock, deadline)
+    }
+
+    fn handle_now(&self) -> Result<io::response::Now> {
+        Ok(io::response::Now {
+            nanos: self.clock.now().as_nanos().try_into()?,
+        })
+    }
+
+    fn handle_remaining(&self) -> Result<io::response::Remaining> {
+        Ok(io::response::Remaining {
+            nanos: match self.deadline {
+                Some(deadline) => Some(
+                    deadline
+                        .saturating_sub(self.clock.now())
+                        .as_nanos()
+                        .try_into()?,
+                ),
+                None => None,
+            },
+        })
+    }
+
+    fn sleep_until(
+        &self,
+        req: io::request::SleepUntil,
+    ) -> impl Future<Output = Result<io::response::SleepUntil>> + Send + 'static {
+        let target = Duration::from_nanos(req.nanos);
+        let deadline = self.deadline;
+
+        // note: the sleep never outlives the task's deadline
+        let wake = match deadline {
+            Some(deadline) => target.min(deadline),
+            None => target,
+        };
+
+        let clock = self.clock.clone();
+        let sleep = clock.sleep_until(wake);
+        async move {
+            sleep.await;
+
+            if wake < target {
+                return Err(ExternError::limit_exceeded(format!(
+                    "the sleep outlives the task's deadline: {target:?} > {wake:?}",
+                ))
+                .into());
+            }
+            Ok(io::response::SleepUntil {
+                nanos: clock.now().as_nanos().try_into()?,
+            })
+        }
+    }
+}
+
+#[async_trait]
+impl<M> InterruptHandler<M> for TimerHandler
+where
+    M: Memory,
+{
+    async unsafe fn handle_raw(&mut self, _memory: &mut M, inputs: &[u8]) -> Result<AlignedVec> {
+        match PinnedInner::deserialize_owned(inputs)? {
+            io::OpCode::Now(_) => self.handle_now()?.to_bytes().map_err(Into::into),
+            io::OpCode::SleepUntil(req) => {
+                self.sleep_until(req).await?.to_bytes().map_err(Into::into)
+            }
+            io::OpCode::Remaining(_) => self.handle_remaining()?.to_bytes().map_err(Into::into),
+        }
+    }
+
+    async unsafe fn submit_raw(
+        &mut self,
+        memory: &mut M,
+        inputs: &[u8],
+    ) -> Result<InterruptSubmission> {
+        match PinnedInner::deserialize_owned(inputs)? {
+            io::OpCode::SleepUntil(req) => {
+                let sleep = self.sleep_until(req);
+
+                Ok(InterruptSubmission::pending(async move {
+                    sleep.await?.to_bytes().map(Into::into).map_err(Into::into)
+                }))
+            }
+            _ => self
+                .handle_raw(memory, inputs)
+                .await
+                .map(InterruptSubmission::Ready),
+        }
+    }
+
+    async fn release(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use std::sync::Arc;
+
+    use ipis::tokio;
+    use ipwis_kernel_common::error::{ErrorCode, ExternError};
+    use ipwis_modules_timer_common::io;
+    use ipwis_testkit::{
+        handler::{call, submit},
+        memory::VecMemory,
+    };
+
+    use super::{TimerHandler, VirtualClock};
+
+    #[tokio::test]
+    async fn test_virtual_clock() {
+        let mut memory = VecMemory::default();
+
+        let clock = VirtualClock::default();
+        let deadline = Duration::from_secs(10);
+        let mut handler = TimerHandler::new(Arc::new(clock.clone()), Some(deadline));
+
+        let sleep = |secs| {
+            io::OpCode::SleepUntil(io::request::SleepUntil::new(Duration::from_secs(secs)).unwrap())
+        };
+        let remaining = io::OpCode::Remaining(io::request::Remaining {});
+
+        // test the sleep is finished only when the clock advances
+        let (outputs, ()) = tokio::join!(
+            submit::<_, _, io::response::SleepUntil>(&mut handler, &mut memory, &sleep(3)),
+            async { clock.advance(Duration::from_secs(3)) },
+        );
+        assert_eq!(
+            outputs.unwrap().nanos,
+            Duration::from_secs(3).as_nanos() as u64
+        );
+
+        let outputs: io::response::Remaining =
+            call(&mut handler, &mut memory, &remaining).await.unwrap();
+        assert_eq!(
+            outputs.nanos,
+            Some(Duration::from_secs(7).as_nanos() as u64)
+        );
+
+        // test the sleep is cut at the deadline
+        let (outputs, ()) = tokio::join!(
+            submit::<_, _, io::response::SleepUntil>(&mut handler, &mut memory, &sleep(60)),
+            async { clock.advance(Duration::from_secs(7)) },
+        );
+        let error = outputs.unwrap_err().downcast::<ExternError>().unwrap();
+        assert_eq!(error.code, ErrorCode::LimitExceeded);
+
+        let outputs: io::response::Now = call(
+            &mut handler,
+            &mut memory,
+            &io::OpCode::Now(io::request::Now {}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outputs.nanos, deadline.as_nanos() as u64);
+    }
+}
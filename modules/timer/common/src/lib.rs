@@ -0,0 +1,130 @@
+use core::time::Duration;
+
+use bytecheck::CheckBytes;
+use ipis::core::anyhow::Result;
+use ipwis_kernel_common::interrupt::{InterruptId, InterruptTicket};
+use rkyv::{
+    de::deserializers::SharedDeserializeMap, validation::validators::DefaultValidator, Archive,
+    Deserialize, Serialize,
+};
+
+/// Returns the monotonic time of the kernel.
+pub unsafe fn now() -> Result<Duration> {
+    let res: io::response::Now = io::OpCode::Now(io::request::Now {}).syscall()?;
+    Ok(Duration::from_nanos(res.nanos))
+}
+
+/// Sleeps until the given monotonic time.
+///
+/// If the task's deadline comes first, it wakes up at the deadline with an error.
+pub unsafe fn sleep_until(deadline: Duration) -> Result<Duration> {
+    let res: io::response::SleepUntil =
+        io::OpCode::SleepUntil(io::request::SleepUntil::new(deadline)?).syscall()?;
+    Ok(Duration::from_nanos(res.nanos))
+}
+
+pub unsafe fn sleep(duration: Duration) -> Result<Duration> {
+    sleep_until(now()? + duration)
+}
+
+/// Submits a sleep, which is completed at the given monotonic time.
+pub unsafe fn submit_sleep_until(deadline: Duration) -> Result<InterruptTicket> {
+    io::OpCode::ID.submit(&mut io::OpCode::SleepUntil(io::request::SleepUntil::new(
+        deadline,
+    )?))
+}
+
+/// Returns the remaining time until the task's deadline, if any.
+pub unsafe fn remaining() -> Result<Option<Duration>> {
+    let res: io::response::Remaining =
+        io::OpCode::Remaining(io::request::Remaining {}).syscall()?;
+    Ok(res.nanos.map(Duration::from_nanos))
+}
+
+pub mod io {
+    use super::*;
+
+    #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+    #[archive_attr(derive(CheckBytes))]
+    pub enum OpCode {
+        Now(self::request::Now),
+        SleepUntil(self::request::SleepUntil),
+        Remaining(self::request::Remaining),
+    }
+
+    impl ::ipis::core::signed::IsSigned for OpCode {}
+
+    impl OpCode {
+        pub const ID: InterruptId = InterruptId("ipwis_modules_timer");
+
+        pub(crate) unsafe fn syscall<O>(mut self) -> Result<O>
+        where
+            O: Archive,
+            <O as Archive>::Archived:
+                for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+        {
+            Self::ID.syscall(&mut self)
+        }
+    }
+
+    pub mod request {
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Now {}
+
+        impl ::ipis::core::signed::IsSigned for Now {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct SleepUntil {
+            pub nanos: u64,
+        }
+
+        impl ::ipis::core::signed::IsSigned for SleepUntil {}
+
+        impl SleepUntil {
+            pub fn new(deadline: Duration) -> Result<Self> {
+                Ok(Self {
+                    nanos: deadline.as_nanos().try_into()?,
+                })
+            }
+        }
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Remaining {}
+
+        impl ::ipis::core::signed::IsSigned for Remaining {}
+    }
+
+    pub mod response {
+        use super::*;
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Now {
+            pub nanos: u64,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Now {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct SleepUntil {
+            /// The monotonic time when the sleep is finished.
+            pub nanos: u64,
+        }
+
+        impl ::ipis::core::signed::IsSigned for SleepUntil {}
+
+        #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+        #[archive_attr(derive(CheckBytes))]
+        pub struct Remaining {
+            pub nanos: Option<u64>,
+        }
+
+        impl ::ipis::core::signed::IsSigned for Remaining {}
+    }
+}
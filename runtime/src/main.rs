@@ -1,7 +1,13 @@
-use ipis::{env::Infer, tokio};
+use ipis::{env::Infer, log::warn, tokio};
 use ipwis_api::server::IpwisServer;
 
 #[tokio::main]
 async fn main() {
-    IpwisServer::infer().await.run().await
+    let server = IpwisServer::infer().await;
+
+    // note: the forwarded syscalls are rejected as unsupported without any receiver
+    if server.syscall_receiver().is_none() {
+        warn!("the forwarded syscalls are not supported: no receiver is set");
+    }
+    server.run().await
 }
@@ -0,0 +1,237 @@
+use ipis::core::anyhow::{bail, Result};
+use ipwis_kernel_common::interrupt::InterruptId;
+
+/// The maximum size of the guest memory, in pages.
+pub const MEMORY_MAX_PAGES: u32 = 256;
+
+/// The start of the guest heap, below which the data segments can be placed.
+pub const HEAP_BASE: u32 = 4_096;
+
+/// Builds a guest module, which exports a memory and a growing bump allocator.
+///
+/// The body should export the `__ipwis_syscall` entry.
+pub fn build_guest(imports: &str, body: &str) -> Result<Vec<u8>> {
+    build_guest_with_heap(imports, body, HEAP_BASE)
+}
+
+/// Builds a guest module, whose heap starts at the given address.
+///
+/// e.g. the large data segments can be placed below the heap.
+pub fn build_guest_with_heap(imports: &str, body: &str, heap_base: u32) -> Result<Vec<u8>> {
+    // note: the data segments should fit in the initial memory
+    let pages = ((heap_base as u64 + 0xffff) >> 16).max(1);
+    if pages > MEMORY_MAX_PAGES as u64 {
+        bail!("too large heap base: {heap_base}");
+    }
+
+    let source = format!(
+        r#"
+        (module
+            {imports}
+
+            (memory (export "memory") {pages} {MEMORY_MAX_PAGES})
+
+            ;; a bump allocator, which never frees
+            (global $heap (mut i32) (i32.const {heap_base}))
+            (func $alloc (export "__alloc") (param $size i32) (param $align i32) (result i32)
+                (local $ptr i32)
+                (local $end i32)
+                (local.set $ptr (i32.and
+                    (i32.add (global.get $heap) (i32.sub (local.get $align) (i32.const 1)))
+                    (i32.sub (i32.const 0) (local.get $align))))
+                (local.set $end (i32.add (local.get $ptr) (local.get $size)))
+
+                ;; grow the memory if needed, which invalidates the host views
+                (if (i32.gt_u (local.get $end) (i32.shl (memory.size) (i32.const 16)))
+                    (then
+                        (if (i32.eq
+                                (memory.grow (i32.sub
+                                    (i32.shr_u (i32.add (local.get $end) (i32.const 65535)) (i32.const 16))
+                                    (memory.size)))
+                                (i32.const -1))
+                            (then unreachable))))
+
+                (global.set $heap (local.get $end))
+                (local.get $ptr))
+            (func (export "__alloc_zeroed") (param $size i32) (param $align i32) (result i32)
+                (local $ptr i32)
+                (local.set $ptr (call $alloc (local.get $size) (local.get $align)))
+                (memory.fill (local.get $ptr) (i32.const 0) (local.get $size))
+                (local.get $ptr))
+            (func (export "__dealloc") (param i32 i32 i32))
+            (func (export "__realloc")
+                (param $ptr i32) (param $size i32) (param $align i32) (param $new_size i32)
+                (result i32)
+                (local $new i32)
+                (local.set $new (call $alloc (local.get $new_size) (local.get $align)))
+                (memory.copy (local.get $new) (local.get $ptr) (local.get $size))
+                (local.get $new))
+
+            {body}
+        )
+        "#
+    );
+
+    ::wat::parse_str(source).map_err(Into::into)
+}
+
+/// Builds a guest module, which makes the syscalls in order and then returns.
+///
+/// The guest traps if any of the syscalls fails.
+pub fn build_syscalls(calls: &[(InterruptId, &[u8])]) -> Result<Vec<u8>> {
+    // the guest memory layout
+    const PTR_DESCRIPTORS: u32 = 256;
+    const PTR_DATA: u32 = 1_024;
+    const PTR_HEAP: u32 = HEAP_BASE;
+
+    let imports = r#"
+        (import "__ipwis_kernel" "__ipwis_syscall"
+            (func $syscall (param i32 i32 i32 i32) (result i32)))
+    "#;
+
+    let mut segments = String::new();
+    let mut body = String::new();
+    let mut ptr_data = PTR_DATA;
+    for (index, (id, inputs)) in calls.iter().enumerate() {
+        let ptr = PTR_DESCRIPTORS + 32 * index as u32;
+        let ptr_handler = ptr_data;
+        let ptr_inputs = ptr_handler + id.0.len() as u32;
+        ptr_data = ptr_inputs + inputs.len() as u32;
+        if ptr + 32 > PTR_DATA || ptr_data > PTR_HEAP {
+            bail!("too many syscalls to be embedded");
+        }
+
+        let descriptors: Vec<u8> = [
+            (ptr_handler, id.0.len()),
+            (ptr_inputs, inputs.len()),
+            (0, 0), // outputs
+            (0, 0), // errors
+        ]
+        .into_iter()
+        .flat_map(|(ptr, len)| {
+            // note: wasm uses little-endian
+            let mut buf = ptr.to_le_bytes().to_vec();
+            buf.extend((len as u32).to_le_bytes());
+            buf
+        })
+        .collect();
+
+        segments.push_str(&format!(
+            r#"
+            (data (i32.const {ptr}) "{descriptors}")
+            (data (i32.const {ptr_handler}) "{handler}")
+            (data (i32.const {ptr_inputs}) "{inputs}")
+            "#,
+            descriptors = escape(&descriptors),
+            handler = escape(id.0.as_bytes()),
+            inputs = escape(inputs),
+        ));
+        body.push_str(&format!(
+            r#"
+            (if (i32.ne
+                    (call $syscall
+                        (i32.const {ptr}) (i32.const {})
+                        (i32.const {}) (i32.const {}))
+                    (i32.const 0))
+                (then unreachable))
+            "#,
+            ptr + 8,
+            ptr + 16,
+            ptr + 24,
+        ));
+    }
+
+    let body = format!(
+        r#"
+        {segments}
+
+        (func (export "__ipwis_syscall") (param i32 i32 i32 i32) (result i32)
+            {body}
+            (i32.const 0))
+        "#
+    );
+
+    build_guest(imports, &body)
+}
+
+/// Builds a guest module, which makes the batched syscalls and then reports
+/// their outputs to the given module.
+///
+/// The guest traps if the batch itself or the report fails.
+pub fn build_syscall_batch(batch: &[u8], report: InterruptId) -> Result<Vec<u8>> {
+    // the guest memory layout
+    const PTR_BATCH: u32 = 256;
+    const PTR_BATCH_OUTPUTS: u32 = 264;
+    const PTR_BATCH_ERRORS: u32 = 272;
+    const PTR_REPORT: u32 = 280;
+    const PTR_DATA: u32 = 1_024;
+    const PTR_HEAP: u32 = HEAP_BASE;
+
+    let imports = r#"
+        (import "__ipwis_kernel" "__ipwis_syscall"
+            (func $syscall (param i32 i32 i32 i32) (result i32)))
+        (import "__ipwis_kernel" "__ipwis_syscall_batch"
+            (func $syscall_batch (param i32 i32 i32) (result i32)))
+    "#;
+
+    let ptr_report = PTR_DATA;
+    let ptr_batch = ptr_report + report.0.len() as u32;
+    if ptr_batch + batch.len() as u32 > PTR_HEAP {
+        bail!("too large batch to be embedded");
+    }
+
+    let descriptors: Vec<u8> = [
+        (ptr_batch, batch.len()),     // batch inputs
+        (0, 0),                       // batch outputs
+        (0, 0),                       // batch errors
+        (ptr_report, report.0.len()), // report handler
+        (0, 0),                       // report outputs
+        (0, 0),                       // report errors
+    ]
+    .into_iter()
+    .flat_map(|(ptr, len)| {
+        // note: wasm uses little-endian
+        let mut buf = ptr.to_le_bytes().to_vec();
+        buf.extend((len as u32).to_le_bytes());
+        buf
+    })
+    .collect();
+
+    let body = format!(
+        r#"
+        (data (i32.const {PTR_BATCH}) "{descriptors}")
+        (data (i32.const {ptr_report}) "{report}")
+        (data (i32.const {ptr_batch}) "{batch}")
+
+        (func (export "__ipwis_syscall") (param i32 i32 i32 i32) (result i32)
+            (if (i32.ne
+                    (call $syscall_batch
+                        (i32.const {PTR_BATCH})
+                        (i32.const {PTR_BATCH_OUTPUTS})
+                        (i32.const {PTR_BATCH_ERRORS}))
+                    (i32.const 0))
+                (then unreachable))
+
+            ;; note: the batch outputs are given to the report as its inputs
+            (if (i32.ne
+                    (call $syscall
+                        (i32.const {PTR_REPORT}) (i32.const {PTR_BATCH_OUTPUTS})
+                        (i32.const {}) (i32.const {}))
+                    (i32.const 0))
+                (then unreachable))
+            (i32.const 0))
+        "#,
+        PTR_REPORT + 8,
+        PTR_REPORT + 16,
+        descriptors = escape(&descriptors),
+        report = escape(report.0.as_bytes()),
+        batch = escape(batch),
+    );
+
+    build_guest(imports, &body)
+}
+
+/// Escapes the bytes to be embedded into a data segment.
+pub fn escape(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("\\{byte:02x}")).collect()
+}
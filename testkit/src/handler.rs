@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use ipiis_api::{client::IpiisClient, common::Ipiis};
+use ipis::{
+    bytecheck::CheckBytes,
+    core::{
+        anyhow::Result,
+        signed::{IsSigned, Serializer},
+    },
+    pin::PinnedInner,
+    rkyv::{
+        de::deserializers::SharedDeserializeMap, validation::validators::DefaultValidator,
+        AlignedVec, Archive, Deserialize, Serialize,
+    },
+};
+use ipwis_kernel_common::{
+    interrupt::{InterruptHandler, InterruptSubmission, InterruptTask},
+    memory::Memory,
+    protection::ProtectionMode,
+    resource::ResourceId,
+    task::{TaskCtx, TaskId},
+};
+
+use crate::memory::VecMemory;
+
+/// Creates a sandboxed task, which is guaranteed by the client itself.
+pub fn task(client: &IpiisClient) -> Result<InterruptTask> {
+    task_with_ctx(client, TaskCtx::new_sandbox())
+}
+
+pub fn task_with_ctx(client: &IpiisClient, ctx: TaskCtx) -> Result<InterruptTask> {
+    let ctx = client.sign(client.account_me().account_ref(), ctx)?;
+    let ctx = client.sign_as_guarantor(ctx)?;
+
+    Ok(InterruptTask {
+        id: TaskId(0),
+        resource_id: ResourceId(0),
+        ctx: Arc::new(ctx),
+        protection_mode: ProtectionMode::Entry,
+        extensions: Default::default(),
+        parent: None,
+    })
+}
+
+pub fn encode<I>(inputs: &I) -> Result<AlignedVec>
+where
+    I: Serialize<Serializer> + IsSigned,
+{
+    inputs.to_bytes().map_err(Into::into)
+}
+
+pub fn decode<O>(outputs: &[u8]) -> Result<O>
+where
+    O: Archive,
+    <O as Archive>::Archived:
+        for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+{
+    // note: the outputs may be misaligned for the archives
+    let mut buf = AlignedVec::with_capacity(outputs.len());
+    buf.extend_from_slice(outputs);
+    PinnedInner::deserialize_owned(buf)
+}
+
+/// Calls the handler directly, as if the guest had made a syscall.
+pub async fn call<H, I, O>(handler: &mut H, memory: &mut VecMemory, inputs: &I) -> Result<O>
+where
+    H: InterruptHandler<VecMemory>,
+    I: Serialize<Serializer> + IsSigned,
+    O: Archive,
+    <O as Archive>::Archived:
+        for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+{
+    let inputs = encode(inputs)?;
+
+    // safety: `VecMemory` checks every access
+    let outputs = unsafe { handler.handle_raw(memory, &inputs) }.await?;
+    decode(&outputs)
+}
+
+/// Submits to the handler directly, and then waits for its completion.
+pub async fn submit<H, I, O>(handler: &mut H, memory: &mut VecMemory, inputs: &I) -> Result<O>
+where
+    H: InterruptHandler<VecMemory>,
+    I: Serialize<Serializer> + IsSigned,
+    O: Archive,
+    <O as Archive>::Archived:
+        for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<O, SharedDeserializeMap>,
+{
+    let inputs = encode(inputs)?;
+
+    // safety: `VecMemory` checks every access
+    let outputs = match unsafe { handler.submit_raw(memory, &inputs) }.await? {
+        InterruptSubmission::Ready(outputs) => outputs,
+        InterruptSubmission::Pending(future) => {
+            let completion = future.await?;
+
+            // copy-out semantics, as the kernel does
+            if let Some((dst, data)) = completion.copy_to {
+                memory.write_raw(dst, &data)?;
+            }
+            completion.outputs
+        }
+    };
+    decode(&outputs)
+}
@@ -0,0 +1,500 @@
+use std::sync::{Arc, Mutex};
+
+use ipiis_api::{client::IpiisClient, common::Ipiis};
+use ipis::{
+    async_trait::async_trait,
+    core::{account::GuarantorSigned, anyhow::Result},
+    env::Infer,
+    rkyv::AlignedVec,
+};
+use ipwis_api::resource::DummyResourceManager;
+use ipwis_kernel::{
+    config::KernelConfig, interrupt::InterruptManager, kernel::Kernel, memory::IpwisMemoryFamily,
+    task::TaskRecord,
+};
+use ipwis_kernel_common::{
+    interrupt::{
+        DynInterruptHandler, InterruptHandler, InterruptId, InterruptModule, InterruptTask,
+    },
+    memory::Memory,
+    task::{TaskCtx, TaskPoll},
+};
+
+/// A syscall, which is made to a fake module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyscallRecord {
+    pub id: InterruptId,
+    pub inputs: Vec<u8>,
+}
+
+type SyscallLog = Arc<Mutex<Vec<SyscallRecord>>>;
+
+type Script = Arc<dyn Fn(&[u8]) -> Result<AlignedVec> + Send + Sync>;
+
+#[derive(Default)]
+pub struct TestKernelBuilder {
+    config: KernelConfig,
+    interrupt_manager: InterruptManager,
+    log: SyscallLog,
+}
+
+impl TestKernelBuilder {
+    pub fn config(mut self, config: KernelConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Registers a fake module, which responds to the syscalls with the given script.
+    pub fn fake<F>(mut self, id: InterruptId, script: F) -> Result<Self>
+    where
+        F: Fn(&[u8]) -> Result<AlignedVec> + Send + Sync + 'static,
+    {
+        self.interrupt_manager.insert(FakeModule {
+            id,
+            script: Arc::new(script),
+            log: self.log.clone(),
+        })?;
+        Ok(self)
+    }
+
+    /// Registers a real module.
+    pub fn module<M>(mut self, module: M) -> Result<Self>
+    where
+        M: InterruptModule<IpwisMemoryFamily> + 'static,
+    {
+        self.interrupt_manager.insert(module)?;
+        Ok(self)
+    }
+
+    pub async fn boot(self) -> Result<TestKernel> {
+        Ok(TestKernel {
+            // create an IPIIS account
+            client: IpiisClient::infer().await,
+            kernel: Kernel::boot_with_interrupts(self.config, self.interrupt_manager).await?,
+            log: self.log,
+        })
+    }
+}
+
+pub struct TestKernel {
+    client: IpiisClient,
+    kernel: Kernel<DummyResourceManager>,
+    log: SyscallLog,
+}
+
+impl TestKernel {
+    pub fn builder() -> TestKernelBuilder {
+        Default::default()
+    }
+
+    pub fn client(&self) -> &IpiisClient {
+        &self.client
+    }
+
+    pub fn kernel(&self) -> &Kernel<DummyResourceManager> {
+        &self.kernel
+    }
+
+    /// Signs the task as both its guarantee and guarantor.
+    pub fn sign(&self, ctx: TaskCtx) -> Result<GuarantorSigned<TaskCtx>> {
+        let ctx = self
+            .client
+            .sign(self.client.account_me().account_ref(), ctx)?;
+        self.client.sign_as_guarantor(ctx)
+    }
+
+    pub async fn run(&self, program: &[u8]) -> Result<TestOutcome> {
+        self.run_with_ctx(TaskCtx::new_sandbox(), program).await
+    }
+
+    /// Runs the program until finished.
+    ///
+    /// Note that the syscalls are recorded per kernel, so the runs should not overlap.
+    pub async fn run_with_ctx(&self, ctx: TaskCtx, program: &[u8]) -> Result<TestOutcome> {
+        self.log.lock().unwrap().clear();
+
+        let id = self.kernel.spawn(self.sign(ctx)?, program).await?;
+        let record = self.kernel.wait(id).await?;
+        self.kernel.acknowledge(id).await?;
+
+        Ok(TestOutcome {
+            record,
+            syscalls: ::core::mem::take(&mut *self.log.lock().unwrap()),
+        })
+    }
+}
+
+pub struct TestOutcome {
+    pub record: Arc<TaskRecord>,
+    pub syscalls: Vec<SyscallRecord>,
+}
+
+impl TestOutcome {
+    pub fn syscalls_to(&self, id: InterruptId) -> impl Iterator<Item = &SyscallRecord> + '_ {
+        self.syscalls.iter().filter(move |record| record.id == id)
+    }
+
+    #[track_caller]
+    pub fn assert_ready(&self) -> &Self {
+        match &self.record.poll {
+            TaskPoll::Ready(_) => self,
+            poll => panic!("the task is not ready: {poll:?}"),
+        }
+    }
+
+    #[track_caller]
+    pub fn assert_syscalls(&self, expected: &[InterruptId]) -> &Self {
+        let actual: Vec<_> = self.syscalls.iter().map(|record| record.id).collect();
+        assert_eq!(actual, expected, "unexpected syscalls");
+        self
+    }
+}
+
+struct FakeModule {
+    id: InterruptId,
+    script: Script,
+    log: SyscallLog,
+}
+
+#[async_trait]
+impl InterruptModule<IpwisMemoryFamily> for FakeModule {
+    fn id(&self) -> InterruptId {
+        self.id
+    }
+
+    async fn spawn_handler(
+        &self,
+        _task: &InterruptTask,
+    ) -> Result<Box<dyn DynInterruptHandler<IpwisMemoryFamily>>> {
+        Ok(Box::new(FakeHandler {
+            id: self.id,
+            script: self.script.clone(),
+            log: self.log.clone(),
+        }))
+    }
+}
+
+struct FakeHandler {
+    id: InterruptId,
+    script: Script,
+    log: SyscallLog,
+}
+
+#[async_trait]
+impl<M> InterruptHandler<M> for FakeHandler
+where
+    M: Memory,
+{
+    async unsafe fn handle_raw(&mut self, _memory: &mut M, inputs: &[u8]) -> Result<AlignedVec> {
+        self.log.lock().unwrap().push(SyscallRecord {
+            id: self.id,
+            inputs: inputs.to_vec(),
+        });
+        (self.script)(inputs)
+    }
+
+    async fn release(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use ipiis_api::common::Ipiis;
+    use ipis::{
+        async_trait::async_trait,
+        core::anyhow::{bail, Result},
+        rkyv::AlignedVec,
+        tokio,
+    };
+    use ipwis_kernel::{
+        config::{KernelConfig, RetentionPolicy},
+        memory::IpwisMemoryFamily,
+    };
+    use ipwis_kernel_common::{
+        batch::{SyscallBatch, SyscallBatchOutput, SyscallBatchOutputs},
+        error::{ErrorCode, ExternError},
+        interrupt::{
+            DynInterruptHandler, InterruptHandler, InterruptId, InterruptModule, InterruptTask,
+            InterruptTaskUsage,
+        },
+        memory::Memory,
+        task::{TaskCtx, TaskPoll},
+        usage::SyscallCount,
+    };
+
+    use super::{SyscallRecord, TestKernel};
+    use crate::{
+        guest::{build_syscall_batch, build_syscalls},
+        handler::{decode, encode},
+    };
+
+    const ECHO: InterruptId = InterruptId("ipwis_test_echo");
+    const READ: InterruptId = InterruptId("ipwis_test_read");
+    const RELEASE: InterruptId = InterruptId("ipwis_test_release");
+    const REPORT: InterruptId = InterruptId("ipwis_test_report");
+
+    fn echo(inputs: &[u8]) -> Result<AlignedVec> {
+        if inputs == b"fail" {
+            bail!(ExternError::not_found("scripted failure"));
+        }
+        let mut outputs = AlignedVec::with_capacity(inputs.len());
+        outputs.extend_from_slice(inputs);
+        Ok(outputs)
+    }
+
+    /// Reads the inputs as if they were given from a stream.
+    struct ReadModule;
+
+    #[async_trait]
+    impl InterruptModule<IpwisMemoryFamily> for ReadModule {
+        fn id(&self) -> InterruptId {
+            READ
+        }
+
+        async fn spawn_handler(
+            &self,
+            task: &InterruptTask,
+        ) -> Result<Box<dyn DynInterruptHandler<IpwisMemoryFamily>>> {
+            Ok(Box::new(ReadHandler {
+                usage: InterruptTaskUsage::with_task(task),
+            }))
+        }
+    }
+
+    struct ReadHandler {
+        usage: Arc<InterruptTaskUsage>,
+    }
+
+    #[async_trait]
+    impl<M> InterruptHandler<M> for ReadHandler
+    where
+        M: Memory,
+    {
+        async unsafe fn handle_raw(
+            &mut self,
+            _memory: &mut M,
+            inputs: &[u8],
+        ) -> Result<AlignedVec> {
+            self.usage.add_stream_bytes_read(inputs.len() as u64);
+            Ok(Default::default())
+        }
+
+        async fn release(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fake_module() {
+        let kernel = TestKernel::builder()
+            .fake(ECHO, echo)
+            .unwrap()
+            .boot()
+            .await
+            .unwrap();
+
+        // test the syscalls are recorded in order
+        let program = build_syscalls(&[(ECHO, b"hello"), (ECHO, b"world")]).unwrap();
+        let outcome = kernel.run(&program).await.unwrap();
+        outcome.assert_ready().assert_syscalls(&[ECHO, ECHO]);
+        assert_eq!(
+            outcome.syscalls,
+            [
+                SyscallRecord {
+                    id: ECHO,
+                    inputs: b"hello".to_vec(),
+                },
+                SyscallRecord {
+                    id: ECHO,
+                    inputs: b"world".to_vec(),
+                },
+            ],
+        );
+
+        // test the failed syscall traps the guest, and the records are not shared between runs
+        let program = build_syscalls(&[(ECHO, b"fail"), (ECHO, b"unreachable")]).unwrap();
+        let outcome = kernel.run(&program).await.unwrap();
+        assert!(matches!(&outcome.record.poll, TaskPoll::Trap(_)));
+        outcome.assert_syscalls(&[ECHO]);
+        assert_eq!(outcome.syscalls_to(ECHO).next().unwrap().inputs, b"fail");
+    }
+
+    #[tokio::test]
+    async fn test_syscall_batch() {
+        let kernel = TestKernel::builder()
+            .fake(ECHO, echo)
+            .unwrap()
+            .fake(REPORT, |_| Ok(Default::default()))
+            .unwrap()
+            .boot()
+            .await
+            .unwrap();
+
+        let mut batch = SyscallBatch::default();
+        for inputs in [&b"hello"[..], b"fail", b"world"] {
+            batch.push_raw(ECHO, inputs.to_vec());
+        }
+        let program = build_syscall_batch(&encode(&batch).unwrap(), REPORT).unwrap();
+        let outcome = kernel.run(&program).await.unwrap();
+
+        // test the calls are executed in order, regardless of the errors
+        outcome
+            .assert_ready()
+            .assert_syscalls(&[ECHO, ECHO, ECHO, REPORT]);
+        let inputs: Vec<_> = outcome
+            .syscalls_to(ECHO)
+            .map(|record| record.inputs.as_slice())
+            .collect();
+        assert_eq!(inputs, [&b"hello"[..], b"fail", b"world"]);
+
+        // test the errors are returned per call
+        let report = outcome.syscalls_to(REPORT).next().unwrap();
+        let outputs: SyscallBatchOutputs = decode(&report.inputs).unwrap();
+        assert_eq!(outputs.results.len(), 3);
+        assert_eq!(
+            outputs.results[0],
+            SyscallBatchOutput::Ok(b"hello".to_vec())
+        );
+        match &outputs.results[1] {
+            SyscallBatchOutput::Err(error) => {
+                assert_eq!(error.code, ErrorCode::NotFound);
+                assert_eq!(error.module.as_deref(), Some(ECHO.0));
+            }
+            SyscallBatchOutput::Ok(_) => panic!("the failed call should return an error"),
+        }
+        assert_eq!(
+            outputs.results[2],
+            SyscallBatchOutput::Ok(b"world".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_usage_record() {
+        let kernel = TestKernel::builder()
+            .module(ReadModule)
+            .unwrap()
+            .boot()
+            .await
+            .unwrap();
+
+        let program = build_syscalls(&[(READ, b"hello"), (READ, b"world!")]).unwrap();
+        kernel.run(&program).await.unwrap().assert_ready();
+
+        // test the finished task is billed to the guarantee
+        let account = kernel.client().account_me().account_ref();
+        let summary = kernel.kernel().usage_summary(&account);
+        assert_eq!(summary.tasks, 1);
+        assert!(summary.fuel > 0);
+        assert_eq!(
+            summary.syscalls,
+            [SyscallCount {
+                id: READ.0.into(),
+                count: 2,
+            }],
+        );
+        assert_eq!(summary.stream_bytes_read, 11);
+        assert_eq!(summary.stream_bytes_written, 0);
+    }
+
+    /// Marks that its handlers are released, i.e. the task is completed by the kernel.
+    struct ReleaseModule {
+        released: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl InterruptModule<IpwisMemoryFamily> for ReleaseModule {
+        fn id(&self) -> InterruptId {
+            RELEASE
+        }
+
+        async fn spawn_handler(
+            &self,
+            _task: &InterruptTask,
+        ) -> Result<Box<dyn DynInterruptHandler<IpwisMemoryFamily>>> {
+            Ok(Box::new(ReleaseHandler {
+                released: self.released.clone(),
+            }))
+        }
+    }
+
+    struct ReleaseHandler {
+        released: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl<M> InterruptHandler<M> for ReleaseHandler
+    where
+        M: Memory,
+    {
+        async unsafe fn handle_raw(
+            &mut self,
+            _memory: &mut M,
+            _inputs: &[u8],
+        ) -> Result<AlignedVec> {
+            Ok(Default::default())
+        }
+
+        async fn release(&mut self) -> Result<()> {
+            self.released.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retention() {
+        let released = Arc::<AtomicBool>::default();
+        let kernel = TestKernel::builder()
+            .config(KernelConfig {
+                retention: RetentionPolicy {
+                    ttl: Duration::from_millis(200),
+                    reap_interval: Duration::from_millis(10),
+                },
+                ..Default::default()
+            })
+            .module(ReleaseModule {
+                released: released.clone(),
+            })
+            .unwrap()
+            .boot()
+            .await
+            .unwrap();
+        let program = build_syscalls(&[(RELEASE, b"")]).unwrap();
+        let spawn = || async {
+            let ctx = kernel.sign(TaskCtx::new_sandbox()).unwrap();
+            kernel.kernel().spawn(ctx, &program).await.unwrap()
+        };
+
+        // test the handlers are released once the task is finished, even if it is never polled
+        spawn().await;
+        for _ in 0..100 {
+            if released.load(Ordering::SeqCst) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(released.load(Ordering::SeqCst));
+
+        // test the acknowledged result is dropped at once
+        let id = spawn().await;
+        kernel.kernel().wait(id).await.unwrap();
+        kernel.kernel().acknowledge(id).await.unwrap();
+        assert!(kernel.kernel().poll(id).await.is_err());
+        assert!(kernel.kernel().acknowledge(id).await.is_err());
+
+        // test the result is dropped after its TTL
+        let id = spawn().await;
+        kernel.kernel().wait(id).await.unwrap();
+        assert!(kernel.kernel().poll(id).await.unwrap().is_some());
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(kernel.kernel().poll(id).await.is_err());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod guest;
+pub mod handler;
+pub mod kernel;
+pub mod memory;
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+
+use ipis::{
+    async_trait::async_trait,
+    core::anyhow::{bail, Result},
+    rkyv::AlignedVec,
+};
+use ipwis_kernel_common::{
+    data::{ExternData, ExternDataRef, EXTERN_DATA_ALIGN},
+    error::ExternError,
+    memory::Memory,
+};
+
+/// A `Memory` backed by a host buffer, which acts like a guest memory.
+///
+/// Like a real guest memory, the buffer may be reallocated when it grows,
+/// so the views are invalidated on every allocation.
+#[derive(Debug)]
+pub struct VecMemory {
+    data: AlignedVec,
+    heap: ExternDataRef,
+    live: BTreeMap<ExternDataRef, (ExternDataRef, ExternDataRef)>,
+}
+
+impl Default for VecMemory {
+    fn default() -> Self {
+        Self {
+            data: Default::default(),
+            // note: keep the nullptr unallocated
+            heap: EXTERN_DATA_ALIGN,
+            live: Default::default(),
+        }
+    }
+}
+
+impl VecMemory {
+    /// Allocates a zeroed buffer, like the guest's `__alloc_zeroed`.
+    pub fn alloc(&mut self, len: ExternDataRef, align: ExternDataRef) -> Result<ExternData> {
+        if !align.is_power_of_two() {
+            bail!(ExternError::invalid_input(format!(
+                "invalid alignment: {align}"
+            )));
+        }
+
+        // note: zero-sized allocations are not allowed, so use a dangling pointer instead
+        if len == 0 {
+            return Ok(ExternData { ptr: align, len });
+        }
+
+        let ptr = self
+            .heap
+            .checked_add(align - 1)
+            .map(|ptr| ptr & !(align - 1))
+            .ok_or_else(|| ExternError::limit_exceeded("out of memory"))?;
+        let end = ptr
+            .checked_add(len)
+            .ok_or_else(|| ExternError::limit_exceeded("out of memory"))?;
+
+        if end as usize > self.data.len() {
+            self.data.resize(end as usize, 0);
+        }
+        self.heap = end;
+        self.live.insert(ptr, (len, align));
+
+        Ok(ExternData { ptr, len })
+    }
+
+    /// Copies the data into a new buffer, as if the guest had made it.
+    pub fn insert(&mut self, data: &[u8]) -> Result<ExternData> {
+        let dst = self.alloc(data.len().try_into()?, EXTERN_DATA_ALIGN)?;
+        self.write_raw(dst, data).map(|()| dst)
+    }
+
+    /// Copies the value into a new buffer, and returns its pointer.
+    pub fn insert_value<T>(&mut self, value: T) -> Result<ExternDataRef>
+    where
+        T: Copy,
+    {
+        let len = ::core::mem::size_of::<T>().try_into()?;
+        let align = ::core::mem::align_of::<T>().try_into()?;
+
+        let dst = self.alloc(len, align)?;
+        self.write_value(dst.ptr, value).map(|()| dst.ptr)
+    }
+
+    /// Returns the live buffers, in the order of their pointers.
+    pub fn live(&self) -> impl Iterator<Item = ExternData> + '_ {
+        self.live
+            .iter()
+            .map(|(&ptr, &(len, _))| ExternData { ptr, len })
+    }
+
+    pub fn live_bytes(&self) -> u64 {
+        self.live.values().map(|&(len, _)| len as u64).sum()
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+#[async_trait]
+impl Memory for VecMemory {
+    fn host_check(&self, data: ExternData) -> Result<()> {
+        if data.is_null() {
+            bail!(ExternError::invalid_input("data is null"));
+        }
+
+        match data.checked_end() {
+            Some(end) if end as usize <= self.data.len() => Ok(()),
+            _ => bail!(ExternError::invalid_input(format!(
+                "data overflow: {:#x}+{:#x}",
+                data.ptr, data.len,
+            ))),
+        }
+    }
+
+    unsafe fn host_ptr_unchecked<T>(&self, ptr: ExternDataRef) -> *const T {
+        self.data.as_ptr().add(ptr as usize) as *const T
+    }
+
+    unsafe fn host_ptr_mut_unchecked<T>(&mut self, ptr: ExternDataRef) -> *mut T {
+        self.data.as_mut_ptr().add(ptr as usize) as *mut T
+    }
+
+    async fn dump_aligned(&mut self, data: &[u8], align: ExternDataRef) -> Result<ExternData> {
+        let dst = self.alloc(data.len().try_into()?, align)?;
+        if dst.len > 0 {
+            self.write_raw(dst, data)?;
+        }
+        Ok(dst)
+    }
+
+    async unsafe fn free_aligned(&mut self, data: ExternData, align: ExternDataRef) -> Result<()> {
+        // note: zero-sized data is never allocated
+        if data.len == 0 {
+            return Ok(());
+        }
+
+        match self.live.get(&data.ptr) {
+            Some(&allocation) if allocation == (data.len, align) => {
+                self.live.remove(&data.ptr);
+                Ok(())
+            }
+            Some(&(len, align_live)) => bail!(ExternError::invalid_input(format!(
+                "mismatched layout: {:#x}: ({len}, {align_live}) != ({}, {align})",
+                data.ptr, data.len,
+            ))),
+            None => bail!(ExternError::not_found(format!(
+                "double free or invalid pointer: {:#x}",
+                data.ptr,
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ipis::{object::IntoObjectData, tokio};
+    use ipwis_kernel_common::{data::EXTERN_DATA_ALIGN, memory::Memory, task::TaskCtx};
+
+    use super::VecMemory;
+
+    #[tokio::test]
+    async fn test_vec_memory_roundtrip() {
+        let mut memory = VecMemory::default();
+
+        let ctx = TaskCtx::new_sandbox();
+        let data = memory.dump_object(&ctx).await.unwrap();
+        assert_eq!(data.ptr % EXTERN_DATA_ALIGN, 0);
+
+        // test the memory may grow without breaking the former data
+        let object = "hello world".__into_object_data();
+        let doubled = memory.dump_doubled_object(&object).await.unwrap();
+
+        assert_eq!(memory.load_object_raw::<TaskCtx>(data).unwrap(), ctx);
+        assert_eq!(memory.load_object(doubled.ptr).unwrap(), object);
+
+        // test the buffers are released
+        unsafe { memory.free(data) }.await.unwrap();
+        assert!(unsafe { memory.free(data) }.await.is_err());
+        assert_eq!(memory.live().count(), 2);
+    }
+}